@@ -1,16 +1,25 @@
 use crate::configuration_service::logger;
 use crate::constants;
+use crate::monitor::prometheus_exporter::{remove_certificate_expiry, set_certificate_expiry};
+use crate::proxy::acme_client::run_acme_task;
+use crate::proxy::certificate_store::{CertificateResolver, CertificateStore};
 use crate::proxy::tcp_proxy::TcpProxy;
+use crate::proxy::udp_proxy::UdpProxy;
 use crate::proxy::HttpProxy;
 use crate::vojo::api_service_manager::ApiServiceManager;
 use crate::vojo::app_config::ServiceConfig;
-use crate::vojo::app_config::{ApiService, AppConfig, ServiceType};
+use crate::vojo::app_config::{AppConfig, AppConfigDocument, ServiceType, TcpTlsMode};
+use crate::vojo::authentication::ApiKeyStore;
+use crate::vojo::geoip::{GeoIpConfig, GeoIpDatabase, ASN_DATABASE, COUNTRY_DATABASE};
+use crate::vojo::ip_set::{IpSet, IpSetMatcher};
 use dashmap::DashMap;
 use futures::FutureExt;
 use lazy_static::lazy_static;
 use log::Level;
+use rustls::sign::CertifiedKey;
 use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc;
 use tokio::sync::RwLock;
@@ -18,6 +27,16 @@ use tokio::time::sleep;
 lazy_static! {
     pub static ref GLOBAL_APP_CONFIG: RwLock<AppConfig> = RwLock::new(Default::default());
     pub static ref GLOBAL_CONFIG_MAPPING: DashMap<String, ApiServiceManager> = Default::default();
+    pub static ref GLOBAL_API_KEY_STORES: DashMap<String, ApiKeyStore> = Default::default();
+    pub static ref GLOBAL_IP_SETS: DashMap<String, Arc<IpSetMatcher>> = Default::default();
+    pub static ref GLOBAL_GEOIP_DATABASES: DashMap<String, Arc<GeoIpDatabase>> = Default::default();
+    pub static ref GLOBAL_CERTIFICATE_RESOLVERS: DashMap<String, Arc<CertificateResolver>> =
+        Default::default();
+    // The key authorizations of the Http01 challenges by the token.
+    pub static ref GLOBAL_ACME_HTTP_CHALLENGES: DashMap<String, String> = Default::default();
+    // The challenge certificates of the TlsAlpn01 challenges by the domain.
+    pub static ref GLOBAL_ACME_ALPN_CHALLENGES: DashMap<String, Arc<CertifiedKey>> =
+        Default::default();
 }
 
 pub async fn init() {
@@ -52,6 +71,9 @@ async fn update_mapping_from_global_appconfig() -> Result<(), anyhow::Error> {
         .try_read()
         .map_err(|err| anyhow!(err.to_string()))?;
     let api_services = rw_global_app_config.api_service_config.clone();
+    update_api_key_stores(rw_global_app_config.api_key_stores.clone());
+    update_ip_sets(rw_global_app_config.ip_sets.clone());
+    update_geoip_databases(rw_global_app_config.geoip.clone());
 
     let new_item_hash = api_services
         .iter()
@@ -122,8 +144,135 @@ async fn update_mapping_from_global_appconfig() -> Result<(), anyhow::Error> {
         }
     }
 
+    update_certificate_resolvers();
     Ok(())
 }
+fn update_certificate_expiry(
+    mapping_key: &str,
+    certificate_store: &CertificateStore,
+    previous_certificate_store: Option<&CertificateStore>,
+) {
+    if let Some(previous_certificate_store) = previous_certificate_store {
+        for server_name in previous_certificate_store.expiry_times.keys() {
+            remove_certificate_expiry(mapping_key, server_name);
+        }
+    }
+    for (server_name, expiry_time) in certificate_store.expiry_times.iter() {
+        set_certificate_expiry(mapping_key, server_name, *expiry_time);
+    }
+}
+// The certificates of the running https listeners are reloaded when the config or the files are
+// changed,the previous certificates are kept when the new ones could not be loaded.
+fn update_certificate_resolvers() {
+    for item in GLOBAL_CERTIFICATE_RESOLVERS.iter() {
+        let mapping_key = item.key();
+        let service_config = match GLOBAL_CONFIG_MAPPING.get(mapping_key) {
+            Some(api_service_manager) => api_service_manager.service_config.clone(),
+            None => continue,
+        };
+        match item.value().is_changed(&service_config) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(err) => {
+                error!("{}", err);
+                continue;
+            }
+        }
+        match item.value().reload(&service_config) {
+            Ok(previous_certificate_store) => {
+                info!("Reload the certificates of {} successfully!", mapping_key);
+                if let Ok(certificate_store) = item.value().get_certificate_store() {
+                    update_certificate_expiry(
+                        mapping_key,
+                        &certificate_store,
+                        Some(&previous_certificate_store),
+                    );
+                }
+            }
+            Err(err) => error!(
+                "Cause error when reloading the certificates of {},the previous certificates are kept,{}",
+                mapping_key, err
+            ),
+        }
+    }
+}
+fn update_api_key_stores(api_key_stores: Vec<ApiKeyStore>) {
+    GLOBAL_API_KEY_STORES.retain(|key, _| api_key_stores.iter().any(|item| &item.name == key));
+    for api_key_store in api_key_stores {
+        GLOBAL_API_KEY_STORES.insert(api_key_store.name.clone(), api_key_store);
+    }
+}
+// The ip set and the geoip database are only rebuilt when the config or the file is changed.
+fn update_ip_sets(ip_sets: Vec<IpSet>) {
+    GLOBAL_IP_SETS.retain(|key, _| ip_sets.iter().any(|item| &item.name == key));
+    for ip_set in ip_sets {
+        if let Some(ip_set_matcher) = GLOBAL_IP_SETS.get(&ip_set.name) {
+            if !ip_set_matcher.is_changed(&ip_set) {
+                continue;
+            }
+        }
+        let name = ip_set.name.clone();
+        match IpSetMatcher::new(ip_set) {
+            Ok(ip_set_matcher) => {
+                GLOBAL_IP_SETS.insert(name, Arc::new(ip_set_matcher));
+            }
+            Err(err) => error!("Cause error when loading the ip set {},{}", name, err),
+        }
+    }
+}
+fn update_geoip_databases(geoip_config: Option<GeoIpConfig>) {
+    let geoip_config = geoip_config.unwrap_or_default();
+    for (key, path_option) in [
+        (COUNTRY_DATABASE, geoip_config.country_database),
+        (ASN_DATABASE, geoip_config.asn_database),
+    ] {
+        let path = match path_option {
+            Some(path) => path,
+            None => {
+                GLOBAL_GEOIP_DATABASES.remove(key);
+                continue;
+            }
+        };
+        if let Some(database) = GLOBAL_GEOIP_DATABASES.get(key) {
+            if !database.is_changed(path.as_str()) {
+                continue;
+            }
+        }
+        match GeoIpDatabase::open(path.as_str()) {
+            Ok(database) => {
+                GLOBAL_GEOIP_DATABASES.insert(String::from(key), Arc::new(database));
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+}
+// The certificate resolver is registered to be reloaded by the sync task.
+fn register_certificate_resolver(
+    mapping_key: &String,
+) -> Result<Arc<CertificateResolver>, anyhow::Error> {
+    let service_config = GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .ok_or(anyhow!(
+            "Can not find the config mapping on the key {}!",
+            mapping_key
+        ))?
+        .service_config
+        .clone();
+    let certificate_store = CertificateStore::new(&service_config)?;
+    update_certificate_expiry(mapping_key, &certificate_store, None);
+    let certificate_resolver = Arc::new(CertificateResolver::new(certificate_store));
+    GLOBAL_CERTIFICATE_RESOLVERS.insert(mapping_key.clone(), certificate_resolver.clone());
+    Ok(certificate_resolver)
+}
+fn unregister_certificate_resolver(mapping_key: &String) {
+    if let Some((_, certificate_resolver)) = GLOBAL_CERTIFICATE_RESOLVERS.remove(mapping_key) {
+        if let Ok(certificate_store) = certificate_resolver.get_certificate_store() {
+            for server_name in certificate_store.expiry_times.keys() {
+                remove_certificate_expiry(mapping_key, server_name);
+            }
+        }
+    }
+}
 pub async fn start_proxy(
     port: i32,
     channel: mpsc::Receiver<()>,
@@ -138,27 +287,43 @@ pub async fn start_proxy(
         };
         http_proxy.start_http_server().await
     } else if server_type == ServiceType::HTTPS {
-        let key_clone = mapping_key.clone();
-        let service_config = GLOBAL_CONFIG_MAPPING
-            .get(&key_clone)
-            .unwrap()
-            .service_config
-            .clone();
-        let pem_str = service_config.cert_str.unwrap();
-        let key_str = service_config.key_str.unwrap();
+        let certificate_resolver = register_certificate_resolver(&mapping_key)?;
         let mut http_proxy = HttpProxy {
             port: port,
             channel: channel,
             mapping_key: mapping_key.clone(),
         };
-        http_proxy.start_https_server(pem_str, key_str).await
+        let acme_task = tokio::spawn(run_acme_task(mapping_key.clone()));
+        let result = http_proxy.start_https_server(certificate_resolver).await;
+        acme_task.abort();
+        unregister_certificate_resolver(&mapping_key);
+        result
+    } else if server_type == ServiceType::UDP {
+        let mut udp_proxy = UdpProxy {
+            port,
+            mapping_key: mapping_key.clone(),
+            channel,
+        };
+        udp_proxy.start_proxy().await
     } else {
+        let is_tls_terminated = GLOBAL_CONFIG_MAPPING
+            .get(&mapping_key)
+            .and_then(|api_service_manager| api_service_manager.service_config.tcp_tls.clone())
+            == Some(TcpTlsMode::Terminate);
+        let certificate_resolver = match is_tls_terminated {
+            true => Some(register_certificate_resolver(&mapping_key)?),
+            false => None,
+        };
         let mut tcp_proxy = TcpProxy {
             port: port,
-            mapping_key: mapping_key,
+            mapping_key: mapping_key.clone(),
             channel: channel,
         };
-        tcp_proxy.start_proxy().await
+        let result = tcp_proxy.start_proxy(certificate_resolver).await;
+        if is_tls_terminated {
+            unregister_certificate_resolver(&mapping_key);
+        }
+        result
     }
 }
 async fn init_static_config() {
@@ -199,13 +364,13 @@ async fn init_app_service_config() -> Result<(), anyhow::Error> {
         Ok(file) => file,
         Err(err) => return Err(anyhow!(err.to_string())),
     };
-    let scrape_config: Vec<ApiService> = match serde_yaml::from_reader(file) {
-        Ok(apiservices) => apiservices,
+    let scrape_config: AppConfigDocument = match serde_yaml::from_reader(file) {
+        Ok(app_config_document) => app_config_document,
         Err(err) => return Err(anyhow!(err.to_string())),
     };
     let mut rw_app_config_write = GLOBAL_APP_CONFIG.write().await;
-
-    (*rw_app_config_write).api_service_config = scrape_config;
+    scrape_config.validate(&rw_app_config_write)?;
+    scrape_config.apply_to(&mut rw_app_config_write);
     Ok(())
 }
 
@@ -379,8 +544,20 @@ mod tests {
             sender: sender,
             service_config: ServiceConfig {
                 key_str: Some(private_key),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
                 server_type: crate::vojo::app_config::ServiceType::HTTPS,
                 cert_str: Some(certificate),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 routes: vec![Route {
                     host_name: None,
                     route_id: crate::vojo::app_config::new_uuid(),
@@ -389,6 +566,13 @@ mod tests {
                     allow_deny_list: None,
                     authentication: None,
                     ratelimit: None,
+                    response_templates: None,
+                    in_flight_limit: None,
+                    waf: None,
+                    max_body_size: None,
+                    server_name: None,
+                    upstream_tls: None,
+                    tcp_matcher: None,
                 }],
             },
         };
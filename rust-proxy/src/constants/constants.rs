@@ -3,8 +3,34 @@ pub const DENY_RESPONSE: &'static str = r#"{
     "response_code": -1,
     "response_object": "The request has been blocked by the silverwind!"
 }"#;
+pub const TOO_MANY_REQUESTS_RESPONSE: &'static str = r#"{
+    "response_code": -1,
+    "response_object": "The request has been rate limited by the silverwind!"
+}"#;
+pub const SERVICE_UNAVAILABLE_RESPONSE: &'static str = r#"{
+    "response_code": -1,
+    "response_object": "The route is overloaded,please try again later!"
+}"#;
+pub const PAYLOAD_TOO_LARGE_RESPONSE: &str = r#"{
+    "response_code": -1,
+    "response_object": "The request body is too large!"
+}"#;
+pub const HEADER_TOO_LARGE_RESPONSE: &str = r#"{
+    "response_code": -1,
+    "response_object": "The request header fields are too large!"
+}"#;
+pub const REQUEST_TIMEOUT_RESPONSE: &str = r#"{
+    "response_code": -1,
+    "response_object": "The request body is sent too slowly!"
+}"#;
 pub const NOT_FOUND: &'static str = r#"{
     "response_code": -1,
     "response_object": "The route could not be found in the Proxy!"
 }"#;
-pub const DEFAULT_FIXEDWINDOW_MAP_SIZE: i32 = 3;
+pub const DEFAULT_KEYED_MAP_SIZE: usize = 10000;
+pub const DEFAULT_PROXY_PROTOCOL_TIMEOUT_MILLIS: u64 = 5000;
+pub const DEFAULT_CLIENT_HELLO_TIMEOUT_MILLIS: u64 = 5000;
+pub const DEFAULT_TLS_HANDSHAKE_TIMEOUT_MILLIS: u64 = 5000;
+pub const DEFAULT_TCP_CONNECT_TIMEOUT_MILLIS: u64 = 5000;
+pub const DEFAULT_UDP_IDLE_TIMEOUT_MILLIS: u64 = 60000;
+pub const DEFAULT_UDP_MAX_SESSIONS: usize = 10000;
@@ -1,7 +1,13 @@
 use crate::configuration_service::app_config_service::GLOBAL_APP_CONFIG;
+use crate::proxy::certificate_store::{
+    load_certificate_pem, load_certified_key, load_default_pem, load_ocsp_response,
+};
 use crate::proxy::http_proxy::GeneralError;
-use crate::vojo::app_config::ApiService;
+use crate::proxy::upstream_connector::new_client_config;
+use crate::vojo::app_config::AppConfigDocument;
+use crate::vojo::app_config::ServiceConfig;
 use crate::vojo::app_config::ServiceType;
+use crate::vojo::app_config::TcpTlsMode;
 use crate::vojo::vojo::BaseResponse;
 use prometheus::{Encoder, TextEncoder};
 use std::convert::Infallible;
@@ -43,25 +49,50 @@ async fn get_prometheus_metrics() -> Result<impl warp::Reply, Infallible> {
         .unwrap())
 }
 
-async fn post_app_config(api_services: Vec<ApiService>) -> Result<impl warp::Reply, Infallible> {
-    let validata_result = api_services
+async fn post_app_config(
+    app_config_document: AppConfigDocument,
+) -> Result<impl warp::Reply, Infallible> {
+    let validata_result = app_config_document
+        .api_service_config
         .iter()
-        .filter(|s| s.service_config.server_type == ServiceType::HTTPS)
         .map(|s| {
-            return validate_tls_config(
-                s.service_config.cert_str.clone(),
-                s.service_config.key_str.clone(),
-            );
+            let is_tls_terminated = s.service_config.server_type == ServiceType::HTTPS
+                || s.service_config.tcp_tls == Some(TcpTlsMode::Terminate);
+            if is_tls_terminated {
+                validate_service_certificates(&s.service_config).map_err(|err| {
+                    anyhow!(
+                        "The certificates of the port {} are invalid,{}",
+                        s.listen_port,
+                        err
+                    )
+                })?;
+            }
+            validate_proxy_protocol(&s.service_config).map_err(|err| {
+                anyhow!(
+                    "The proxy protocol of the port {} is invalid,{}",
+                    s.listen_port,
+                    err
+                )
+            })?;
+            validate_upstream_tls(&s.service_config).map_err(|err| {
+                anyhow!(
+                    "The upstream tls of the port {} is invalid,{}",
+                    s.listen_port,
+                    err
+                )
+            })
         })
         .collect::<Result<Vec<()>, anyhow::Error>>();
+    let mut rw_global_lock = GLOBAL_APP_CONFIG.write().await;
+    let validata_result =
+        validata_result.and_then(|_| app_config_document.validate(&rw_global_lock));
     if let Err(err) = validata_result {
         return Ok(Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .status(StatusCode::BAD_REQUEST)
             .body(err.to_string())
             .unwrap());
     }
-    let mut rw_global_lock = GLOBAL_APP_CONFIG.write().await;
-    (*rw_global_lock).api_service_config = api_services.clone();
+    app_config_document.apply_to(&mut rw_global_lock);
     let data = BaseResponse {
         response_code: 0,
         response_object: 0,
@@ -73,28 +104,84 @@ async fn post_app_config(api_services: Vec<ApiService>) -> Result<impl warp::Rep
         .body(json_str)
         .unwrap())
 }
+// The http upstream with the PROXY header is connected by a plain tcp connection per request,
+// so the https endpoint could not be used with the send.
+fn validate_proxy_protocol(service_config: &ServiceConfig) -> Result<(), anyhow::Error> {
+    let proxy_protocol = match &service_config.proxy_protocol {
+        Some(proxy_protocol) => proxy_protocol,
+        None => return Ok(()),
+    };
+    proxy_protocol.validate()?;
+    let is_http = service_config.server_type == ServiceType::HTTP
+        || service_config.server_type == ServiceType::HTTPS;
+    if proxy_protocol.send.is_none() || !is_http {
+        return Ok(());
+    }
+    for route in service_config.routes.iter() {
+        for base_route in route.route_cluster.get_base_routes() {
+            if base_route.endpoint.starts_with("https://") {
+                return Err(anyhow!(
+                    "The send could not be used with the https endpoint {}!",
+                    base_route.endpoint
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+fn validate_upstream_tls(service_config: &ServiceConfig) -> Result<(), anyhow::Error> {
+    for route in service_config.routes.iter() {
+        if let Some(upstream_tls) = &route.upstream_tls {
+            new_client_config(upstream_tls)?;
+        }
+    }
+    Ok(())
+}
+fn validate_service_certificates(service_config: &ServiceConfig) -> Result<(), anyhow::Error> {
+    if let Some(acme_config) = &service_config.acme {
+        acme_config.validate()?;
+    }
+    if let Some(tls_policy) = &service_config.tls_policy {
+        tls_policy.validate()?;
+    }
+    load_ocsp_response(&service_config.ocsp_file)?;
+    let default_pem = load_default_pem(service_config)?;
+    let is_default_required =
+        service_config.certificates.is_empty() && service_config.acme.is_none();
+    if is_default_required || default_pem.is_some() {
+        let (cert_pem, key_pem) = default_pem.unzip();
+        validate_tls_config(cert_pem, key_pem, service_config.key_password.as_deref())?;
+    }
+    for certificate in service_config.certificates.iter() {
+        let (cert_pem, key_pem) = load_certificate_pem(certificate)?;
+        let key_password = certificate.key_password.as_deref();
+        load_ocsp_response(&certificate.ocsp_file)?;
+        validate_tls_config(Some(cert_pem), Some(key_pem), key_password).map_err(|err| {
+            anyhow!(
+                "The certificate of {:?} is invalid,{}",
+                certificate.server_names,
+                err
+            )
+        })?;
+    }
+    Ok(())
+}
+// The certificate is loaded in the same way as the listener,so that the invalid certificate is
+// rejected before it is applied.
 fn validate_tls_config(
     cert_pem_option: Option<String>,
     key_pem_option: Option<String>,
+    key_password: Option<&str>,
 ) -> Result<(), anyhow::Error> {
-    if cert_pem_option.is_none() || key_pem_option.is_none() {
-        return Err(anyhow!("Cert or key is none"));
-    }
-    let cert_pem = cert_pem_option.unwrap();
-    let mut cer_reader = std::io::BufReader::new(cert_pem.as_bytes());
-    let result_certs = rustls_pemfile::certs(&mut cer_reader);
-    if result_certs.is_err() || result_certs.unwrap().len() == 0 {
-        return Err(anyhow!("Can not parse the certs pem."));
+    match (cert_pem_option, key_pem_option) {
+        (Some(cert_pem), Some(key_pem)) => {
+            load_certified_key(&cert_pem, &key_pem, key_password).map(|_| ())
+        }
+        _ => Err(anyhow!("Cert or key is none")),
     }
-    let key_pem = key_pem_option.unwrap();
-    let key_pem_result = pkcs8::PrivateKeyDocument::from_pem(key_pem.as_str());
-    if key_pem_result.is_err() {
-        return Err(anyhow!("Can not parse the key pem."));
-    }
-    Ok(())
 }
 
-fn json_body() -> impl Filter<Extract = (Vec<ApiService>,), Error = warp::Rejection> + Clone {
+fn json_body() -> impl Filter<Extract = (AppConfigDocument,), Error = warp::Rejection> + Clone {
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
 }
 pub async fn handle_not_found(reject: Rejection) -> Result<impl Reply, Rejection> {
@@ -256,7 +343,7 @@ mod tests {
             .join("test_cert.pem");
         let certificate = std::fs::read_to_string(certificate_path).unwrap();
 
-        let validation_res = validate_tls_config(Some(certificate), Some(private_key));
+        let validation_res = validate_tls_config(Some(certificate), Some(private_key), None);
         assert_eq!(validation_res.is_ok(), true);
     }
     #[test]
@@ -268,7 +355,7 @@ mod tests {
         let certificate = std::fs::read_to_string(certificate_path).unwrap();
 
         let private_key = String::from("private key");
-        let validation_res = validate_tls_config(Some(certificate), Some(private_key));
+        let validation_res = validate_tls_config(Some(certificate), Some(private_key), None);
         assert_eq!(validation_res.is_err(), true);
     }
     #[test]
@@ -280,7 +367,7 @@ mod tests {
         let private_key = std::fs::read_to_string(private_key_path).unwrap();
         let certificate = String::from("test");
 
-        let validation_res = validate_tls_config(Some(certificate), Some(private_key));
+        let validation_res = validate_tls_config(Some(certificate), Some(private_key), None);
         assert_eq!(validation_res.is_err(), true);
     }
     #[test]
@@ -363,4 +450,103 @@ mod tests {
             assert_eq!(res.status(), StatusCode::OK);
         })
     }
+    #[test]
+    fn test_post_invalid_proxy_protocol() {
+        let new_body = |proxy_protocol: &str, endpoint: &str| {
+            format!(
+                r#"[
+            {{
+                "listen_port": 4486,
+                "service_config": {{
+                    "server_type": "HTTP",
+                    "proxy_protocol": {},
+                    "routes": [
+                        {{
+                            "matcher": {{
+                                "prefix": "/get",
+                                "prefix_rewrite": "ssss"
+                            }},
+                            "route_cluster": {{
+                                "type": "RandomRoute",
+                                "routes": [
+                                    {{
+                                        "base_route": {{
+                                            "endpoint": "{}",
+                                            "try_file": null
+                                        }}
+                                    }}
+                                ]
+                            }}
+                        }}
+                    ]
+                }}
+            }}
+        ]"#,
+                proxy_protocol, endpoint
+            )
+        };
+        TOKIO_RUNTIME.block_on(async {
+            let post_app_config = warp::post()
+                .and(warp::path("appConfig"))
+                .and(warp::path::end())
+                .and(json_body())
+                .and_then(post_app_config)
+                .recover(handle_not_found);
+            let invalid_bodies = vec![
+                new_body(r#"{"send": "V1"}"#, "https://localhost:8000"),
+                new_body(r#"{"accept": true}"#, "http://localhost:8000"),
+            ];
+            for body in invalid_bodies {
+                let res = warp::test::request()
+                    .method("POST")
+                    .path("/appConfig")
+                    .body(body)
+                    .reply(&post_app_config)
+                    .await;
+                assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            }
+        })
+    }
+    #[test]
+    fn test_post_unknown_api_key_store() {
+        let body = r#"[
+            {
+                "listen_port": 4486,
+                "service_config": {
+                    "server_type": "HTTP",
+                    "routes": [
+                        {
+                            "matcher": {
+                                "prefix": "/get",
+                                "prefix_rewrite": "ssss"
+                            },
+                            "authentication": {
+                                "type": "ApiKeyStoreAuth",
+                                "store_name": "not_found_store"
+                            },
+                            "route_cluster": {
+                                "type": "RandomRoute",
+                                "routes": []
+                            }
+                        }
+                    ]
+                }
+            }
+        ]"#;
+        TOKIO_RUNTIME.block_on(async {
+            let post_app_config = warp::post()
+                .and(warp::path("appConfig"))
+                .and(warp::path::end())
+                .and(json_body())
+                .and_then(post_app_config)
+                .recover(handle_not_found);
+            let res = warp::test::request()
+                .method("POST")
+                .path("/appConfig")
+                .body(body)
+                .reply(&post_app_config)
+                .await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        })
+    }
 }
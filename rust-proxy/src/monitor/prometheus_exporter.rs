@@ -1,6 +1,9 @@
 use lazy_static::lazy_static;
-use prometheus::{labels, opts, register_counter_vec, register_gauge, register_histogram_vec};
-use prometheus::{CounterVec, Gauge, Histogram, HistogramVec};
+use prometheus::{
+    labels, opts, register_counter_vec, register_gauge, register_gauge_vec, register_histogram_vec,
+};
+use prometheus::{CounterVec, Gauge, GaugeVec, Histogram, HistogramVec};
+use std::time::Duration;
 
 lazy_static! {
     static ref HTTP_COUNTER: CounterVec = register_counter_vec!(
@@ -11,6 +14,98 @@ lazy_static! {
         &["port", "request_path", "status_code"]
     )
     .unwrap();
+    static ref HTTP_CONSUMER_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_http_consumer_requests_total",
+            "Number of HTTP requests made by the authenticated consumer.",
+        ),
+        &["port", "consumer", "status_code"]
+    )
+    .unwrap();
+    static ref WAF_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_waf_requests_total",
+            "Number of HTTP requests matched by the waf rules.",
+        ),
+        &["port", "rule", "action"]
+    )
+    .unwrap();
+    static ref CERTIFICATE_EXPIRY_GAUGE: GaugeVec = register_gauge_vec!(
+        opts!(
+            "silverwind_certificate_expiry_timestamp_seconds",
+            "The not after timestamp of the certificates of the https listeners.",
+        ),
+        &["port", "server_name"]
+    )
+    .unwrap();
+    static ref TCP_BYTES_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_tcp_bytes_total",
+            "Number of bytes transferred by the tcp connections.",
+        ),
+        &["port", "endpoint", "direction"]
+    )
+    .unwrap();
+    static ref TCP_CONNECT_ERROR_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_tcp_connect_errors_total",
+            "Number of the failed connections to the tcp endpoints.",
+        ),
+        &["port", "endpoint"]
+    )
+    .unwrap();
+    static ref TCP_REJECTED_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_tcp_rejected_connections_total",
+            "Number of the tcp connections rejected by the listeners.",
+        ),
+        &["port", "reason"]
+    )
+    .unwrap();
+    static ref TCP_CONNECTION_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "silverwind_tcp_connection_duration_seconds",
+        "The durations of the tcp connections in seconds.",
+        &["port", "endpoint"]
+    )
+    .unwrap();
+    static ref UDP_PACKETS_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_udp_packets_total",
+            "Number of packets transferred by the udp sessions.",
+        ),
+        &["port", "endpoint", "direction"]
+    )
+    .unwrap();
+    static ref UDP_BYTES_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_udp_bytes_total",
+            "Number of bytes transferred by the udp sessions.",
+        ),
+        &["port", "endpoint", "direction"]
+    )
+    .unwrap();
+    static ref UDP_SESSION_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "silverwind_udp_session_duration_seconds",
+        "The durations of the udp sessions in seconds.",
+        &["port", "endpoint"]
+    )
+    .unwrap();
+    static ref UDP_DROPPED_COUNTER: CounterVec = register_counter_vec!(
+        opts!(
+            "silverwind_udp_dropped_packets_total",
+            "Number of the udp packets dropped by the listeners.",
+        ),
+        &["port", "reason"]
+    )
+    .unwrap();
+    static ref UDP_SESSION_GAUGE: GaugeVec = register_gauge_vec!(
+        opts!(
+            "silverwind_udp_sessions",
+            "Number of the active udp sessions.",
+        ),
+        &["port"]
+    )
+    .unwrap();
     static ref HTTP_BODY_GAUGE: Gauge = register_gauge!(opts!(
         "silverwind_http_response_size_bytes",
         "The HTTP response sizes in bytes.",
@@ -33,6 +128,84 @@ pub fn inc(key: String, path: String, code: u16) {
         .inc();
     HTTP_COUNTER.with_label_values(&["all", "all", "all"]).inc();
 }
+pub fn inc_consumer(key: String, consumer: String, code: u16) {
+    HTTP_CONSUMER_COUNTER
+        .with_label_values(&[key.as_str(), consumer.as_str(), code.to_string().as_str()])
+        .inc();
+}
+pub fn inc_waf(key: String, rule: String, action: &str) {
+    WAF_COUNTER
+        .with_label_values(&[key.as_str(), rule.as_str(), action])
+        .inc();
+}
+pub fn set_certificate_expiry(key: &str, server_name: &str, timestamp: i64) {
+    CERTIFICATE_EXPIRY_GAUGE
+        .with_label_values(&[key, server_name])
+        .set(timestamp as f64);
+}
+pub fn remove_certificate_expiry(key: &str, server_name: &str) {
+    let _ = CERTIFICATE_EXPIRY_GAUGE.remove_label_values(&[key, server_name]);
+}
+// The bytes in are read from the client and the bytes out are written to the client.
+pub fn observe_tcp_connection(
+    key: &str,
+    endpoint: &str,
+    bytes_in: u64,
+    bytes_out: u64,
+    duration: Duration,
+) {
+    TCP_BYTES_COUNTER
+        .with_label_values(&[key, endpoint, "in"])
+        .inc_by(bytes_in as f64);
+    TCP_BYTES_COUNTER
+        .with_label_values(&[key, endpoint, "out"])
+        .inc_by(bytes_out as f64);
+    TCP_CONNECTION_HISTOGRAM
+        .with_label_values(&[key, endpoint])
+        .observe(duration.as_secs_f64());
+}
+pub fn inc_tcp_rejected_connection(key: &str, reason: &str) {
+    TCP_REJECTED_COUNTER.with_label_values(&[key, reason]).inc();
+}
+pub fn inc_tcp_connect_error(key: &str, endpoint: &str) {
+    TCP_CONNECT_ERROR_COUNTER
+        .with_label_values(&[key, endpoint])
+        .inc();
+}
+// The packets in are received from the client and the packets out are sent to the client.
+pub fn observe_udp_session(
+    key: &str,
+    endpoint: &str,
+    packets: (u64, u64),
+    bytes: (u64, u64),
+    duration: Duration,
+) {
+    UDP_PACKETS_COUNTER
+        .with_label_values(&[key, endpoint, "in"])
+        .inc_by(packets.0 as f64);
+    UDP_PACKETS_COUNTER
+        .with_label_values(&[key, endpoint, "out"])
+        .inc_by(packets.1 as f64);
+    UDP_BYTES_COUNTER
+        .with_label_values(&[key, endpoint, "in"])
+        .inc_by(bytes.0 as f64);
+    UDP_BYTES_COUNTER
+        .with_label_values(&[key, endpoint, "out"])
+        .inc_by(bytes.1 as f64);
+    UDP_SESSION_HISTOGRAM
+        .with_label_values(&[key, endpoint])
+        .observe(duration.as_secs_f64());
+}
+pub fn inc_udp_dropped_packets(key: &str, reason: &str, packets: u64) {
+    UDP_DROPPED_COUNTER
+        .with_label_values(&[key, reason])
+        .inc_by(packets as f64);
+}
+pub fn set_udp_sessions(key: &str, sessions: usize) {
+    UDP_SESSION_GAUGE
+        .with_label_values(&[key])
+        .set(sessions as f64);
+}
 pub fn get_timer_list(key: String, path: String) -> Vec<Histogram> {
     vec![
         HTTP_REQ_HISTOGRAM.with_label_values(&[key.as_str(), path.as_str()]),
@@ -0,0 +1,729 @@
+use crate::configuration_service::app_config_service::{
+    GLOBAL_ACME_ALPN_CHALLENGES, GLOBAL_ACME_HTTP_CHALLENGES, GLOBAL_CONFIG_MAPPING,
+};
+use crate::proxy::certificate_store::to_certified_key;
+use crate::vojo::acme::{AcmeChallengeType, AcmeConfig, ACME_HTTP_CHALLENGE_PREFIX};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use http::StatusCode;
+use hyper::body::Bytes;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request, Response};
+use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+use x509_parser::extensions::GeneralName;
+
+const ACME_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const ACME_POLL_ATTEMPTS: usize = 30;
+const ACME_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const ACME_MAX_RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AcmeDirectory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+#[derive(Debug, Clone, Deserialize)]
+struct AcmeOrder {
+    status: String,
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    #[serde(default)]
+    certificate: Option<String>,
+}
+#[derive(Debug, Clone, Deserialize)]
+struct AcmeIdentifier {
+    value: String,
+}
+#[derive(Debug, Clone, Deserialize)]
+struct AcmeChallenge {
+    #[serde(rename = "type")]
+    challenge_type: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+}
+#[derive(Debug, Clone, Deserialize)]
+struct AcmeAuthorization {
+    identifier: AcmeIdentifier,
+    status: String,
+    #[serde(default)]
+    challenges: Vec<AcmeChallenge>,
+}
+struct AcmeResponse {
+    status: StatusCode,
+    location: Option<String>,
+    body: Bytes,
+}
+impl AcmeResponse {
+    fn json<T: DeserializeOwned>(&self) -> Result<T, anyhow::Error> {
+        serde_json::from_slice(&self.body)
+            .map_err(|err| anyhow!("Can not parse the response of the acme server,{}", err))
+    }
+}
+// The account key is the ECDSA P-256 key and the requests are signed by ES256.
+pub struct AccountKey {
+    key_pair: EcdsaKeyPair,
+}
+impl AccountKey {
+    pub fn from_pem(pem: &str) -> Result<Self, anyhow::Error> {
+        let key_pair = rcgen::KeyPair::from_pem(pem)
+            .map_err(|err| anyhow!("Can not parse the account key,{}", err))?;
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &key_pair.serialize_der())
+                .map_err(|err| anyhow!("The account key is not supported,{}", err))?;
+        Ok(AccountKey { key_pair })
+    }
+    pub fn generate() -> Result<(Self, String), anyhow::Error> {
+        let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)
+            .map_err(|err| anyhow!("Can not generate the account key,{}", err))?;
+        let pem = key_pair.serialize_pem();
+        Ok((AccountKey::from_pem(&pem)?, pem))
+    }
+    fn get_jwk(&self) -> Value {
+        // The public key is the uncompressed point,0x04 followed by x and y.
+        let public_key = self.key_pair.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": URL_SAFE_NO_PAD.encode(&public_key[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&public_key[33..65]),
+        })
+    }
+    // The RFC 7638 thumbprint,the members are in the lexicographic order without whitespace.
+    pub fn get_thumbprint(&self) -> String {
+        let jwk = self.get_jwk();
+        let canonical = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":{},"y":{}}}"#,
+            jwk["x"], jwk["y"]
+        );
+        URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+    }
+    pub fn sign(&self, protected: &Value, payload: &str) -> Result<Value, anyhow::Error> {
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+        let signature = self
+            .key_pair
+            .sign(
+                &SystemRandom::new(),
+                format!("{}.{}", protected, payload).as_bytes(),
+            )
+            .map_err(|err| anyhow!("Can not sign the acme request,{}", err))?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }))
+    }
+}
+fn load_account_key(acme_config: &AcmeConfig) -> Result<AccountKey, anyhow::Error> {
+    let account_key_file = acme_config.get_account_key_file();
+    if let Ok(pem) = fs::read_to_string(&account_key_file) {
+        return AccountKey::from_pem(&pem);
+    }
+    let (account_key, pem) = AccountKey::generate()?;
+    write_file(&account_key_file, &pem)?;
+    Ok(account_key)
+}
+// Write to the temporary file first,so that the certificate store never reads a partial file.
+fn write_file(path: &str, content: &str) -> Result<(), anyhow::Error> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| anyhow!("Can not create the directory {:?},{}", parent, err))?;
+    }
+    let temp_path = format!("{}.tmp", path);
+    write_private_file(&temp_path, content)
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|err| anyhow!("Can not write the file {},{}", path, err))
+}
+// The account key and the private keys are only readable by the owner.
+#[cfg(unix)]
+fn write_private_file(path: &str, content: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    // The mode only applies to the new file,so the stale temporary file is removed first.
+    let _ = fs::remove_file(path);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(content.as_bytes())
+}
+#[cfg(not(unix))]
+fn write_private_file(path: &str, content: &str) -> std::io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(content.as_bytes())
+}
+fn new_https_client(
+    ca_file: Option<&String>,
+) -> Result<Client<HttpsConnector<HttpConnector>>, anyhow::Error> {
+    let tls = match ca_file {
+        Some(ca_file) => {
+            let ca_pem = fs::read(ca_file)
+                .map_err(|err| anyhow!("Can not read the file {},{}", ca_file, err))?;
+            let certs = rustls_pemfile::certs(&mut BufReader::new(ca_pem.as_slice()))
+                .map_err(|err| anyhow!("Can not parse the ca pem,{}", err))?;
+            let mut root_store = rustls::RootCertStore::empty();
+            let (added, _) = root_store.add_parsable_certificates(&certs);
+            if added == 0 {
+                return Err(anyhow!("Can not find the certificate in {}!", ca_file));
+            }
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(root_store)
+                .with_no_client_auth()
+        }
+        None => rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_webpki_roots()
+            .with_no_client_auth(),
+    };
+    let https = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls)
+        .https_only()
+        .enable_http1()
+        .build();
+    Ok(Client::builder().build::<_, Body>(https))
+}
+// The challenge certificate of RFC 8737,the acmeIdentifier extension contains the SHA-256 digest
+// of the key authorization.
+pub fn new_alpn_challenge_certificate(
+    domain: &str,
+    key_authorization: &str,
+) -> Result<rustls::sign::CertifiedKey, anyhow::Error> {
+    let mut params = rcgen::CertificateParams::new(vec![String::from(domain)]);
+    let digest = Sha256::digest(key_authorization.as_bytes());
+    params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(&digest)];
+    let certificate = rcgen::Certificate::from_params(params)
+        .map_err(|err| anyhow!("Can not generate the challenge certificate,{}", err))?;
+    to_certified_key(&certificate)
+}
+// Return the der of the certificate request and the pem of the new key.
+fn new_certificate_request(domains: &[String]) -> Result<(Vec<u8>, String), anyhow::Error> {
+    let mut params = rcgen::CertificateParams::new(domains.to_vec());
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let certificate = rcgen::Certificate::from_params(params)
+        .map_err(|err| anyhow!("Can not generate the certificate key,{}", err))?;
+    let csr_der = certificate
+        .serialize_request_der()
+        .map_err(|err| anyhow!("Can not serialize the certificate request,{}", err))?;
+    Ok((csr_der, certificate.serialize_private_key_pem()))
+}
+fn publish_challenge(
+    challenge_type: &AcmeChallengeType,
+    domain: &str,
+    token: &str,
+    key_authorization: &str,
+) -> Result<(), anyhow::Error> {
+    match challenge_type {
+        AcmeChallengeType::Http01 => {
+            GLOBAL_ACME_HTTP_CHALLENGES
+                .insert(String::from(token), String::from(key_authorization));
+        }
+        AcmeChallengeType::TlsAlpn01 => {
+            let certified_key = new_alpn_challenge_certificate(domain, key_authorization)?;
+            GLOBAL_ACME_ALPN_CHALLENGES.insert(String::from(domain), Arc::new(certified_key));
+        }
+    }
+    Ok(())
+}
+fn remove_challenge(challenge_type: &AcmeChallengeType, domain: &str, token: &str) {
+    match challenge_type {
+        AcmeChallengeType::Http01 => {
+            GLOBAL_ACME_HTTP_CHALLENGES.remove(token);
+        }
+        AcmeChallengeType::TlsAlpn01 => {
+            GLOBAL_ACME_ALPN_CHALLENGES.remove(domain);
+        }
+    }
+}
+// The http listeners answer the Http01 challenges before matching the routes.
+pub fn get_acme_challenge_response(path: &str) -> Option<Response<Body>> {
+    let token = path.strip_prefix(ACME_HTTP_CHALLENGE_PREFIX)?;
+    let key_authorization = GLOBAL_ACME_HTTP_CHALLENGES.get(token)?;
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/octet-stream")
+        .body(Body::from(key_authorization.value().clone()))
+        .ok()
+}
+pub struct AcmeClient {
+    client: Client<HttpsConnector<HttpConnector>>,
+    directory: AcmeDirectory,
+    account_key: AccountKey,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+impl AcmeClient {
+    pub async fn new(acme_config: &AcmeConfig) -> Result<Self, anyhow::Error> {
+        let client = new_https_client(acme_config.ca_file.as_ref())?;
+        let account_key = load_account_key(acme_config)?;
+        let mut acme_client = AcmeClient {
+            client,
+            directory: AcmeDirectory {
+                new_nonce: String::new(),
+                new_account: String::new(),
+                new_order: String::new(),
+            },
+            account_key,
+            account_url: None,
+            nonce: None,
+        };
+        let request = Request::get(acme_config.directory_url.as_str()).body(Body::empty())?;
+        let response = acme_client.send(request).await?;
+        if !response.status.is_success() {
+            return Err(anyhow!(
+                "Can not get the directory from {},the status is {}!",
+                acme_config.directory_url,
+                response.status
+            ));
+        }
+        acme_client.directory = response.json()?;
+        Ok(acme_client)
+    }
+    async fn send(&mut self, request: Request<Body>) -> Result<AcmeResponse, anyhow::Error> {
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|err| anyhow!("Can not connect to the acme server,{}", err))?;
+        if let Some(nonce) = response.headers().get("Replay-Nonce") {
+            self.nonce = Some(String::from(nonce.to_str()?));
+        }
+        let status = response.status();
+        let location = response
+            .headers()
+            .get("Location")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(AcmeResponse {
+            status,
+            location,
+            body,
+        })
+    }
+    async fn get_nonce(&mut self) -> Result<String, anyhow::Error> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let request = Request::head(self.directory.new_nonce.as_str()).body(Body::empty())?;
+        self.send(request).await?;
+        self.nonce
+            .take()
+            .ok_or(anyhow!("Can not get the nonce from the acme server!"))
+    }
+    // The request without the payload is the POST-as-GET request.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&Value>,
+    ) -> Result<AcmeResponse, anyhow::Error> {
+        let payload = payload.map_or(String::new(), |payload| payload.to_string());
+        let mut retried = false;
+        loop {
+            let mut protected = json!({
+                "alg": "ES256",
+                "nonce": self.get_nonce().await?,
+                "url": url,
+            });
+            match &self.account_url {
+                Some(account_url) => protected["kid"] = json!(account_url),
+                None => protected["jwk"] = self.account_key.get_jwk(),
+            }
+            let body = self.account_key.sign(&protected, &payload)?;
+            let request = Request::post(url)
+                .header("Content-Type", "application/jose+json")
+                .body(Body::from(body.to_string()))?;
+            let response = self.send(request).await?;
+            if response.status.is_success() {
+                return Ok(response);
+            }
+            // The nonce could be expired,retry once with the nonce in the error response.
+            let problem: Value = response.json().unwrap_or_default();
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(anyhow!(
+                "The acme server responds {} on {},{}",
+                response.status,
+                url,
+                String::from_utf8_lossy(&response.body)
+            ));
+        }
+    }
+    async fn register_account(&mut self, contacts: &[String]) -> Result<(), anyhow::Error> {
+        let contacts: Vec<String> = contacts
+            .iter()
+            .map(|contact| match contact.starts_with("mailto:") {
+                true => contact.clone(),
+                false => format!("mailto:{}", contact),
+            })
+            .collect();
+        let new_account = self.directory.new_account.clone();
+        let response = self
+            .post(
+                &new_account,
+                Some(&json!({"termsOfServiceAgreed": true, "contact": contacts})),
+            )
+            .await?;
+        self.account_url = Some(
+            response
+                .location
+                .ok_or(anyhow!("Can not find the account url in the response!"))?,
+        );
+        Ok(())
+    }
+    async fn authorize(
+        &mut self,
+        authorization_url: &str,
+        challenge_type: &AcmeChallengeType,
+    ) -> Result<(), anyhow::Error> {
+        let authorization: AcmeAuthorization = self.post(authorization_url, None).await?.json()?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+        let type_name = match challenge_type {
+            AcmeChallengeType::Http01 => "http-01",
+            AcmeChallengeType::TlsAlpn01 => "tls-alpn-01",
+        };
+        let domain = authorization.identifier.value.to_ascii_lowercase();
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|challenge| challenge.challenge_type == type_name)
+            .ok_or(anyhow!(
+                "Can not find the {} challenge of {}!",
+                type_name,
+                domain
+            ))?;
+        let key_authorization =
+            format!("{}.{}", challenge.token, self.account_key.get_thumbprint());
+        publish_challenge(
+            challenge_type,
+            &domain,
+            &challenge.token,
+            &key_authorization,
+        )?;
+        let result = self
+            .validate_challenge(&challenge.url, authorization_url)
+            .await;
+        remove_challenge(challenge_type, &domain, &challenge.token);
+        result.map_err(|err| anyhow!("Can not validate the domain {},{}", domain, err))
+    }
+    async fn validate_challenge(
+        &mut self,
+        challenge_url: &str,
+        authorization_url: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.post(challenge_url, Some(&json!({}))).await?;
+        for _ in 0..ACME_POLL_ATTEMPTS {
+            sleep(ACME_POLL_INTERVAL).await;
+            let authorization: AcmeAuthorization =
+                self.post(authorization_url, None).await?.json()?;
+            match authorization.status.as_str() {
+                "valid" => return Ok(()),
+                "pending" | "processing" => continue,
+                status => return Err(anyhow!("The authorization is {}!", status)),
+            }
+        }
+        Err(anyhow!("The authorization is not finished in time!"))
+    }
+    async fn poll_order(&mut self, order_url: &str) -> Result<AcmeOrder, anyhow::Error> {
+        for _ in 0..ACME_POLL_ATTEMPTS {
+            let order: AcmeOrder = self.post(order_url, None).await?.json()?;
+            match order.status.as_str() {
+                "valid" => return Ok(order),
+                "pending" | "ready" | "processing" => sleep(ACME_POLL_INTERVAL).await,
+                status => return Err(anyhow!("The order is {}!", status)),
+            }
+        }
+        Err(anyhow!("The order is not finished in time!"))
+    }
+    // Return the pem of the certificate chain and the pem of the key.
+    pub async fn issue_certificate(
+        &mut self,
+        acme_config: &AcmeConfig,
+    ) -> Result<(String, String), anyhow::Error> {
+        self.register_account(&acme_config.contacts).await?;
+        let identifiers: Vec<Value> = acme_config
+            .domains
+            .iter()
+            .map(|domain| json!({"type": "dns", "value": domain}))
+            .collect();
+        let new_order = self.directory.new_order.clone();
+        let response = self
+            .post(&new_order, Some(&json!({ "identifiers": identifiers })))
+            .await?;
+        let order_url = response
+            .location
+            .clone()
+            .ok_or(anyhow!("Can not find the order url in the response!"))?;
+        let order: AcmeOrder = response.json()?;
+        for authorization_url in order.authorizations.iter() {
+            self.authorize(authorization_url, &acme_config.challenge)
+                .await?;
+        }
+        let (csr_der, key_pem) = new_certificate_request(&acme_config.domains)?;
+        self.post(
+            &order.finalize,
+            Some(&json!({ "csr": URL_SAFE_NO_PAD.encode(csr_der) })),
+        )
+        .await?;
+        let order = self.poll_order(&order_url).await?;
+        let certificate_url = order
+            .certificate
+            .ok_or(anyhow!("Can not find the certificate url in the order!"))?;
+        let response = self.post(&certificate_url, None).await?;
+        let cert_pem = String::from_utf8(response.body.to_vec())?;
+        Ok((cert_pem, key_pem))
+    }
+}
+// The certificate is renewed when it is missing,expires in renew_before_days or does not cover
+// all the domains.
+pub fn is_renewal_needed(acme_config: &AcmeConfig) -> bool {
+    let cert_pem = match fs::read(acme_config.get_cert_file()) {
+        Ok(cert_pem) => cert_pem,
+        Err(_) => return true,
+    };
+    if !Path::new(&acme_config.get_key_file()).exists() {
+        return true;
+    }
+    let pem = match x509_parser::pem::parse_x509_pem(&cert_pem) {
+        Ok((_, pem)) => pem,
+        Err(_) => return true,
+    };
+    let certificate = match pem.parse_x509() {
+        Ok(certificate) => certificate,
+        Err(_) => return true,
+    };
+    let dns_names: Vec<String> = match certificate.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns_name) => Some(dns_name.to_ascii_lowercase()),
+                _ => None,
+            })
+            .collect(),
+        _ => return true,
+    };
+    if acme_config
+        .domains
+        .iter()
+        .any(|domain| !dns_names.contains(&domain.to_ascii_lowercase()))
+    {
+        return true;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    let renew_before = acme_config.renew_before_days as i64 * 24 * 3600;
+    certificate.validity().not_after.timestamp() - now < renew_before
+}
+// The key is written before the certificate,the certificate store reloads them when the files
+// are changed.
+pub async fn renew_certificate(acme_config: &AcmeConfig) -> Result<(), anyhow::Error> {
+    acme_config.validate()?;
+    let mut acme_client = AcmeClient::new(acme_config).await?;
+    let (cert_pem, key_pem) = acme_client.issue_certificate(acme_config).await?;
+    write_file(&acme_config.get_key_file(), &key_pem)?;
+    write_file(&acme_config.get_cert_file(), &cert_pem)?;
+    Ok(())
+}
+// The task runs with the https listener,the failed renewal is retried with the exponential
+// backoff.
+pub async fn run_acme_task(mapping_key: String) {
+    let mut retry_interval = ACME_CHECK_INTERVAL;
+    loop {
+        let acme_config = GLOBAL_CONFIG_MAPPING
+            .get(&mapping_key)
+            .and_then(|api_service_manager| api_service_manager.service_config.acme.clone());
+        let interval = match acme_config {
+            Some(acme_config) if is_renewal_needed(&acme_config) => {
+                match renew_certificate(&acme_config).await {
+                    Ok(()) => {
+                        info!(
+                            "Issue the certificate of {:?} successfully!",
+                            acme_config.domains
+                        );
+                        retry_interval = ACME_CHECK_INTERVAL;
+                        ACME_CHECK_INTERVAL
+                    }
+                    Err(err) => {
+                        error!(
+                            "Cause error when issuing the certificate of {:?},{}",
+                            acme_config.domains, err
+                        );
+                        let interval = retry_interval;
+                        retry_interval = (retry_interval * 2).min(ACME_MAX_RETRY_INTERVAL);
+                        interval
+                    }
+                }
+            }
+            _ => ACME_CHECK_INTERVAL,
+        };
+        sleep(interval).await;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+    use std::env;
+
+    fn new_acme_config(storage_dir: &str, domains: Vec<&str>) -> AcmeConfig {
+        AcmeConfig {
+            directory_url: String::from("https://localhost:14000/dir"),
+            contacts: vec![],
+            domains: domains.into_iter().map(String::from).collect(),
+            challenge: AcmeChallengeType::Http01,
+            storage_dir: String::from(storage_dir),
+            renew_before_days: 30,
+            ca_file: None,
+        }
+    }
+    #[test]
+    fn test_account_key_sign() {
+        let (account_key, pem) = AccountKey::generate().unwrap();
+        let account_key2 = AccountKey::from_pem(&pem).unwrap();
+        assert_eq!(account_key.get_jwk(), account_key2.get_jwk());
+        assert_eq!(account_key.get_thumbprint().len(), 43);
+
+        let protected = json!({"alg": "ES256", "nonce": "abc", "url": "https://localhost/"});
+        let jws = account_key.sign(&protected, "{}").unwrap();
+        let signing_input = format!(
+            "{}.{}",
+            jws["protected"].as_str().unwrap(),
+            jws["payload"].as_str().unwrap()
+        );
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        let public_key = UnparsedPublicKey::new(
+            &ECDSA_P256_SHA256_FIXED,
+            account_key.key_pair.public_key().as_ref(),
+        );
+        assert_eq!(
+            public_key
+                .verify(signing_input.as_bytes(), &signature)
+                .is_ok(),
+            true
+        );
+        assert_eq!(jws["payload"], URL_SAFE_NO_PAD.encode("{}"));
+    }
+    #[test]
+    fn test_alpn_challenge_certificate() {
+        let certified_key =
+            new_alpn_challenge_certificate("www.example.com", "token.thumbprint").unwrap();
+        let (_, certificate) =
+            x509_parser::parse_x509_certificate(&certified_key.cert[0].0).unwrap();
+        let extension = certificate
+            .extensions()
+            .iter()
+            .find(|extension| extension.oid.to_id_string() == "1.3.6.1.5.5.7.1.31")
+            .unwrap();
+        assert_eq!(extension.critical, true);
+        let digest = Sha256::digest("token.thumbprint".as_bytes());
+        assert_eq!(&extension.value[2..], digest.as_slice());
+    }
+    #[test]
+    fn test_http_challenge_response() {
+        GLOBAL_ACME_HTTP_CHALLENGES.insert(String::from("test-token"), String::from("test-auth"));
+        let response =
+            get_acme_challenge_response("/.well-known/acme-challenge/test-token").unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            get_acme_challenge_response("/.well-known/acme-challenge/unknown").is_none(),
+            true
+        );
+        assert_eq!(get_acme_challenge_response("/test-token").is_none(), true);
+        GLOBAL_ACME_HTTP_CHALLENGES.remove("test-token");
+    }
+    #[test]
+    fn test_is_renewal_needed() {
+        let storage_dir = env::temp_dir().join("silverwind_test_acme_renewal");
+        let storage_dir = storage_dir.to_str().unwrap();
+        let acme_config = new_acme_config(storage_dir, vec!["www.example.com"]);
+        let _ = fs::remove_dir_all(storage_dir);
+        assert_eq!(is_renewal_needed(&acme_config), true);
+
+        let write_certificate = |not_after| {
+            let mut params = rcgen::CertificateParams::new(vec![String::from("www.example.com")]);
+            params.not_after = not_after;
+            let certificate = rcgen::Certificate::from_params(params).unwrap();
+            write_file(
+                &acme_config.get_cert_file(),
+                &certificate.serialize_pem().unwrap(),
+            )
+            .unwrap();
+            write_file(
+                &acme_config.get_key_file(),
+                &certificate.serialize_private_key_pem(),
+            )
+            .unwrap();
+        };
+        write_certificate(rcgen::date_time_ymd(2099, 1, 1));
+        assert_eq!(is_renewal_needed(&acme_config), false);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(acme_config.get_key_file()).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+        let acme_config2 = new_acme_config(storage_dir, vec!["www.example.com", "api.example.com"]);
+        assert_eq!(is_renewal_needed(&acme_config2), true);
+        write_certificate(rcgen::date_time_ymd(2000, 1, 1));
+        assert_eq!(is_renewal_needed(&acme_config), true);
+        fs::remove_dir_all(storage_dir).unwrap();
+    }
+    #[tokio::test]
+    #[ignore = "requires a local Pebble and SILVERWIND_TEST_ACME_DIRECTORY_URL"]
+    async fn test_issue_certificate_with_pebble() {
+        // Run with `cargo test -- --ignored` against a local Pebble,e.g.
+        // SILVERWIND_TEST_ACME_DIRECTORY_URL=https://127.0.0.1:14000/dir
+        // SILVERWIND_TEST_ACME_CA_FILE=test/certs/pebble.minica.pem
+        // and the http listener on the port 5002 answers the Http01 challenges.
+        let directory_url = env::var("SILVERWIND_TEST_ACME_DIRECTORY_URL")
+            .expect("SILVERWIND_TEST_ACME_DIRECTORY_URL must be set to run the pebble test");
+        let storage_dir = env::temp_dir().join("silverwind_test_acme_pebble");
+        let mut acme_config = new_acme_config(storage_dir.to_str().unwrap(), vec!["localhost"]);
+        acme_config.directory_url = directory_url;
+        acme_config.ca_file = env::var("SILVERWIND_TEST_ACME_CA_FILE").ok();
+        let http_server = tokio::spawn(async {
+            let make_service = hyper::service::make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                    |req: Request<Body>| async move {
+                        Ok::<_, std::convert::Infallible>(
+                            get_acme_challenge_response(req.uri().path())
+                                .unwrap_or(Response::new(Body::empty())),
+                        )
+                    },
+                ))
+            });
+            let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 5002));
+            hyper::Server::bind(&addr).serve(make_service).await
+        });
+        renew_certificate(&acme_config).await.unwrap();
+        http_server.abort();
+        assert_eq!(is_renewal_needed(&acme_config), false);
+        fs::remove_dir_all(storage_dir).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use crate::constants::constants::DEFAULT_CLIENT_HELLO_TIMEOUT_MILLIS;
+use std::io;
+use std::time::Duration;
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+
+const RECORD_HEADER_LENGTH: usize = 5;
+const MAX_RECORD_LENGTH: usize = 16384;
+const HANDSHAKE_HEADER_LENGTH: usize = 4;
+const MAX_CLIENT_HELLO_LENGTH: usize = 65536;
+// The largest ClientHello split into the full records,the peek buffer holds all of them.
+const MAX_CLIENT_HELLO_RECORDS: usize =
+    (HANDSHAKE_HEADER_LENGTH + MAX_CLIENT_HELLO_LENGTH).div_ceil(MAX_RECORD_LENGTH);
+const PEEK_BUFFER_LENGTH: usize = HANDSHAKE_HEADER_LENGTH
+    + MAX_CLIENT_HELLO_LENGTH
+    + MAX_CLIENT_HELLO_RECORDS * RECORD_HEADER_LENGTH;
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 0x01;
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const SERVER_NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], anyhow::Error> {
+        if self.buf.len() < length {
+            return Err(anyhow!("The ClientHello is truncated!"));
+        }
+        let (head, tail) = self.buf.split_at(length);
+        self.buf = tail;
+        Ok(head)
+    }
+    fn read_u8(&mut self) -> Result<u8, anyhow::Error> {
+        Ok(self.take(1)?[0])
+    }
+    fn read_u16(&mut self) -> Result<u16, anyhow::Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+    fn read_u24(&mut self) -> Result<usize, anyhow::Error> {
+        let bytes = self.take(3)?;
+        Ok(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize)
+    }
+    fn read_vec_u8(&mut self) -> Result<Reader<'a>, anyhow::Error> {
+        let length = self.read_u8()? as usize;
+        Ok(Reader {
+            buf: self.take(length)?,
+        })
+    }
+    fn read_vec_u16(&mut self) -> Result<Reader<'a>, anyhow::Error> {
+        let length = self.read_u16()? as usize;
+        Ok(Reader {
+            buf: self.take(length)?,
+        })
+    }
+}
+// Return the length of the first record,none is returned when the record header is incomplete.
+fn get_record_length(buf: &[u8]) -> Result<Option<usize>, anyhow::Error> {
+    if buf.len() < RECORD_HEADER_LENGTH {
+        return Ok(None);
+    }
+    if buf[0] != CONTENT_TYPE_HANDSHAKE {
+        return Err(anyhow!(
+            "The connection does not start with the tls handshake!"
+        ));
+    }
+    let length = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+    if length > MAX_RECORD_LENGTH {
+        return Err(anyhow!("The tls record length {} is too large!", length));
+    }
+    Ok(Some(RECORD_HEADER_LENGTH + length))
+}
+// Reassemble the first handshake message from the records,none is returned when the message
+// is not complete in the buffer.
+fn read_handshake(buf: &[u8]) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    let mut handshake = vec![];
+    let mut offset = 0;
+    loop {
+        if handshake.len() >= HANDSHAKE_HEADER_LENGTH {
+            let length = Reader {
+                buf: &handshake[1..HANDSHAKE_HEADER_LENGTH],
+            }
+            .read_u24()?;
+            if length > MAX_CLIENT_HELLO_LENGTH {
+                return Err(anyhow!("The ClientHello length {} is too large!", length));
+            }
+            if handshake.len() >= HANDSHAKE_HEADER_LENGTH + length {
+                handshake.truncate(HANDSHAKE_HEADER_LENGTH + length);
+                return Ok(Some(handshake));
+            }
+        }
+        let record_length = match get_record_length(&buf[offset..])? {
+            Some(record_length) if offset + record_length <= buf.len() => record_length,
+            _ => return Ok(None),
+        };
+        handshake.extend_from_slice(&buf[offset + RECORD_HEADER_LENGTH..offset + record_length]);
+        offset += record_length;
+    }
+}
+// The SNI name is none when the client does not send the server_name extension.
+pub fn parse_server_name(handshake: &[u8]) -> Result<Option<String>, anyhow::Error> {
+    let mut reader = Reader { buf: handshake };
+    if reader.read_u8()? != HANDSHAKE_TYPE_CLIENT_HELLO {
+        return Err(anyhow!(
+            "The first handshake message is not the ClientHello!"
+        ));
+    }
+    let length = reader.read_u24()?;
+    let mut client_hello = Reader {
+        buf: reader.take(length)?,
+    };
+    // The legacy version and the random.
+    client_hello.take(2 + 32)?;
+    client_hello.read_vec_u8()?;
+    client_hello.read_vec_u16()?;
+    client_hello.read_vec_u8()?;
+    if client_hello.buf.is_empty() {
+        return Ok(None);
+    }
+    let mut extensions = client_hello.read_vec_u16()?;
+    while !extensions.buf.is_empty() {
+        let extension_type = extensions.read_u16()?;
+        let mut extension = extensions.read_vec_u16()?;
+        if extension_type != EXTENSION_SERVER_NAME {
+            continue;
+        }
+        let mut server_names = extension.read_vec_u16()?;
+        while !server_names.buf.is_empty() {
+            let name_type = server_names.read_u8()?;
+            let name = server_names.read_vec_u16()?;
+            if name_type == SERVER_NAME_TYPE_HOST_NAME {
+                let server_name = std::str::from_utf8(name.buf)
+                    .map_err(|err| anyhow!("The SNI name is invalid,{}", err))?;
+                return Ok(Some(server_name.to_ascii_lowercase()));
+            }
+        }
+    }
+    Ok(None)
+}
+// The peek of the tokio stream waits for the readiness,so the duplicated socket is peeked
+// without blocking inside the try_io.
+fn peek_socket(stream: &TcpStream, buf: &mut [u8]) -> io::Result<usize> {
+    #[cfg(unix)]
+    let socket = std::os::unix::io::AsFd::as_fd(stream).try_clone_to_owned()?;
+    #[cfg(windows)]
+    let socket = std::os::windows::io::AsSocket::as_socket(stream).try_clone_to_owned()?;
+    std::net::TcpStream::from(socket).peek(buf)
+}
+// Peek the ClientHello without consuming it,so the tls bytes could be forwarded as they are.
+pub async fn peek_server_name(stream: &TcpStream) -> Result<Option<String>, anyhow::Error> {
+    let timeout = Duration::from_millis(DEFAULT_CLIENT_HELLO_TIMEOUT_MILLIS);
+    let mut buf = vec![0u8; PEEK_BUFFER_LENGTH];
+    let peek_future = async {
+        loop {
+            stream.readable().await?;
+            // The incomplete ClientHello is reported as WouldBlock,so the readiness is cleared and
+            // the readable waits for the next bytes instead of waking up again immediately.
+            let result = stream.try_io(Interest::READABLE, || {
+                let size = peek_socket(stream, &mut buf)?;
+                if size == 0 {
+                    return Ok(Err(anyhow!(
+                        "The connection is closed before the ClientHello!"
+                    )));
+                }
+                match read_handshake(&buf[..size]) {
+                    Ok(Some(handshake)) => Ok(Ok(handshake)),
+                    Ok(None) if size < buf.len() => Err(io::ErrorKind::WouldBlock.into()),
+                    Ok(None) => Ok(Err(anyhow!("The ClientHello exceeds the peek buffer!"))),
+                    Err(err) => Ok(Err(err)),
+                }
+            });
+            match result {
+                Ok(handshake) => return parse_server_name(&handshake?),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(anyhow!("Can not peek the ClientHello,{}", err)),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, peek_future)
+        .await
+        .map_err(|_| anyhow!("Read the ClientHello timeout!"))?
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    fn new_client_hello(server_name: &str) -> Vec<u8> {
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+        let mut connection = ClientConnection::new(
+            Arc::new(config),
+            rustls::ServerName::try_from(server_name).unwrap(),
+        )
+        .unwrap();
+        let mut buf = vec![];
+        connection.write_tls(&mut buf).unwrap();
+        buf
+    }
+    // Split the handshake of the single record into two records at the given payload offset.
+    fn split_records(record: &[u8], at: usize) -> Vec<u8> {
+        let (head, tail) = record[RECORD_HEADER_LENGTH..].split_at(at);
+        let mut buf = vec![];
+        for fragment in [head, tail] {
+            buf.extend_from_slice(&record[..3]);
+            buf.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            buf.extend_from_slice(fragment);
+        }
+        buf
+    }
+    // Pad the ClientHello to the given length with the padding extension and split it into the
+    // full records.
+    fn new_large_client_hello(server_name: &str, length: usize) -> Vec<u8> {
+        let record = new_client_hello(server_name);
+        let mut handshake = record[RECORD_HEADER_LENGTH..].to_vec();
+        let mut client_hello = Reader {
+            buf: &handshake[HANDSHAKE_HEADER_LENGTH..],
+        };
+        client_hello.take(2 + 32).unwrap();
+        client_hello.read_vec_u8().unwrap();
+        client_hello.read_vec_u16().unwrap();
+        client_hello.read_vec_u8().unwrap();
+        let extensions_offset = handshake.len() - client_hello.buf.len();
+        let padding_length = length - (handshake.len() - HANDSHAKE_HEADER_LENGTH) - 4;
+        handshake.extend_from_slice(&[0x00, 0x15]);
+        handshake.extend_from_slice(&(padding_length as u16).to_be_bytes());
+        handshake.resize(handshake.len() + padding_length, 0);
+        let extensions_length = (handshake.len() - extensions_offset - 2) as u16;
+        handshake[extensions_offset..extensions_offset + 2]
+            .copy_from_slice(&extensions_length.to_be_bytes());
+        handshake[1..HANDSHAKE_HEADER_LENGTH].copy_from_slice(&(length as u32).to_be_bytes()[1..]);
+        let mut buf = vec![];
+        for fragment in handshake.chunks(MAX_RECORD_LENGTH) {
+            buf.extend_from_slice(&record[..3]);
+            buf.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            buf.extend_from_slice(fragment);
+        }
+        buf
+    }
+    #[test]
+    fn test_parse_server_name() {
+        let client_hello = new_client_hello("WWW.Example.com");
+        let record_length = get_record_length(&client_hello).unwrap().unwrap();
+        assert_eq!(record_length, client_hello.len());
+        let handshake = read_handshake(&client_hello).unwrap().unwrap();
+        assert_eq!(handshake, client_hello[RECORD_HEADER_LENGTH..].to_vec());
+        assert_eq!(
+            parse_server_name(&handshake).unwrap(),
+            Some(String::from("www.example.com"))
+        );
+        // The SNI is not sent for the ip address.
+        let client_hello = new_client_hello("127.0.0.1");
+        let handshake = read_handshake(&client_hello).unwrap().unwrap();
+        assert_eq!(parse_server_name(&handshake).unwrap(), None);
+    }
+    #[test]
+    fn test_parse_server_name_across_records() {
+        let client_hello = split_records(&new_client_hello("www.example.com"), 30);
+        // The first record alone does not hold the whole ClientHello.
+        let first_record_length = get_record_length(&client_hello).unwrap().unwrap();
+        assert_eq!(
+            read_handshake(&client_hello[..first_record_length]).unwrap(),
+            None
+        );
+        let handshake = read_handshake(&client_hello).unwrap().unwrap();
+        assert_eq!(
+            parse_server_name(&handshake).unwrap(),
+            Some(String::from("www.example.com"))
+        );
+    }
+    #[test]
+    fn test_parse_server_name_error() {
+        let client_hello = new_client_hello("www.example.com");
+        assert_eq!(get_record_length(&client_hello[..3]).unwrap(), None);
+        assert_eq!(read_handshake(&client_hello[..50]).unwrap(), None);
+        let handshake = read_handshake(&client_hello).unwrap().unwrap();
+        assert_eq!(parse_server_name(&handshake[..45]).is_err(), true);
+        assert_eq!(get_record_length(b"GET / HTTP/1.1\r\n").is_err(), true);
+        assert_eq!(read_handshake(b"GET / HTTP/1.1\r\n").is_err(), true);
+    }
+    #[tokio::test]
+    async fn test_peek_server_name_large_client_hello() {
+        // The largest ClientHello takes five full records.
+        let client_hello = new_large_client_hello("api.example.com", MAX_CLIENT_HELLO_LENGTH);
+        assert_eq!(client_hello.len(), PEEK_BUFFER_LENGTH);
+        let handshake = read_handshake(&client_hello).unwrap().unwrap();
+        assert_eq!(
+            handshake.len(),
+            HANDSHAKE_HEADER_LENGTH + MAX_CLIENT_HELLO_LENGTH
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            for chunk in client_hello.chunks(MAX_RECORD_LENGTH) {
+                stream.write_all(chunk).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            stream
+        });
+        let (inbound, _) = listener.accept().await.unwrap();
+        let server_name = peek_server_name(&inbound).await.unwrap();
+        assert_eq!(server_name, Some(String::from("api.example.com")));
+        drop(client.await.unwrap());
+    }
+    #[tokio::test]
+    async fn test_peek_server_name() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client_hello = split_records(&new_client_hello("api.example.com"), 30);
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let (head, tail) = client_hello.split_at(20);
+            stream.write_all(head).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream.write_all(tail).await.unwrap();
+            stream
+        });
+        let (inbound, _) = listener.accept().await.unwrap();
+        let server_name = peek_server_name(&inbound).await.unwrap();
+        assert_eq!(server_name, Some(String::from("api.example.com")));
+        // The ClientHello is still in the stream.
+        let mut buf = [0u8; 1];
+        assert_eq!(inbound.peek(&mut buf).await.unwrap(), 1);
+        assert_eq!(buf[0], CONTENT_TYPE_HANDSHAKE);
+        drop(client.await.unwrap());
+    }
+}
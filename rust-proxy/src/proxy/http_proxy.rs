@@ -1,30 +1,54 @@
 use crate::configuration_service::app_config_service::GLOBAL_CONFIG_MAPPING;
 
 use crate::constants::constants;
-use crate::monitor::prometheus_exporter::{get_timer_list, inc};
+use crate::monitor::prometheus_exporter::{get_timer_list, inc, inc_consumer, inc_waf};
+use crate::proxy::acme_client::get_acme_challenge_response;
+use crate::proxy::certificate_store::CertificateResolver;
+use crate::proxy::proxy_protocol::{
+    encode_proxy_header, get_proxy_protocol_sent, LocalAddr, ProxyProtocolIncoming,
+    ProxyProtocolStream,
+};
 use crate::proxy::tls_acceptor::TlsAcceptor;
-use crate::proxy::tls_stream::TlsStream;
+use crate::proxy::tls_stream::{ServerName, TlsStream};
+use crate::proxy::upstream_connector::{
+    insert_upstream_cache, new_https_connector, UpstreamTlsKey,
+};
+use crate::vojo::acme::{AcmeChallengeType, ACME_TLS_ALPN_PROTOCOL};
+use crate::vojo::app_config::{AccessOutcome, ResponseTemplate, ResponseTemplates};
+use crate::vojo::authentication::AuthenticationFailure;
+use crate::vojo::identity::Identity;
+use crate::vojo::rate_limit::RatelimitQuota;
+use crate::vojo::request_context::RequestContext;
+use crate::vojo::request_limit::{
+    guard_body, BodyGuard, ConnectionLimiter, ConnectionPermit, RequestLimits,
+};
 use crate::vojo::route::BaseRoute;
+use crate::vojo::tls_policy::TlsPolicy;
+use crate::vojo::upstream_tls::UpstreamTls;
+use crate::vojo::waf::{read_body_prefix, WafMode, WafPolicy, WafRequest};
 use dashmap::DashMap;
 use http::uri::InvalidUri;
+use http::HeaderMap;
+use http::HeaderValue;
 use http::StatusCode;
+use hyper::body::HttpBody;
 use hyper::client::HttpConnector;
 use hyper::server::conn::AddrIncoming;
-use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Client, Request, Response, Server};
-use hyper_rustls::ConfigBuilderExt;
+use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
 use hyper_staticfile::Static;
 use lazy_static::lazy_static;
 use log::Level;
 use prometheus::{CounterVec, Gauge, HistogramTimer, HistogramVec};
 use serde_json::json;
 use std::convert::Infallible;
-use std::io::BufReader;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use url::Url;
 lazy_static! {
@@ -72,7 +96,9 @@ pub struct HttpProxy {
 #[derive(Clone)]
 pub struct Clients {
     pub http_client: Client<HttpConnector>,
-    pub https_client: Client<hyper_rustls::HttpsConnector<HttpConnector>>,
+    pub https_client: Client<HttpsConnector<HttpConnector>>,
+    // The pooled clients of the routes with the upstream tls,keyed by the tls options.
+    pub upstream_https_clients: Arc<DashMap<UpstreamTlsKey, Client<HttpsConnector<HttpConnector>>>>,
 }
 impl Clients {
     fn new() -> Clients {
@@ -95,13 +121,35 @@ impl Clients {
         return Clients {
             http_client: http_client,
             https_client: https_client,
+            upstream_https_clients: Default::default(),
         };
     }
     async fn request_http(&self, req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
         return self.http_client.request(req).await;
     }
-    async fn request_https(&self, req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-        return self.https_client.request(req).await;
+    fn get_https_client(
+        &self,
+        upstream_tls: &Option<UpstreamTls>,
+    ) -> Result<Client<HttpsConnector<HttpConnector>>, anyhow::Error> {
+        let upstream_tls = match upstream_tls {
+            Some(upstream_tls) => upstream_tls,
+            None => return Ok(self.https_client.clone()),
+        };
+        let key = UpstreamTlsKey::new(upstream_tls);
+        if let Some(client) = self.upstream_https_clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = Client::builder().build::<_, Body>(new_https_connector(upstream_tls)?);
+        insert_upstream_cache(&self.upstream_https_clients, key, client.clone());
+        Ok(client)
+    }
+    async fn request_https(
+        &self,
+        req: Request<Body>,
+        upstream_tls: &Option<UpstreamTls>,
+    ) -> Result<Response<Body>, anyhow::Error> {
+        let client = self.get_https_client(upstream_tls)?;
+        Ok(client.request(req).await?)
     }
 }
 
@@ -111,27 +159,41 @@ impl HttpProxy {
         let addr = SocketAddr::from(([0, 0, 0, 0], port_clone as u16));
         let client = Clients::new();
         let mapping_key_clone1 = self.mapping_key.clone();
-        let make_service = make_service_fn(move |socket: &AddrStream| {
+        let connection_limiter = Arc::new(ConnectionLimiter::default());
+        let make_service = make_service_fn(move |socket: &ProxyProtocolStream| {
             let client = client.clone();
             let mapping_key2 = mapping_key_clone1.clone();
             let remote_addr = socket.remote_addr();
+            let local_addr = socket.local_addr();
+            let connection_permit =
+                acquire_connection(&connection_limiter, mapping_key2.as_str(), remote_addr);
             async move {
-                Ok::<_, Infallible>(service_fn(move |req| {
+                let connection_permit = connection_permit?;
+                Ok::<_, GeneralError>(service_fn(move |mut req: Request<Body>| {
+                    // The permit is released when the connection is closed.
+                    let _ = &connection_permit;
+                    req.extensions_mut().insert(LocalAddr(local_addr));
                     proxy_adapter(client.clone(), req, mapping_key2.clone(), remote_addr)
                 }))
             }
         });
-        let server = Server::try_bind(&addr)
-            .map_err(|e| {
-                anyhow!(
-                    "Cause error when binding the socket,the addr is {},the error is {}.",
-                    addr.clone(),
-                    e.to_string()
-                )
-            })?
-            .http1_preserve_header_case(true)
-            .http1_title_case_headers(true)
-            .serve(make_service);
+        let incoming = AddrIncoming::bind(&addr).map_err(|e| {
+            anyhow!(
+                "Cause error when binding the socket,the addr is {},the error is {}.",
+                addr.clone(),
+                e.to_string()
+            )
+        })?;
+        let mut builder = Server::builder(ProxyProtocolIncoming::new(
+            incoming,
+            self.mapping_key.clone(),
+        ))
+        .http1_preserve_header_case(true)
+        .http1_title_case_headers(true);
+        if let Some(header_read_timeout) = get_header_read_timeout(self.mapping_key.as_str()) {
+            builder = builder.http1_header_read_timeout(header_read_timeout);
+        }
+        let server = builder.serve(make_service);
         info!("Listening on http://{}", addr);
 
         let reveiver = &mut self.channel;
@@ -146,41 +208,47 @@ impl HttpProxy {
     }
     pub async fn start_https_server(
         &mut self,
-        pem_str: String,
-        key_str: String,
+        certificate_resolver: Arc<CertificateResolver>,
     ) -> Result<(), anyhow::Error> {
         let port_clone = self.port.clone();
         let addr = SocketAddr::from(([0, 0, 0, 0], port_clone as u16));
         let client = Clients::new();
         let mapping_key_clone1 = self.mapping_key.clone();
 
+        let connection_limiter = Arc::new(ConnectionLimiter::default());
         let make_service = make_service_fn(move |socket: &TlsStream| {
             let client = client.clone();
             let mapping_key2 = mapping_key_clone1.clone();
             let remote_addr = socket.remote_addr();
+            let local_addr = socket.local_addr();
+            let server_name = socket.server_name();
+            let connection_permit =
+                acquire_connection(&connection_limiter, mapping_key2.as_str(), remote_addr);
 
             async move {
-                Ok::<_, Infallible>(service_fn(move |req| {
+                let connection_permit = connection_permit?;
+                Ok::<_, GeneralError>(service_fn(move |mut req: Request<Body>| {
+                    // The permit is released when the connection is closed.
+                    let _ = &connection_permit;
+                    req.extensions_mut().insert(LocalAddr(local_addr));
+                    if let Some(server_name) = server_name.get() {
+                        req.extensions_mut().insert(ServerName(server_name.clone()));
+                    }
                     proxy_adapter(client.clone(), req, mapping_key2.clone(), remote_addr)
                 }))
             }
         });
-        let mut cer_reader = BufReader::new(pem_str.as_bytes());
-        let certs = rustls_pemfile::certs(&mut cer_reader)
-            .unwrap()
-            .iter()
-            .map(|s| rustls::Certificate((*s).clone()))
-            .collect();
-
-        let doc = pkcs8::PrivateKeyDocument::from_pem(&key_str).unwrap();
-        let key_der = rustls::PrivateKey(doc.as_ref().to_owned());
-
         let tls_cfg = {
-            let cfg = rustls::ServerConfig::builder()
-                .with_safe_defaults()
-                .with_no_client_auth()
-                .with_single_cert(certs, key_der)
-                .unwrap();
+            let tls_policy = get_tls_policy(self.mapping_key.as_str()).unwrap_or_default();
+            let mut cfg = tls_policy.new_server_config(certificate_resolver)?;
+            // The listener must negotiate the acme-tls/1 protocol to answer the TlsAlpn01
+            // challenge,the listener must be restarted for the change to take effect.
+            if is_tls_alpn_challenge_enabled(self.mapping_key.as_str()) {
+                if cfg.alpn_protocols.is_empty() {
+                    cfg.alpn_protocols.push(b"http/1.1".to_vec());
+                }
+                cfg.alpn_protocols.push(ACME_TLS_ALPN_PROTOCOL.to_vec());
+            }
             Arc::new(cfg)
         };
         let incoming = AddrIncoming::bind(&addr).map_err(|e| {
@@ -190,7 +258,12 @@ impl HttpProxy {
                 e.to_string()
             )
         })?;
-        let server = Server::builder(TlsAcceptor::new(tls_cfg, incoming)).serve(make_service);
+        let incoming = ProxyProtocolIncoming::new(incoming, self.mapping_key.clone());
+        let mut builder = Server::builder(TlsAcceptor::new(tls_cfg, incoming));
+        if let Some(header_read_timeout) = get_header_read_timeout(self.mapping_key.as_str()) {
+            builder = builder.http1_header_read_timeout(header_read_timeout);
+        }
+        let server = builder.serve(make_service);
         info!("Listening on https://{}", addr);
 
         let reveiver = &mut self.channel;
@@ -206,6 +279,52 @@ impl HttpProxy {
     }
 }
 
+fn get_request_limits(mapping_key: &str) -> Option<RequestLimits> {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.request_limits.clone())
+}
+fn get_tls_policy(mapping_key: &str) -> Option<TlsPolicy> {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.tls_policy.clone())
+}
+fn is_tls_alpn_challenge_enabled(mapping_key: &str) -> bool {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.acme.clone())
+        .is_some_and(|acme_config| acme_config.challenge == AcmeChallengeType::TlsAlpn01)
+}
+fn get_header_read_timeout(mapping_key: &str) -> Option<Duration> {
+    get_request_limits(mapping_key)
+        .and_then(|request_limits| request_limits.header_read_timeout_ms)
+        .map(Duration::from_millis)
+}
+// The connection is closed by the hyper when the error is returned from the make_service.
+fn acquire_connection(
+    connection_limiter: &Arc<ConnectionLimiter>,
+    mapping_key: &str,
+    remote_addr: SocketAddr,
+) -> Result<Option<ConnectionPermit>, GeneralError> {
+    let request_limits = match get_request_limits(mapping_key) {
+        Some(request_limits) => request_limits,
+        None => return Ok(None),
+    };
+    let connection_permit = connection_limiter
+        .acquire(remote_addr.ip().to_canonical(), &request_limits)
+        .map_err(GeneralError)?;
+    if connection_permit.is_none() {
+        warn!(
+            "The connection from {} is rejected by the connection limit of {}.",
+            remote_addr, mapping_key
+        );
+        return Err(GeneralError(anyhow!(
+            "The connection limit of {} is exceeded!",
+            mapping_key
+        )));
+    }
+    Ok(connection_permit)
+}
 async fn proxy_adapter(
     client: Clients,
     req: Request<Body>,
@@ -217,11 +336,15 @@ async fn proxy_adapter(
     let path = uri.path();
     let headers = req.headers().clone();
     let current_time = SystemTime::now();
+    let client_addr = SocketAddr::new(
+        get_client_ip(mapping_key.as_str(), remote_addr, &headers),
+        remote_addr.port(),
+    );
     let monitor_timer_list = get_timer_list(mapping_key.clone(), String::from(path.clone()))
         .iter()
         .map(|item| item.start_timer())
         .collect::<Vec<HistogramTimer>>();
-    let res = match proxy(client, req, mapping_key.clone(), remote_addr).await {
+    let res = match proxy(client, req, mapping_key.clone(), client_addr).await {
         Ok(r) => Ok(r),
         Err(err) => {
             let json_value = json!({
@@ -240,6 +363,24 @@ async fn proxy_adapter(
         elapsed_time = elapsed_times.as_millis();
     }
     let status = res.as_ref().unwrap().status().as_u16();
+    let consumer_id = res
+        .as_ref()
+        .unwrap()
+        .extensions()
+        .get::<Identity>()
+        .map(|identity| identity.consumer_id.clone());
+    let auth_failures = res
+        .as_ref()
+        .unwrap()
+        .extensions()
+        .get::<Vec<AuthenticationFailure>>()
+        .map_or(String::from("-"), |items| {
+            items
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<String>>()
+                .join(";")
+        });
     let json_value: serde_json::Value = format!("{:?}", headers).into();
     monitor_timer_list
         .into_iter()
@@ -249,17 +390,30 @@ async fn proxy_adapter(
         String::from(path.clone()),
         status.clone(),
     );
+    if let Some(consumer) = consumer_id.clone() {
+        inc_consumer(mapping_key.clone(), consumer, status);
+    }
     info!(target: "app",
-        "{}$${}$${}$${}$${}$${}",
-        remote_addr.to_string().clone(),
+        "{}$${}$${}$${}$${}$${}$${}$${}",
+        client_addr,
         elapsed_time,
         status,
         method.to_string(),
         path,
-        json_value.to_string()
+        json_value.to_string(),
+        consumer_id.unwrap_or(String::from("-")),
+        auth_failures
     );
     return res;
 }
+fn get_client_ip(mapping_key: &str, remote_addr: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.client_ip.clone())
+        .map_or(remote_addr.ip().to_canonical(), |client_ip_config| {
+            client_ip_config.resolve(remote_addr.ip(), headers)
+        })
+}
 async fn proxy(
     client: Clients,
     mut req: Request<Body>,
@@ -269,6 +423,9 @@ async fn proxy(
     if log_enabled!(Level::Debug) {
         debug!("req: {:?}", req);
     }
+    if let Some(response) = get_acme_challenge_response(req.uri().path()) {
+        return Ok(response);
+    }
 
     let backend_path = req.uri().path();
     let api_service_manager = GLOBAL_CONFIG_MAPPING
@@ -278,7 +435,15 @@ async fn proxy(
             mapping_key.clone()
         ))))?
         .clone();
-    let addr_string = remote_addr.ip().to_string();
+    let request_limits = api_service_manager
+        .service_config
+        .request_limits
+        .clone()
+        .unwrap_or_default();
+    if request_limits.is_header_too_large(req.headers()) {
+        return Ok(limit_response(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE));
+    }
+    let addr_string = remote_addr.ip().to_canonical().to_string();
     for item in api_service_manager.service_config.routes {
         let match_prefix = item
             .matcher
@@ -291,60 +456,352 @@ async fn proxy(
         if !match_result {
             continue;
         }
+        let server_name = req
+            .extensions()
+            .get::<ServerName>()
+            .map(|server_name| server_name.0.clone());
+        if !item
+            .is_server_name_matched(server_name.as_deref())
+            .map_err(GeneralError)?
+        {
+            continue;
+        }
 
-        let is_allowed = item
-            .is_allowed(addr_string.clone(), Some(req.headers().clone()))
+        let max_body_size = item.max_body_size.or(request_limits.max_body_size);
+        let content_length = req
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if let (Some(max_body_size), Some(content_length)) = (max_body_size, content_length) {
+            if content_length > max_body_size {
+                return Ok(limit_response(StatusCode::PAYLOAD_TOO_LARGE));
+            }
+        }
+        let mut body_guard = None;
+        if (max_body_size.is_some() || request_limits.min_body_rate.is_some())
+            && !req.body().is_end_stream()
+        {
+            let (body, guard) = guard_body(
+                std::mem::take(req.body_mut()),
+                max_body_size,
+                request_limits.min_body_rate.clone(),
+            );
+            *req.body_mut() = body;
+            body_guard = Some(guard);
+        }
+        let mut request_context =
+            RequestContext::new(addr_string.clone(), req.uri(), req.headers().clone());
+        let mut access_outcome = item
+            .check_access(&mut request_context)
+            .await
             .map_err(|err| GeneralError(anyhow!(err.to_string())))?;
-        if !is_allowed {
-            return Ok(Response::builder()
-                .status(StatusCode::FORBIDDEN)
-                .body(Body::from(constants::DENY_RESPONSE))
-                .unwrap());
+        if let (AccessOutcome::Allowed, Some(waf_policy)) = (&access_outcome, &item.waf) {
+            let waf_result =
+                check_waf(waf_policy, &mut req, &request_context, mapping_key.as_str()).await;
+            if let Some(res) = body_limit_response(&body_guard) {
+                return Ok(res);
+            }
+            if let Some(rule_id) =
+                waf_result.map_err(|err| GeneralError(anyhow!(err.to_string())))?
+            {
+                access_outcome = AccessOutcome::WafBlocked(rule_id);
+            }
+        }
+        let mut in_flight_permit = None;
+        if let (AccessOutcome::Allowed, Some(in_flight_limit)) =
+            (&access_outcome, &item.in_flight_limit)
+        {
+            in_flight_permit = in_flight_limit
+                .acquire(&request_context)
+                .await
+                .map_err(|err| GeneralError(anyhow!(err.to_string())))?;
+            if in_flight_permit.is_none() {
+                access_outcome = AccessOutcome::Overloaded;
+            }
         }
+        let mut res = if access_outcome != AccessOutcome::Allowed {
+            deny_response(
+                access_outcome,
+                item.response_templates.clone().unwrap_or_default(),
+                &request_context,
+            )
+        } else {
+            let route_cluster = item
+                .route_cluster
+                .clone()
+                .get_route(req.headers().clone())
+                .map_err(|err| GeneralError(anyhow!(err.to_string())))?;
+            let proxy_header = get_proxy_protocol_sent(mapping_key.as_str()).map(|version| {
+                let local_addr = req
+                    .extensions()
+                    .get::<LocalAddr>()
+                    .map_or(SocketAddr::from(([0, 0, 0, 0], 0)), |local_addr| {
+                        local_addr.0
+                    });
+                encode_proxy_header(&version, remote_addr, local_addr)
+            });
+            let route_result = route_request(
+                client,
+                route_cluster,
+                match_prefix,
+                req,
+                proxy_header,
+                &item.upstream_tls,
+            )
+            .await;
+            if let Some(res) = body_limit_response(&body_guard) {
+                return Ok(res);
+            }
+            route_result?
+        };
+        if let Some(in_flight_permit) = in_flight_permit {
+            let body = std::mem::take(res.body_mut());
+            *res.body_mut() = in_flight_permit.attach_to_body(body);
+        }
+        if res.status() != StatusCode::TOO_MANY_REQUESTS {
+            if let Some(quota) = &request_context.ratelimit_quota {
+                add_ratelimit_headers(res.headers_mut(), quota);
+            }
+        }
+        if let Some(identity) = request_context.identity {
+            res.extensions_mut().insert(identity);
+        }
+        if !request_context.auth_failures.is_empty() {
+            res.extensions_mut().insert(request_context.auth_failures);
+        }
+        return Ok(res);
+    }
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from(constants::NOT_FOUND))
+        .unwrap())
+}
 
-        let route_cluster = item
-            .route_cluster
-            .clone()
-            .get_route(req.headers().clone())
-            .map_err(|err| GeneralError(anyhow!(err.to_string())))?;
-        let endpoint = route_cluster.clone().endpoint;
-        if !endpoint.clone().contains("http") {
-            return route_file(route_cluster, req).await;
+fn limit_response(status: StatusCode) -> Response<Body> {
+    let body = match status {
+        StatusCode::PAYLOAD_TOO_LARGE => constants::PAYLOAD_TOO_LARGE_RESPONSE,
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE => constants::HEADER_TOO_LARGE_RESPONSE,
+        _ => constants::REQUEST_TIMEOUT_RESPONSE,
+    };
+    Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+fn body_limit_response(body_guard: &Option<Arc<BodyGuard>>) -> Option<Response<Body>> {
+    body_guard
+        .as_ref()
+        .and_then(|body_guard| body_guard.get_status())
+        .map(limit_response)
+}
+// Return the id of the rule when the request is blocked,the matched request is only logged
+// and counted in the DetectOnly mode.
+async fn check_waf(
+    waf_policy: &WafPolicy,
+    req: &mut Request<Body>,
+    request_context: &RequestContext,
+    mapping_key: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let mut body = vec![];
+    if waf_policy.is_body_inspected() {
+        let (inspected, new_body) =
+            read_body_prefix(std::mem::take(req.body_mut()), waf_policy.max_body_size).await?;
+        body = inspected;
+        *req.body_mut() = new_body;
+    }
+    let waf_request = WafRequest {
+        request_context,
+        body: body.as_slice(),
+    };
+    let violation = match waf_policy.inspect(&waf_request) {
+        Some(violation) => violation,
+        None => return Ok(None),
+    };
+    let action = match waf_policy.mode {
+        WafMode::Block => "blocked",
+        WafMode::DetectOnly => "detected",
+    };
+    warn!(
+        "The request {} from {} is {} by the waf rule {} on the {}.",
+        request_context.path,
+        request_context.remote_ip,
+        action,
+        violation.rule_id,
+        violation.target
+    );
+    inc_waf(String::from(mapping_key), violation.rule_id.clone(), action);
+    match waf_policy.mode {
+        WafMode::Block => Ok(Some(violation.rule_id)),
+        WafMode::DetectOnly => Ok(None),
+    }
+}
+fn deny_response(
+    access_outcome: AccessOutcome,
+    response_templates: ResponseTemplates,
+    request_context: &RequestContext,
+) -> Response<Body> {
+    let (status, template_option, default_body) = match access_outcome.clone() {
+        AccessOutcome::Unauthenticated(_) => {
+            let json_value = json!({
+                "response_code": -1,
+                "response_object": "The request could not be authenticated by the silverwind!",
+                "reasons": request_context.auth_failures
+            });
+            (
+                StatusCode::UNAUTHORIZED,
+                response_templates.unauthorized,
+                json_value.to_string(),
+            )
+        }
+        AccessOutcome::RateLimited(_) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            response_templates.too_many_requests,
+            String::from(constants::TOO_MANY_REQUESTS_RESPONSE),
+        ),
+        AccessOutcome::Overloaded => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            response_templates.service_unavailable,
+            String::from(constants::SERVICE_UNAVAILABLE_RESPONSE),
+        ),
+        _ => (
+            StatusCode::FORBIDDEN,
+            response_templates.forbidden,
+            String::from(constants::DENY_RESPONSE),
+        ),
+    };
+    let template = template_option.unwrap_or(ResponseTemplate {
+        content_type: String::from("application/json"),
+        body: default_body,
+    });
+    let mut builder = Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, template.content_type);
+    match access_outcome {
+        AccessOutcome::Unauthenticated(challenge) => {
+            builder = builder.header(http::header::WWW_AUTHENTICATE, challenge);
         }
-        let host =
-            Url::parse(endpoint.as_str()).map_err(|err| GeneralError(anyhow!(err.to_string())))?;
+        AccessOutcome::RateLimited(quota) => {
+            builder = builder.header(http::header::RETRY_AFTER, quota.reset_after_secs);
+            if let Some(headers) = builder.headers_mut() {
+                add_ratelimit_headers(headers, &quota);
+            }
+        }
+        _ => {}
+    }
+    // the builder only fails on an invalid header,fall back to the default deny response
+    builder
+        .body(Body::from(template.body))
+        .unwrap_or_else(|err| {
+            error!("Can not build the deny response,{}", err);
+            let mut response = Response::new(Body::from(constants::DENY_RESPONSE));
+            *response.status_mut() = status;
+            response
+        })
+}
+fn add_ratelimit_headers(headers: &mut HeaderMap, quota: &RatelimitQuota) {
+    headers.insert("RateLimit-Limit", HeaderValue::from(quota.limit));
+    headers.insert("RateLimit-Remaining", HeaderValue::from(quota.remaining));
+    headers.insert("RateLimit-Reset", HeaderValue::from(quota.reset_after_secs));
+    if let Some(rule) = quota
+        .rule
+        .as_ref()
+        .and_then(|rule| HeaderValue::from_str(rule).ok())
+    {
+        headers.insert("RateLimit-Rule", rule);
+    }
+}
+async fn route_request(
+    client: Clients,
+    route_cluster: BaseRoute,
+    match_prefix: String,
+    mut req: Request<Body>,
+    proxy_header: Option<Vec<u8>>,
+    upstream_tls: &Option<UpstreamTls>,
+) -> Result<Response<Body>, GeneralError> {
+    let endpoint = route_cluster.clone().endpoint;
+    if !endpoint.clone().contains("http") {
+        return route_file(route_cluster, req).await;
+    }
+    let host =
+        Url::parse(endpoint.as_str()).map_err(|err| GeneralError(anyhow!(err.to_string())))?;
 
-        let request_path = host
-            .join(match_prefix.clone().as_str())
-            .map_err(|err| GeneralError(anyhow!(err.to_string())))?
-            .to_string();
-        *req.uri_mut() = request_path
-            .parse()
-            .map_err(|err: InvalidUri| GeneralError(anyhow!(err.to_string())))?;
-        if request_path.contains("https") {
-            return client.request_https(req).await.map_err(|err| {
+    let request_path = host
+        .join(match_prefix.clone().as_str())
+        .map_err(|err| GeneralError(anyhow!(err.to_string())))?
+        .to_string();
+    *req.uri_mut() = request_path
+        .parse()
+        .map_err(|err: InvalidUri| GeneralError(anyhow!(err.to_string())))?;
+    if let Some(proxy_header) = proxy_header {
+        return request_with_proxy_header(req, proxy_header)
+            .await
+            .map_err(|err| {
                 GeneralError(anyhow!(
                     "{},the dst endpoint is {}",
-                    err.to_string(),
+                    err,
                     request_path.clone()
                 ))
             });
-        } else {
-            return client.request_http(req).await.map_err(|err| {
+    }
+    if request_path.contains("https") {
+        client
+            .request_https(req, upstream_tls)
+            .await
+            .map_err(|err| {
                 GeneralError(anyhow!(
                     "{},the dst endpoint is {}",
                     err.to_string(),
                     request_path.clone()
                 ))
-            });
+            })
+    } else {
+        client.request_http(req).await.map_err(|err| {
+            GeneralError(anyhow!(
+                "{},the dst endpoint is {}",
+                err.to_string(),
+                request_path.clone()
+            ))
+        })
+    }
+}
+// The upstream connection carries the address of a single client,so it is not pooled.
+async fn request_with_proxy_header(
+    mut req: Request<Body>,
+    proxy_header: Vec<u8>,
+) -> Result<Response<Body>, anyhow::Error> {
+    let uri = req.uri().clone();
+    if uri.scheme_str() != Some("http") {
+        return Err(anyhow!(
+            "The proxy protocol is only supported for the http upstream!"
+        ));
+    }
+    let host = uri
+        .host()
+        .ok_or(anyhow!("The host of the upstream could not be none!"))?;
+    let mut stream = TcpStream::connect((host, uri.port_u16().unwrap_or(80))).await?;
+    stream.write_all(&proxy_header).await?;
+    if !req.headers().contains_key(http::header::HOST) {
+        if let Some(authority) = uri.authority() {
+            req.headers_mut().insert(
+                http::header::HOST,
+                HeaderValue::from_str(authority.as_str())?,
+            );
         }
     }
-    Ok(Response::builder()
-        .status(StatusCode::NOT_FOUND)
-        .body(Body::from(constants::NOT_FOUND))
-        .unwrap())
+    let path_and_query = uri
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    *req.uri_mut() = path_and_query.parse()?;
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            error!("The upstream connection error is {}", err);
+        }
+    });
+    Ok(sender.send_request(req).await?)
 }
-
 async fn route_file(
     base_route: BaseRoute,
     req: Request<Body>,
@@ -384,6 +841,7 @@ async fn route_file(
 mod tests {
     use super::*;
     use crate::configuration_service::app_config_service::GLOBAL_APP_CONFIG;
+    use crate::proxy::certificate_store::CertificateStore;
     use crate::vojo::allow_deny_ip::AllowDenyObject;
     use crate::vojo::allow_deny_ip::AllowType;
 
@@ -393,6 +851,7 @@ mod tests {
     use crate::vojo::app_config::Matcher;
     use crate::vojo::app_config::Route;
     use crate::vojo::app_config::ServiceConfig;
+    use crate::vojo::client_ip::{ClientIpConfig, ClientIpSource};
     use crate::vojo::route::{BaseRoute, LoadbalancerStrategy, RandomBaseRoute, RandomRoute};
     use crate::vojo::vojo::BaseResponse;
     use lazy_static::lazy_static;
@@ -400,7 +859,7 @@ mod tests {
     use std::env;
     use std::fs::File;
     use std::io::BufReader;
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::Ipv4Addr;
     use std::{thread, time};
     use tokio::runtime::{Builder, Runtime};
     lazy_static! {
@@ -494,8 +953,16 @@ mod tests {
         let ca_certificate_path = env::current_dir()
             .unwrap()
             .join("config")
-            .join("test_key.pem");
+            .join("test_cert.pem");
         let ca_certificate = std::fs::read_to_string(ca_certificate_path).unwrap();
+        let service_config = ServiceConfig {
+            cert_str: Some(ca_certificate),
+            key_str: Some(private_key),
+            ..Default::default()
+        };
+        let certificate_resolver = Arc::new(CertificateResolver::new(
+            CertificateStore::new(&service_config).unwrap(),
+        ));
 
         TOKIO_RUNTIME.spawn(async {
             let (_, receiver) = tokio::sync::mpsc::channel(10);
@@ -505,9 +972,7 @@ mod tests {
                 channel: receiver,
                 mapping_key: String::from("random key"),
             };
-            let _result = http_proxy
-                .start_https_server(ca_certificate, private_key)
-                .await;
+            let _result = http_proxy.start_https_server(certificate_resolver).await;
         });
         let sleep_time = time::Duration::from_millis(100);
         thread::sleep(sleep_time);
@@ -517,7 +982,7 @@ mod tests {
                 .uri("https://localhost:4450/get")
                 .body(Body::empty())
                 .unwrap();
-            let response_result = client.request_https(request).await;
+            let response_result = client.request_https(request, &None).await;
             assert_eq!(response_result.is_ok(), true);
             let response = response_result.unwrap();
             assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
@@ -642,8 +1107,20 @@ mod tests {
                 sender: sender,
                 service_config: ServiceConfig {
                     key_str: None,
+                    client_ip: None,
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
                     server_type: crate::vojo::app_config::ServiceType::HTTP,
                     cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
                     routes: vec![Route {
                         host_name: None,
                         route_id: new_uuid(),
@@ -658,6 +1135,13 @@ mod tests {
                         }]),
                         authentication: None,
                         ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
                     }],
                 },
             };
@@ -696,8 +1180,20 @@ mod tests {
                 sender: sender,
                 service_config: ServiceConfig {
                     key_str: None,
+                    client_ip: None,
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
                     server_type: crate::vojo::app_config::ServiceType::TCP,
                     cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
                     routes: vec![Route {
                         route_id: new_uuid(),
                         host_name: None,
@@ -712,6 +1208,13 @@ mod tests {
                         }]),
                         authentication: None,
                         ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
                     }],
                 },
             };
@@ -734,4 +1237,225 @@ mod tests {
             assert_eq!(response.status(), StatusCode::FORBIDDEN);
         });
     }
+    #[test]
+    fn test_proxy_adapter_deny_forwarded_client_ip() {
+        TOKIO_RUNTIME.block_on(async {
+            let route = Box::new(RandomRoute {
+                routes: vec![RandomBaseRoute {
+                    base_route: BaseRoute {
+                        endpoint: String::from("httpbin.org:80"),
+                        try_file: None,
+                    },
+                }],
+            }) as Box<dyn LoadbalancerStrategy>;
+            let (sender, _) = tokio::sync::mpsc::channel(10);
+            let api_service_manager = ApiServiceManager {
+                sender,
+                service_config: ServiceConfig {
+                    key_str: None,
+                    client_ip: Some(ClientIpConfig {
+                        trusted_proxies: vec![String::from("127.0.0.1")],
+                        source: ClientIpSource::XForwardedFor,
+                    }),
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
+                    server_type: crate::vojo::app_config::ServiceType::HTTP,
+                    cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
+                    routes: vec![Route {
+                        route_id: new_uuid(),
+                        host_name: None,
+                        matcher: Some(Matcher {
+                            prefix: String::from("/"),
+                            prefix_rewrite: String::from("test"),
+                        }),
+                        route_cluster: route,
+                        allow_deny_list: Some(vec![AllowDenyObject {
+                            limit_type: AllowType::DENY,
+                            value: Some(String::from("203.0.113.7")),
+                        }]),
+                        authentication: None,
+                        ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
+                    }],
+                },
+            };
+            GLOBAL_CONFIG_MAPPING.insert(String::from("9997-HTTP"), api_service_manager);
+            let client = Clients::new();
+            let request = Request::builder()
+                .uri("http://localhost:4450/get")
+                .header("X-Forwarded-For", "203.0.113.7")
+                .body(Body::empty())
+                .unwrap();
+            let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+            let res = proxy_adapter(client, request, String::from("9997-HTTP"), socket).await;
+            assert_eq!(res.unwrap().status(), StatusCode::FORBIDDEN);
+        });
+    }
+    #[test]
+    fn test_deny_response_rate_limited() {
+        let quota = RatelimitQuota {
+            limited: true,
+            limit: 10,
+            remaining: 0,
+            reset_after_secs: 30,
+            rule: Some(String::from("burst")),
+        };
+        let response = deny_response(
+            AccessOutcome::RateLimited(quota),
+            ResponseTemplates::default(),
+            &RequestContext::default(),
+        );
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "30");
+        assert_eq!(response.headers().get("RateLimit-Limit").unwrap(), "10");
+        assert_eq!(response.headers().get("RateLimit-Remaining").unwrap(), "0");
+        assert_eq!(response.headers().get("RateLimit-Rule").unwrap(), "burst");
+    }
+    #[test]
+    fn test_deny_response_unauthorized_with_template() {
+        let response_templates = ResponseTemplates {
+            unauthorized: Some(ResponseTemplate {
+                content_type: String::from("text/html"),
+                body: String::from("<h1>Unauthorized</h1>"),
+            }),
+            ..Default::default()
+        };
+        let response = deny_response(
+            AccessOutcome::Unauthenticated(String::from("Basic realm=\"silverwind\"")),
+            response_templates,
+            &RequestContext::default(),
+        );
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get("WWW-Authenticate").unwrap(),
+            "Basic realm=\"silverwind\""
+        );
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "text/html");
+        let ip_denied_response = deny_response(
+            AccessOutcome::IpDenied,
+            ResponseTemplates::default(),
+            &RequestContext::default(),
+        );
+        assert_eq!(ip_denied_response.status(), StatusCode::FORBIDDEN);
+    }
+    #[test]
+    fn test_check_waf_block_and_detect() {
+        TOKIO_RUNTIME.block_on(async {
+            let mut waf_policy: WafPolicy = serde_yaml::from_str("max_body_size: 16\n").unwrap();
+            let mut request = Request::builder()
+                .uri("http://localhost:4450/post")
+                .body(Body::from("a=<script>alert(1)</script>"))
+                .unwrap();
+            let request_context =
+                RequestContext::new(String::from("127.0.0.1"), request.uri(), HeaderMap::new());
+            let res = check_waf(&waf_policy, &mut request, &request_context, "9996-HTTP").await;
+            assert_eq!(res.unwrap(), Some(String::from("core-xss")));
+            let body_bytes = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            assert_eq!(body_bytes, "a=<script>alert(1)</script>");
+
+            waf_policy.mode = WafMode::DetectOnly;
+            let mut request = Request::builder()
+                .uri("http://localhost:4450/get?id=1%20union%20select%20password")
+                .body(Body::empty())
+                .unwrap();
+            let request_context =
+                RequestContext::new(String::from("127.0.0.1"), request.uri(), HeaderMap::new());
+            let res = check_waf(&waf_policy, &mut request, &request_context, "9996-HTTP").await;
+            assert_eq!(res.unwrap(), None);
+        });
+    }
+    #[test]
+    fn test_proxy_request_limits() {
+        TOKIO_RUNTIME.block_on(async {
+            let route = Box::new(RandomRoute {
+                routes: vec![RandomBaseRoute {
+                    base_route: BaseRoute {
+                        endpoint: String::from("httpbin.org:80"),
+                        try_file: None,
+                    },
+                }],
+            }) as Box<dyn LoadbalancerStrategy>;
+            let (sender, _) = tokio::sync::mpsc::channel(10);
+            let api_service_manager = ApiServiceManager {
+                sender,
+                service_config: ServiceConfig {
+                    key_str: None,
+                    client_ip: None,
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: Some(RequestLimits {
+                        max_body_size: Some(1024),
+                        max_header_count: Some(2),
+                        ..Default::default()
+                    }),
+                    server_type: crate::vojo::app_config::ServiceType::HTTP,
+                    cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
+                    routes: vec![Route {
+                        route_id: new_uuid(),
+                        host_name: None,
+                        matcher: Some(Matcher {
+                            prefix: String::from("/"),
+                            prefix_rewrite: String::from("test"),
+                        }),
+                        route_cluster: route,
+                        allow_deny_list: None,
+                        authentication: None,
+                        ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: Some(4),
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
+                    }],
+                },
+            };
+            GLOBAL_CONFIG_MAPPING.insert(String::from("9995-HTTP"), api_service_manager);
+            let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+            let request = Request::builder()
+                .uri("http://localhost:4450/post")
+                .header("Content-Length", "5")
+                .body(Body::from("01234"))
+                .unwrap();
+            let res = proxy(Clients::new(), request, String::from("9995-HTTP"), socket).await;
+            assert_eq!(res.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+            let request = Request::builder()
+                .uri("http://localhost:4450/get")
+                .header("X-Test1", "1")
+                .header("X-Test2", "2")
+                .header("X-Test3", "3")
+                .body(Body::empty())
+                .unwrap();
+            let res = proxy(Clients::new(), request, String::from("9995-HTTP"), socket).await;
+            assert_eq!(
+                res.unwrap().status(),
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            );
+        });
+    }
 }
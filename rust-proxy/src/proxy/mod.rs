@@ -1,5 +1,11 @@
+pub mod acme_client;
+pub mod certificate_store;
+pub mod client_hello;
 pub mod http_proxy;
 pub use http_proxy::HttpProxy;
+pub mod proxy_protocol;
 pub mod tcp_proxy;
 pub mod tls_acceptor;
 pub mod tls_stream;
+pub mod udp_proxy;
+pub mod upstream_connector;
@@ -0,0 +1,328 @@
+use crate::configuration_service::app_config_service::GLOBAL_CONFIG_MAPPING;
+use crate::constants::constants::DEFAULT_PROXY_PROTOCOL_TIMEOUT_MILLIS;
+use crate::vojo::app_config::ProxyProtocolVersion;
+use core::task::{Context, Poll};
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use hyper::server::accept::Accept;
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+// Read the v1 or v2 header from the head of the stream,the source address is returned
+// and none is returned for the LOCAL command or the UNKNOWN protocol.
+pub async fn read_proxy_header<S>(stream: &mut S) -> Result<Option<SocketAddr>, anyhow::Error>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut header = [0u8; 12];
+    stream.read_exact(&mut header).await?;
+    if header == V2_SIGNATURE {
+        return read_v2_header(stream).await;
+    }
+    if !header.starts_with(V1_PREFIX) {
+        return Err(anyhow!("The proxy protocol header is missing!"));
+    }
+    let mut line = header.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(anyhow!("The proxy protocol v1 header is too long!"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2])?;
+    parse_v1_header(line)
+}
+fn parse_v1_header(line: &str) -> Result<Option<SocketAddr>, anyhow::Error> {
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source_ip, _, source_port, _] => {
+            let ip = source_ip.parse::<IpAddr>()?;
+            let port = source_port.parse::<u16>()?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(anyhow!("Invalid proxy protocol v1 header '{}'!", line)),
+    }
+}
+async fn read_v2_header<S>(stream: &mut S) -> Result<Option<SocketAddr>, anyhow::Error>
+where
+    S: AsyncRead + Unpin,
+{
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+    let mut addresses = vec![0u8; length];
+    stream.read_exact(&mut addresses).await?;
+    if version_command >> 4 != 2 {
+        return Err(anyhow!(
+            "Invalid proxy protocol version {}!",
+            version_command >> 4
+        ));
+    }
+    match version_command & 0x0F {
+        0 => return Ok(None),
+        1 => {}
+        command => return Err(anyhow!("Invalid proxy protocol command {}!", command)),
+    }
+    match family >> 4 {
+        1 if length >= 12 => {
+            let ip: [u8; 4] = addresses[0..4].try_into()?;
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::from(ip), port)))
+        }
+        2 if length >= 36 => {
+            let ip: [u8; 16] = addresses[0..16].try_into()?;
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::from(ip), port)))
+        }
+        1 | 2 => Err(anyhow!("The proxy protocol v2 addresses are truncated!")),
+        _ => Ok(None),
+    }
+}
+pub fn encode_proxy_header(
+    version: &ProxyProtocolVersion,
+    source: SocketAddr,
+    destination: SocketAddr,
+) -> Vec<u8> {
+    let (source_ip, destination_ip) = match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            (IpAddr::V4(source_ip), IpAddr::V4(destination_ip))
+        }
+        (source_ip, destination_ip) => (
+            IpAddr::V6(to_ipv6(source_ip)),
+            IpAddr::V6(to_ipv6(destination_ip)),
+        ),
+    };
+    match version {
+        ProxyProtocolVersion::V1 => {
+            let protocol = if source_ip.is_ipv4() { "TCP4" } else { "TCP6" };
+            format!(
+                "PROXY {} {} {} {} {}\r\n",
+                protocol,
+                source_ip,
+                destination_ip,
+                source.port(),
+                destination.port()
+            )
+            .into_bytes()
+        }
+        ProxyProtocolVersion::V2 => {
+            let mut header = V2_SIGNATURE.to_vec();
+            header.push(0x21);
+            match (source_ip, destination_ip) {
+                (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+                    header.push(0x11);
+                    header.extend_from_slice(&12u16.to_be_bytes());
+                    header.extend_from_slice(&source_ip.octets());
+                    header.extend_from_slice(&destination_ip.octets());
+                }
+                _ => {
+                    header.push(0x21);
+                    header.extend_from_slice(&36u16.to_be_bytes());
+                    header.extend_from_slice(&to_ipv6(source_ip).octets());
+                    header.extend_from_slice(&to_ipv6(destination_ip).octets());
+                }
+            }
+            header.extend_from_slice(&source.port().to_be_bytes());
+            header.extend_from_slice(&destination.port().to_be_bytes());
+            header
+        }
+    }
+}
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
+        IpAddr::V6(ipv6) => ipv6,
+    }
+}
+pub fn is_proxy_protocol_accepted(mapping_key: &str, peer_ip: &IpAddr) -> bool {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.proxy_protocol.clone())
+        .is_some_and(|proxy_protocol| proxy_protocol.is_accepted_from(peer_ip))
+}
+pub fn get_proxy_protocol_sent(mapping_key: &str) -> Option<ProxyProtocolVersion> {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.proxy_protocol.clone())
+        .and_then(|proxy_protocol| proxy_protocol.send)
+}
+pub async fn read_client_addr<S>(
+    stream: &mut S,
+    remote_addr: SocketAddr,
+) -> Result<SocketAddr, anyhow::Error>
+where
+    S: AsyncRead + Unpin,
+{
+    let timeout = Duration::from_millis(DEFAULT_PROXY_PROTOCOL_TIMEOUT_MILLIS);
+    let source_option = tokio::time::timeout(timeout, read_proxy_header(stream))
+        .await
+        .map_err(|_| anyhow!("Read the proxy protocol header timeout!"))??;
+    Ok(source_option.unwrap_or(remote_addr))
+}
+#[derive(Debug, Clone, Copy)]
+pub struct LocalAddr(pub SocketAddr);
+
+pub struct ProxyProtocolStream {
+    stream: AddrStream,
+    remote_addr: SocketAddr,
+}
+impl ProxyProtocolStream {
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+    pub fn local_addr(&self) -> SocketAddr {
+        self.stream.local_addr()
+    }
+}
+impl AsyncRead for ProxyProtocolStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+impl AsyncWrite for ProxyProtocolStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+// The header is read in the background so that a slow client does not block the accept loop.
+pub struct ProxyProtocolIncoming {
+    incoming: AddrIncoming,
+    mapping_key: String,
+    pending: FuturesUnordered<BoxFuture<'static, Result<ProxyProtocolStream, anyhow::Error>>>,
+}
+impl ProxyProtocolIncoming {
+    pub fn new(incoming: AddrIncoming, mapping_key: String) -> ProxyProtocolIncoming {
+        ProxyProtocolIncoming {
+            incoming,
+            mapping_key,
+            pending: FuturesUnordered::new(),
+        }
+    }
+}
+impl Accept for ProxyProtocolIncoming {
+    type Conn = ProxyProtocolStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let pin = self.get_mut();
+        loop {
+            match Pin::new(&mut pin.incoming).poll_accept(cx) {
+                Poll::Ready(Some(Ok(mut stream))) => {
+                    let remote_addr = stream.remote_addr();
+                    if !is_proxy_protocol_accepted(pin.mapping_key.as_str(), &remote_addr.ip()) {
+                        return Poll::Ready(Some(Ok(ProxyProtocolStream {
+                            stream,
+                            remote_addr,
+                        })));
+                    }
+                    pin.pending.push(
+                        async move {
+                            let remote_addr = read_client_addr(&mut stream, remote_addr).await?;
+                            Ok(ProxyProtocolStream {
+                                stream,
+                                remote_addr,
+                            })
+                        }
+                        .boxed(),
+                    );
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => break,
+            }
+        }
+        loop {
+            match pin.pending.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(stream))) => return Poll::Ready(Some(Ok(stream))),
+                Poll::Ready(Some(Err(err))) => {
+                    error!("Drop the connection,the error is {}", err);
+                }
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::{Builder, Runtime};
+
+    fn new_runtime() -> Runtime {
+        Builder::new_current_thread().enable_all().build().unwrap()
+    }
+    #[test]
+    fn test_read_proxy_header_v1() {
+        new_runtime().block_on(async {
+            let mut header = &b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 443\r\nGET /"[..];
+            let source = read_proxy_header(&mut header).await.unwrap();
+            assert_eq!(source, Some("203.0.113.7:56324".parse().unwrap()));
+            assert_eq!(header, b"GET /");
+
+            let mut header = &b"PROXY UNKNOWN\r\n"[..];
+            assert_eq!(read_proxy_header(&mut header).await.unwrap(), None);
+        });
+    }
+    #[test]
+    fn test_read_proxy_header_v2() {
+        new_runtime().block_on(async {
+            let source: SocketAddr = "[2001:db8::1]:4711".parse().unwrap();
+            let destination: SocketAddr = "10.0.0.1:443".parse().unwrap();
+            let mut encoded = encode_proxy_header(&ProxyProtocolVersion::V2, source, destination);
+            encoded.extend_from_slice(b"GET /");
+            let mut header = &encoded[..];
+            let res = read_proxy_header(&mut header).await.unwrap();
+            assert_eq!(res, Some(source));
+            assert_eq!(header, b"GET /");
+        });
+    }
+    #[test]
+    fn test_encode_proxy_header_v1() {
+        let source: SocketAddr = "203.0.113.7:56324".parse().unwrap();
+        let destination: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let encoded = encode_proxy_header(&ProxyProtocolVersion::V1, source, destination);
+        assert_eq!(
+            encoded,
+            b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 443\r\n".to_vec()
+        );
+        let encoded = encode_proxy_header(&ProxyProtocolVersion::V2, source, destination);
+        assert_eq!(encoded.len(), 28);
+    }
+    #[test]
+    fn test_read_proxy_header_error() {
+        new_runtime().block_on(async {
+            let mut header = &b"GET / HTTP/1.1\r\nHost: a\r\n"[..];
+            assert_eq!(read_proxy_header(&mut header).await.is_err(), true);
+            let mut header = &b"PROXY TCP4 203.0.113.7\r\n"[..];
+            assert_eq!(read_proxy_header(&mut header).await.is_err(), true);
+        });
+    }
+}
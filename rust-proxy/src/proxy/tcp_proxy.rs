@@ -1,18 +1,107 @@
 use crate::configuration_service::app_config_service::GLOBAL_CONFIG_MAPPING;
+use crate::constants::constants::{
+    DEFAULT_TCP_CONNECT_TIMEOUT_MILLIS, DEFAULT_TLS_HANDSHAKE_TIMEOUT_MILLIS,
+};
+use crate::monitor::prometheus_exporter::{
+    inc_tcp_connect_error, inc_tcp_rejected_connection, observe_tcp_connection,
+};
+use crate::proxy::certificate_store::CertificateResolver;
+use crate::proxy::client_hello::peek_server_name;
+use crate::proxy::proxy_protocol::{
+    encode_proxy_header, get_proxy_protocol_sent, is_proxy_protocol_accepted, read_client_addr,
+};
+use crate::proxy::upstream_connector::connect_tls;
+use crate::vojo::app_config::{ip_is_allowed, AccessOutcome, Route, TcpTlsMode};
+use crate::vojo::request_context::RequestContext;
+use core::task::{Context, Poll};
 use futures::FutureExt;
 use http::HeaderMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
 pub struct TcpProxy {
     pub port: i32,
     pub mapping_key: String,
     pub channel: mpsc::Receiver<()>,
 }
+// The inbound and the outbound stream could be the plain tcp stream or the tls stream.
+trait ProxyStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ProxyStream for T {}
+// Count the bytes read from and written to the client.
+struct CountedStream {
+    stream: Box<dyn ProxyStream>,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+impl CountedStream {
+    fn new(stream: Box<dyn ProxyStream>) -> CountedStream {
+        CountedStream {
+            stream,
+            bytes_in: 0,
+            bytes_out: 0,
+        }
+    }
+}
+impl AsyncRead for CountedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let pin = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut pin.stream).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            pin.bytes_in += (buf.filled().len() - filled) as u64;
+        }
+        result
+    }
+}
+impl AsyncWrite for CountedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let pin = self.get_mut();
+        let result = Pin::new(&mut pin.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(size)) = result {
+            pin.bytes_out += size as u64;
+        }
+        result
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
 impl TcpProxy {
-    pub async fn start_proxy(&mut self) -> Result<(), anyhow::Error> {
+    // The tls is terminated by the certificate resolver when it is set.
+    pub async fn start_proxy(
+        &mut self,
+        certificate_resolver: Option<Arc<CertificateResolver>>,
+    ) -> Result<(), anyhow::Error> {
+        let tls_acceptor = match certificate_resolver {
+            Some(certificate_resolver) => {
+                let tls_policy = GLOBAL_CONFIG_MAPPING
+                    .get(&self.mapping_key)
+                    .and_then(|api_service_manager| {
+                        api_service_manager.service_config.tls_policy.clone()
+                    })
+                    .unwrap_or_default();
+                let server_config = tls_policy.new_server_config(certificate_resolver)?;
+                Some(TlsAcceptor::from(Arc::new(server_config)))
+            }
+            None => None,
+        };
         let listen_addr = format!("0.0.0.0:{}", self.port.clone());
         let mapping_key_clone = self.mapping_key.clone();
         info!("Listening on: {}", listen_addr);
@@ -22,14 +111,18 @@ impl TcpProxy {
             let accept_future = listener.accept();
             tokio::select! {
                accept_result=accept_future=>{
-                if let Ok((inbound, socket_addr))=accept_result{
-                   check(mapping_key_clone.clone(),socket_addr)?;
-                   let transfer = transfer(inbound, mapping_key_clone.clone()).map(|r| {
-                        if let Err(e) = r {
-                            println!("Failed to transfer,error is {}", e);
-                        }
-                    });
-                    tokio::spawn(transfer);
+                // The errors of each connection are handled in its own task,so the listener keeps
+                // accepting the new connections.
+                match accept_result {
+                    Ok((inbound, socket_addr)) => {
+                        let transfer = accept(inbound, socket_addr, mapping_key_clone.clone(), tls_acceptor.clone()).map(|r| {
+                            if let Err(e) = r {
+                                error!("Failed to transfer,error is {}", e);
+                            }
+                        });
+                        tokio::spawn(transfer);
+                    }
+                    Err(err) => error!("Accept the tcp connection error,{}", err),
                 }
                },
                _=reveiver.recv()=>{
@@ -41,76 +134,374 @@ impl TcpProxy {
     }
 }
 
-async fn transfer(mut inbound: TcpStream, mapping_key: String) -> Result<(), anyhow::Error> {
-    let proxy_addr = get_route_cluster(mapping_key)?;
-    let mut outbound = TcpStream::connect(proxy_addr)
-        .await
-        .map_err(|err| anyhow!(err.to_string()))?;
-
-    let (mut ri, mut wi) = inbound.split();
-    let (mut ro, mut wo) = outbound.split();
-    let client_to_server = async {
-        io::copy(&mut ri, &mut wo).await?;
-        wo.shutdown().await
+async fn accept(
+    mut inbound: TcpStream,
+    socket_addr: SocketAddr,
+    mapping_key: String,
+    tls_acceptor: Option<TlsAcceptor>,
+) -> Result<(), anyhow::Error> {
+    let client_addr = if is_proxy_protocol_accepted(mapping_key.as_str(), &socket_addr.ip()) {
+        match read_client_addr(&mut inbound, socket_addr).await {
+            Ok(client_addr) => client_addr,
+            Err(err) => {
+                let inbound = Box::new(inbound);
+                return reject(inbound, &mapping_key, socket_addr, "proxy_protocol", err).await;
+            }
+        }
+    } else {
+        socket_addr
     };
-
-    let server_to_client = async {
-        io::copy(&mut ro, &mut wi).await?;
-        wi.shutdown().await
+    let local_addr = inbound.local_addr()?;
+    // The denied client is rejected before the tls handshake and the SNI peek.
+    match check_ip(
+        mapping_key.as_str(),
+        local_addr.port(),
+        &client_addr.ip().to_canonical(),
+    ) {
+        Ok(true) => {}
+        Ok(false) => {
+            let err = anyhow!("The ip is denied");
+            return reject(
+                Box::new(inbound),
+                &mapping_key,
+                client_addr,
+                "ip_denied",
+                err,
+            )
+            .await;
+        }
+        Err(err) => {
+            return reject(
+                Box::new(inbound),
+                &mapping_key,
+                client_addr,
+                "check_error",
+                err,
+            )
+            .await;
+        }
+    }
+    let (inbound, server_name): (Box<dyn ProxyStream>, Option<String>) = match tls_acceptor {
+        Some(tls_acceptor) => {
+            let timeout = Duration::from_millis(DEFAULT_TLS_HANDSHAKE_TIMEOUT_MILLIS);
+            let tls_stream = match tokio::time::timeout(timeout, tls_acceptor.accept(inbound)).await
+            {
+                Ok(Ok(tls_stream)) => tls_stream,
+                Ok(Err(err)) => {
+                    let err = anyhow!("Can not accept the tls connection,{}", err);
+                    record_rejection(&mapping_key, client_addr, "tls_handshake", err);
+                    return Ok(());
+                }
+                Err(_) => {
+                    let err = anyhow!("Accept the tls connection timeout!");
+                    record_rejection(&mapping_key, client_addr, "tls_handshake", err);
+                    return Ok(());
+                }
+            };
+            let server_name = tls_stream.get_ref().1.sni_hostname().map(String::from);
+            (Box::new(tls_stream), server_name)
+        }
+        None if get_tcp_tls(mapping_key.as_str()) == Some(TcpTlsMode::Passthrough) => {
+            match peek_server_name(&inbound).await {
+                Ok(server_name) => (Box::new(inbound), server_name),
+                Err(err) => {
+                    return reject(
+                        Box::new(inbound),
+                        &mapping_key,
+                        client_addr,
+                        "sni_peek",
+                        err,
+                    )
+                    .await;
+                }
+            }
+        }
+        None => (Box::new(inbound), None),
+    };
+    transfer(
+        inbound,
+        local_addr,
+        mapping_key,
+        client_addr,
+        server_name.as_deref(),
+    )
+    .await
+}
+async fn transfer(
+    inbound: Box<dyn ProxyStream>,
+    local_addr: SocketAddr,
+    mapping_key: String,
+    client_addr: SocketAddr,
+    server_name: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let client_ip = client_addr.ip().to_canonical();
+    let route_result = get_route(
+        mapping_key.clone(),
+        server_name,
+        local_addr.port(),
+        &client_ip,
+    );
+    let mut route = match route_result {
+        Ok(route) => route,
+        Err(err) => {
+            return reject(inbound, mapping_key.as_str(), client_addr, "no_route", err).await;
+        }
+    };
+    // The connection is rejected when the check fails,so the broken config does not let the
+    // clients through.
+    match check(&route, client_addr).await {
+        Ok(AccessOutcome::Allowed) => {}
+        Ok(AccessOutcome::RateLimited(_)) => {
+            let err = anyhow!("The connection rate limit is reached");
+            return reject(
+                inbound,
+                mapping_key.as_str(),
+                client_addr,
+                "rate_limited",
+                err,
+            )
+            .await;
+        }
+        Ok(_) => {
+            let err = anyhow!("The ip is denied");
+            return reject(inbound, mapping_key.as_str(), client_addr, "ip_denied", err).await;
+        }
+        Err(err) => {
+            return reject(
+                inbound,
+                mapping_key.as_str(),
+                client_addr,
+                "check_error",
+                err,
+            )
+            .await;
+        }
+    }
+    let (mut outbound, endpoint) = match connect_endpoint(mapping_key.as_str(), &mut route).await {
+        Ok(connected) => connected,
+        Err(err) => {
+            return reject(
+                inbound,
+                mapping_key.as_str(),
+                client_addr,
+                "upstream_unavailable",
+                err,
+            )
+            .await;
+        }
+    };
+    if let Some(version) = get_proxy_protocol_sent(mapping_key.as_str()) {
+        let proxy_header = encode_proxy_header(&version, client_addr, local_addr);
+        outbound.write_all(&proxy_header).await?;
+    }
+    let mut outbound: Box<dyn ProxyStream> = match &route.upstream_tls {
+        Some(upstream_tls) => match connect_tls(outbound, endpoint.as_str(), upstream_tls).await {
+            Ok(tls_stream) => Box::new(tls_stream),
+            Err(err) => {
+                return reject(
+                    inbound,
+                    mapping_key.as_str(),
+                    client_addr,
+                    "upstream_tls",
+                    err,
+                )
+                .await;
+            }
+        },
+        None => Box::new(outbound),
     };
 
-    let result = tokio::try_join!(client_to_server, server_to_client);
+    let start_time = Instant::now();
+    let mut inbound = CountedStream::new(inbound);
+    let result = io::copy_bidirectional(&mut inbound, &mut outbound).await;
 
-    if result.is_err() {
-        error!("Copy stream error!");
+    if let Err(err) = result {
+        error!("Copy stream error,{}", err);
     }
-
+    let elapsed_time = start_time.elapsed();
+    observe_tcp_connection(
+        mapping_key.as_str(),
+        endpoint.as_str(),
+        inbound.bytes_in,
+        inbound.bytes_out,
+        elapsed_time,
+    );
+    info!(
+        "The tcp connection from {} to {} is closed,{} bytes in,{} bytes out,{} ms.",
+        client_addr,
+        endpoint,
+        inbound.bytes_in,
+        inbound.bytes_out,
+        elapsed_time.as_millis()
+    );
     Ok(())
 }
-fn check(mapping_key: String, remote_addr: SocketAddr) -> Result<bool, anyhow::Error> {
-    let value = GLOBAL_CONFIG_MAPPING
-        .get(&mapping_key.clone())
-        .ok_or("Can not get apiservice from global_mapping")
-        .map_err(|err| anyhow!(err.to_string()))?;
-    let service_config = &value.service_config.routes.clone();
-    let service_config_clone = service_config.clone();
-    if service_config_clone.len() == 0 {
-        return Err(anyhow!("The len of routes is 0"));
+// The endpoint selected by the load balancer is connected first,the next endpoints are tried in
+// order when the connection fails.
+async fn connect_endpoint(
+    mapping_key: &str,
+    route: &mut Route,
+) -> Result<(TcpStream, String), anyhow::Error> {
+    let selected = route.route_cluster.get_route(HeaderMap::new())?.endpoint;
+    let endpoints: Vec<String> = route
+        .route_cluster
+        .get_base_routes()
+        .into_iter()
+        .map(|base_route| base_route.endpoint)
+        .collect();
+    let position = endpoints
+        .iter()
+        .position(|endpoint| endpoint == &selected)
+        .unwrap_or(0);
+    let mut candidates = vec![selected.clone()];
+    candidates.extend(
+        endpoints[position..]
+            .iter()
+            .chain(endpoints[..position].iter())
+            .filter(|endpoint| *endpoint != &selected)
+            .cloned(),
+    );
+    let timeout = Duration::from_millis(DEFAULT_TCP_CONNECT_TIMEOUT_MILLIS);
+    let mut last_error = anyhow!("The route {} has no endpoint!", route.route_id);
+    for endpoint in candidates {
+        match tokio::time::timeout(timeout, TcpStream::connect(endpoint.as_str())).await {
+            Ok(Ok(stream)) => return Ok((stream, endpoint)),
+            Ok(Err(err)) => {
+                last_error = anyhow!("Can not connect the endpoint {},{}", endpoint, err)
+            }
+            Err(_) => last_error = anyhow!("Connect the endpoint {} timeout!", endpoint),
+        }
+        warn!("{}", last_error);
+        inc_tcp_connect_error(mapping_key, endpoint.as_str());
     }
-    let route = service_config_clone.first().unwrap();
-    let is_allowed = route
-        .clone()
-        .is_allowed(remote_addr.ip().to_string(), None)?;
-    Ok(is_allowed)
+    Err(last_error)
+}
+fn get_tcp_tls(mapping_key: &str) -> Option<TcpTlsMode> {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.tcp_tls.clone())
 }
-fn get_route_cluster(mapping_key: String) -> Result<String, anyhow::Error> {
+// The rejected connection is logged and metered,then it is shut down without being proxied.
+async fn reject(
+    mut inbound: Box<dyn ProxyStream>,
+    mapping_key: &str,
+    client_addr: SocketAddr,
+    reason: &str,
+    err: anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    record_rejection(mapping_key, client_addr, reason, err);
+    if let Err(err) = inbound.shutdown().await {
+        debug!("Shutdown the rejected connection error,{}", err);
+    }
+    Ok(())
+}
+// The connection which is already closed is only logged and metered.
+fn record_rejection(mapping_key: &str, client_addr: SocketAddr, reason: &str, err: anyhow::Error) {
+    warn!(
+        "The tcp connection from {} is rejected by {},{}",
+        client_addr, mapping_key, err
+    );
+    inc_tcp_rejected_connection(mapping_key, reason);
+}
+// The ip is allowed when one of the routes which could match the connection before the SNI name
+// is known allows it,the selected route checks the ip again after the handshake.The connection
+// without any route is rejected by the route selection.
+fn check_ip(
+    mapping_key: &str,
+    destination_port: u16,
+    source_ip: &IpAddr,
+) -> Result<bool, anyhow::Error> {
+    let value = match GLOBAL_CONFIG_MAPPING.get(mapping_key) {
+        Some(value) => value,
+        None => return Ok(true),
+    };
+    let mut matched = false;
+    for route in value.service_config.routes.iter() {
+        let is_matched = match &route.tcp_matcher {
+            Some(tcp_matcher) => tcp_matcher.is_matched(destination_port, source_ip)?,
+            None => true,
+        };
+        if !is_matched {
+            continue;
+        }
+        matched = true;
+        if ip_is_allowed(route.allow_deny_list.clone(), source_ip.to_string())? {
+            return Ok(true);
+        }
+    }
+    Ok(!matched)
+}
+// Each connection is checked with the allow_deny_list and consumes the ratelimit of the route.
+async fn check(route: &Route, remote_addr: SocketAddr) -> Result<AccessOutcome, anyhow::Error> {
+    let remote_ip = remote_addr.ip().to_canonical().to_string();
+    let is_allowed = ip_is_allowed(route.allow_deny_list.clone(), remote_ip.clone())?;
+    if !is_allowed {
+        return Ok(AccessOutcome::IpDenied);
+    }
+    if let Some(mut ratelimit_strategy) = route.ratelimit.clone() {
+        let request_context = RequestContext {
+            remote_ip,
+            ..Default::default()
+        };
+        if let Some(quota) = ratelimit_strategy
+            .check_limit_async(&request_context)
+            .await?
+        {
+            if quota.limited {
+                return Ok(AccessOutcome::RateLimited(quota));
+            }
+        }
+    }
+    Ok(AccessOutcome::Allowed)
+}
+// The first route matched with the SNI name,the destination port and the source address is
+// selected,only the selected route is cloned.
+pub fn get_route(
+    mapping_key: String,
+    server_name: Option<&str>,
+    destination_port: u16,
+    source_ip: &IpAddr,
+) -> Result<Route, anyhow::Error> {
     let value = GLOBAL_CONFIG_MAPPING
         .get(&mapping_key.clone())
         .ok_or("Can not get apiservice from global_mapping")
         .map_err(|err| anyhow!(err.to_string()))?;
-    let service_config = &value.service_config.routes.clone();
-    let service_config_clone = service_config.clone();
-    if service_config_clone.len() == 0 {
+    let routes = &value.service_config.routes;
+    if routes.is_empty() {
         return Err(anyhow!("The len of routes is 0"));
     }
-    let mut route = service_config_clone.first().unwrap().route_cluster.clone();
-    route.get_route(HeaderMap::new()).map(|s| s.endpoint)
+    for route in routes.iter() {
+        if route.is_tcp_matched(server_name, destination_port, source_ip)? {
+            return Ok(route.clone());
+        }
+    }
+    Err(anyhow!(
+        "Can not find the route of the server name {:?},the destination port {} and the source {}",
+        server_name,
+        destination_port,
+        source_ip
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::configuration_service::app_config_service::GLOBAL_APP_CONFIG;
+    use crate::proxy::certificate_store::CertificateStore;
     use crate::vojo::allow_deny_ip::AllowDenyObject;
     use crate::vojo::allow_deny_ip::AllowType;
     use crate::vojo::api_service_manager::ApiServiceManager;
     use crate::vojo::app_config::new_uuid;
+    use crate::vojo::app_config::{ProxyProtocolConfig, ProxyProtocolVersion, TcpMatcher};
     use crate::vojo::app_config::{Route, ServiceConfig};
     use crate::vojo::route::{BaseRoute, LoadbalancerStrategy, RandomBaseRoute, RandomRoute};
+    use crate::vojo::upstream_tls::UpstreamTls;
     use lazy_static::lazy_static;
+    use std::convert::TryFrom;
     use std::net::TcpListener;
     use std::net::{IpAddr, Ipv4Addr};
     use std::{thread, time, vec};
+    use tokio::io::AsyncReadExt;
 
     use crate::vojo::app_config::ApiService;
     use crate::vojo::app_config::Matcher;
@@ -137,7 +528,7 @@ mod tests {
                 channel: receiver,
                 mapping_key: String::from("random key"),
             };
-            let _result = tcp_proxy.start_proxy().await;
+            let _result = tcp_proxy.start_proxy(None).await;
         });
         TOKIO_RUNTIME.spawn(async {
             let listener = TcpListener::bind("127.0.0.1:3352");
@@ -146,15 +537,148 @@ mod tests {
         let sleep_time = time::Duration::from_millis(200);
         thread::sleep(sleep_time);
     }
+    fn get_rejected_count(mapping_key: &str, reason: &str) -> f64 {
+        prometheus::gather()
+            .iter()
+            .filter(|item| item.get_name() == "silverwind_tcp_rejected_connections_total")
+            .flat_map(|item| item.get_metric().iter())
+            .filter(|metric| {
+                let labels: Vec<&str> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| label.get_value())
+                    .collect();
+                labels.contains(&mapping_key) && labels.contains(&reason)
+            })
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
     #[test]
     fn test_transfer_error() {
-        TOKIO_RUNTIME.spawn(async {
-            let tcp_stream = TcpStream::connect("httpbin.org:80").await.unwrap();
-            let result = transfer(tcp_stream, String::from("test")).await;
-            assert_eq!(result.is_err(), true);
+        TOKIO_RUNTIME.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let local_addr = listener.local_addr().unwrap();
+            let mut client = TcpStream::connect(local_addr).await.unwrap();
+            let (inbound, _) = listener.accept().await.unwrap();
+            let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+            // The mapping key has no service,so the connection is rejected without a route.
+            let result = transfer(
+                Box::new(inbound),
+                local_addr,
+                String::from("test_transfer_error"),
+                socket,
+                None,
+            )
+            .await;
+            assert_eq!(result.is_ok(), true);
+            assert_eq!(get_rejected_count("test_transfer_error", "no_route"), 1.0);
+            let mut received = vec![0u8; 5];
+            assert_eq!(client.read(&mut received).await.unwrap_or(0), 0);
+        });
+    }
+    #[test]
+    fn test_transfer_reject_upstream_unavailable() {
+        TOKIO_RUNTIME.block_on(async {
+            // The port is released so the connection to it is refused.
+            let dead_endpoint = tokio::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .to_string();
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                routes: vec![new_tcp_route(dead_endpoint, None, None)],
+                ..Default::default()
+            };
+            insert_tcp_service("test_upstream_unavailable", service_config);
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let local_addr = listener.local_addr().unwrap();
+            let mut client = TcpStream::connect(local_addr).await.unwrap();
+            let (inbound, client_addr) = listener.accept().await.unwrap();
+            let result = transfer(
+                Box::new(inbound),
+                local_addr,
+                String::from("test_upstream_unavailable"),
+                client_addr,
+                None,
+            )
+            .await;
+            assert_eq!(result.is_ok(), true);
+            assert_eq!(
+                get_rejected_count("test_upstream_unavailable", "upstream_unavailable"),
+                1.0
+            );
+            let mut received = vec![0u8; 5];
+            assert_eq!(client.read(&mut received).await.unwrap_or(0), 0);
+        });
+    }
+    #[test]
+    fn test_accept_reject_denied_ip_before_sni_peek() {
+        TOKIO_RUNTIME.block_on(async {
+            let mut route = new_tcp_route(String::from("127.0.0.1:9"), None, None);
+            route.allow_deny_list = Some(vec![AllowDenyObject {
+                limit_type: AllowType::DENYALL,
+                value: None,
+            }]);
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                tcp_tls: Some(TcpTlsMode::Passthrough),
+                routes: vec![route],
+                ..Default::default()
+            };
+            insert_tcp_service("test_reject_before_sni_peek", service_config);
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let local_addr = listener.local_addr().unwrap();
+            // The silent client is denied without waiting for the ClientHello.
+            let _client = TcpStream::connect(local_addr).await.unwrap();
+            let (inbound, client_addr) = listener.accept().await.unwrap();
+            let result = tokio::time::timeout(
+                time::Duration::from_millis(1000),
+                accept(
+                    inbound,
+                    client_addr,
+                    String::from("test_reject_before_sni_peek"),
+                    None,
+                ),
+            )
+            .await;
+            assert_eq!(result.unwrap().is_ok(), true);
+            assert_eq!(
+                get_rejected_count("test_reject_before_sni_peek", "ip_denied"),
+                1.0
+            );
+        });
+    }
+    #[test]
+    fn test_accept_reject_sni_peek_error() {
+        TOKIO_RUNTIME.block_on(async {
+            // The client which does not speak tls is rejected by the SNI peek.
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                tcp_tls: Some(TcpTlsMode::Passthrough),
+                routes: vec![new_tcp_route(String::from("127.0.0.1:9"), None, None)],
+                ..Default::default()
+            };
+            insert_tcp_service("test_reject_sni_peek_error", service_config);
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let local_addr = listener.local_addr().unwrap();
+            let mut client = TcpStream::connect(local_addr).await.unwrap();
+            client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+            let (inbound, client_addr) = listener.accept().await.unwrap();
+            let result = accept(
+                inbound,
+                client_addr,
+                String::from("test_reject_sni_peek_error"),
+                None,
+            )
+            .await;
+            assert_eq!(result.is_ok(), true);
+            assert_eq!(
+                get_rejected_count("test_reject_sni_peek_error", "sni_peek"),
+                1.0
+            );
         });
-        let sleep_time = time::Duration::from_millis(2000);
-        thread::sleep(sleep_time);
     }
     #[test]
     fn test_transfer_ok() {
@@ -173,8 +697,20 @@ mod tests {
                 sender: sender,
                 service_config: ServiceConfig {
                     key_str: None,
+                    client_ip: None,
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
                     server_type: crate::vojo::app_config::ServiceType::TCP,
                     cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
                     routes: vec![Route {
                         host_name: None,
                         route_id: new_uuid(),
@@ -183,22 +719,526 @@ mod tests {
                         allow_deny_list: None,
                         authentication: None,
                         ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
                     }],
                 },
             };
             GLOBAL_CONFIG_MAPPING.insert(String::from("test123"), api_service_manager);
             let tcp_stream = TcpStream::connect("httpbin.org:80").await.unwrap();
-            let result = transfer(tcp_stream, String::from("test123")).await;
+            let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+            let local_addr = tcp_stream.local_addr().unwrap();
+            let result = transfer(
+                Box::new(tcp_stream),
+                local_addr,
+                String::from("test123"),
+                socket,
+                None,
+            )
+            .await;
             assert_eq!(result.is_ok(), true);
         });
         let sleep_time = time::Duration::from_millis(2000);
         thread::sleep(sleep_time);
     }
     #[test]
-    fn test_get_route_cluster_error() {
-        let result = get_route_cluster(String::from("testxxxx"));
+    fn test_start_proxy_with_proxy_protocol() {
+        TOKIO_RUNTIME.block_on(async {
+            let backend = tokio::net::TcpListener::bind("127.0.0.1:3356")
+                .await
+                .unwrap();
+            let route = Box::new(RandomRoute {
+                routes: vec![RandomBaseRoute {
+                    base_route: BaseRoute {
+                        endpoint: String::from("127.0.0.1:3356"),
+                        try_file: None,
+                    },
+                }],
+            }) as Box<dyn LoadbalancerStrategy>;
+            let (sender, receiver) = tokio::sync::mpsc::channel(10);
+            let api_service_manager = ApiServiceManager {
+                sender,
+                service_config: ServiceConfig {
+                    key_str: None,
+                    client_ip: None,
+                    proxy_protocol: Some(ProxyProtocolConfig {
+                        accept: true,
+                        trusted_sources: vec![String::from("127.0.0.1")],
+                        send: Some(ProxyProtocolVersion::V1),
+                    }),
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
+                    server_type: crate::vojo::app_config::ServiceType::TCP,
+                    cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
+                    routes: vec![Route {
+                        host_name: None,
+                        route_id: new_uuid(),
+                        matcher: Default::default(),
+                        route_cluster: route,
+                        allow_deny_list: None,
+                        authentication: None,
+                        ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
+                    }],
+                },
+            };
+            GLOBAL_CONFIG_MAPPING.insert(String::from("3355-TCP"), api_service_manager);
+            let mut tcp_proxy = TcpProxy {
+                port: 3355,
+                channel: receiver,
+                mapping_key: String::from("3355-TCP"),
+            };
+            tokio::spawn(async move { tcp_proxy.start_proxy(None).await });
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            let mut client = TcpStream::connect("127.0.0.1:3355").await.unwrap();
+            client
+                .write_all(b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 3355\r\nhello")
+                .await
+                .unwrap();
+            let (mut inbound, _) = backend.accept().await.unwrap();
+            let mut received = vec![0u8; 50];
+            inbound.read_exact(&mut received).await.unwrap();
+            let received = String::from_utf8(received).unwrap();
+            assert_eq!(
+                received.starts_with("PROXY TCP4 203.0.113.7 127.0.0.1 56324 3355\r\n"),
+                true
+            );
+            assert_eq!(received.ends_with("hello"), true);
+        });
+    }
+    fn new_tcp_route(
+        endpoint: String,
+        server_name: Option<&str>,
+        upstream_tls: Option<UpstreamTls>,
+    ) -> Route {
+        Route {
+            host_name: None,
+            route_id: new_uuid(),
+            matcher: Default::default(),
+            route_cluster: Box::new(RandomRoute {
+                routes: vec![RandomBaseRoute {
+                    base_route: BaseRoute {
+                        endpoint,
+                        try_file: None,
+                    },
+                }],
+            }),
+            allow_deny_list: None,
+            authentication: None,
+            ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: server_name.map(String::from),
+            upstream_tls,
+            tcp_matcher: None,
+        }
+    }
+    fn insert_tcp_service(mapping_key: &str, service_config: ServiceConfig) {
+        let (sender, _) = tokio::sync::mpsc::channel(10);
+        GLOBAL_CONFIG_MAPPING.insert(
+            String::from(mapping_key),
+            ApiServiceManager {
+                sender,
+                service_config,
+            },
+        );
+    }
+    fn start_tcp_proxy(
+        port: i32,
+        mapping_key: &str,
+        certificate_resolver: Option<Arc<CertificateResolver>>,
+    ) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(10);
+        let mut tcp_proxy = TcpProxy {
+            port,
+            channel: receiver,
+            mapping_key: String::from(mapping_key),
+        };
+        tokio::spawn(async move {
+            // The listener is closed when the sender is dropped.
+            let _sender = sender;
+            tcp_proxy.start_proxy(certificate_resolver).await
+        });
+    }
+    fn new_client_hello(server_name: &str) -> Vec<u8> {
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let mut connection = rustls::ClientConnection::new(
+            Arc::new(config),
+            rustls::ServerName::try_from(server_name).unwrap(),
+        )
+        .unwrap();
+        let mut buf = vec![];
+        connection.write_tls(&mut buf).unwrap();
+        buf
+    }
+    #[test]
+    fn test_start_proxy_tls_terminate() {
+        TOKIO_RUNTIME.block_on(async {
+            let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = backend.local_addr().unwrap().to_string();
+            let config_dir = std::env::current_dir().unwrap().join("config");
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                cert_str: Some(std::fs::read_to_string(config_dir.join("test_cert.pem")).unwrap()),
+                key_str: Some(std::fs::read_to_string(config_dir.join("test_key.pem")).unwrap()),
+                tcp_tls: Some(TcpTlsMode::Terminate),
+                routes: vec![new_tcp_route(endpoint, None, None)],
+                ..Default::default()
+            };
+            let certificate_resolver = Arc::new(CertificateResolver::new(
+                CertificateStore::new(&service_config).unwrap(),
+            ));
+            insert_tcp_service("3360-TCP", service_config);
+            start_tcp_proxy(3360, "3360-TCP", Some(certificate_resolver));
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            let upstream_tls = UpstreamTls {
+                server_name: Some(String::from("www.example.com")),
+                insecure_skip_verify: true,
+                ..Default::default()
+            };
+            let stream = TcpStream::connect("127.0.0.1:3360").await.unwrap();
+            let mut client = connect_tls(stream, "127.0.0.1:3360", &upstream_tls)
+                .await
+                .unwrap();
+            client.write_all(b"hello").await.unwrap();
+            let (mut inbound, _) = backend.accept().await.unwrap();
+            let mut received = vec![0u8; 5];
+            inbound.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, b"hello");
+        });
+    }
+    #[test]
+    fn test_start_proxy_tls_passthrough() {
+        TOKIO_RUNTIME.block_on(async {
+            let api_backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let default_backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                tcp_tls: Some(TcpTlsMode::Passthrough),
+                routes: vec![
+                    new_tcp_route(
+                        api_backend.local_addr().unwrap().to_string(),
+                        Some("^api\\.example\\.com$"),
+                        None,
+                    ),
+                    new_tcp_route(
+                        default_backend.local_addr().unwrap().to_string(),
+                        None,
+                        None,
+                    ),
+                ],
+                ..Default::default()
+            };
+            insert_tcp_service("3361-TCP", service_config);
+            start_tcp_proxy(3361, "3361-TCP", None);
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            for (server_name, backend) in [
+                ("api.example.com", &api_backend),
+                ("www.example.com", &default_backend),
+            ] {
+                let client_hello = new_client_hello(server_name);
+                let mut client = TcpStream::connect("127.0.0.1:3361").await.unwrap();
+                client.write_all(&client_hello).await.unwrap();
+                let (mut inbound, _) = backend.accept().await.unwrap();
+                let mut received = vec![0u8; client_hello.len()];
+                inbound.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, client_hello);
+            }
+        });
+    }
+    #[test]
+    fn test_start_proxy_tls_origination() {
+        TOKIO_RUNTIME.block_on(async {
+            let certificate =
+                rcgen::generate_simple_self_signed(vec![String::from("backend.internal")]).unwrap();
+            let server_config = rustls::ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![rustls::Certificate(certificate.serialize_der().unwrap())],
+                    rustls::PrivateKey(certificate.serialize_private_key_der()),
+                )
+                .unwrap();
+            let acceptor = TlsAcceptor::from(Arc::new(server_config));
+            let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let upstream_tls = UpstreamTls {
+                server_name: Some(String::from("backend.internal")),
+                insecure_skip_verify: true,
+                ..Default::default()
+            };
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                routes: vec![new_tcp_route(
+                    backend.local_addr().unwrap().to_string(),
+                    None,
+                    Some(upstream_tls),
+                )],
+                ..Default::default()
+            };
+            insert_tcp_service("3362-TCP", service_config);
+            start_tcp_proxy(3362, "3362-TCP", None);
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            let mut client = TcpStream::connect("127.0.0.1:3362").await.unwrap();
+            client.write_all(b"hello").await.unwrap();
+            let (inbound, _) = backend.accept().await.unwrap();
+            let mut tls_stream = acceptor.accept(inbound).await.unwrap();
+            assert_eq!(
+                tls_stream.get_ref().1.sni_hostname(),
+                Some("backend.internal")
+            );
+            let mut received = vec![0u8; 5];
+            tls_stream.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, b"hello");
+        });
+    }
+    #[test]
+    fn test_get_route_error() {
+        let result = get_route(
+            String::from("testxxxx"),
+            None,
+            3360,
+            &IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        );
         assert_eq!(result.is_err(), true);
     }
+    #[test]
+    fn test_start_proxy_connect_fallback() {
+        TOKIO_RUNTIME.block_on(async {
+            let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            // The port is released so the connection to it is refused.
+            let dead_endpoint = tokio::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .to_string();
+            let mut route = new_tcp_route(backend.local_addr().unwrap().to_string(), None, None);
+            route.route_cluster = Box::new(RandomRoute {
+                routes: vec![
+                    RandomBaseRoute {
+                        base_route: BaseRoute {
+                            endpoint: dead_endpoint,
+                            try_file: None,
+                        },
+                    },
+                    RandomBaseRoute {
+                        base_route: BaseRoute {
+                            endpoint: backend.local_addr().unwrap().to_string(),
+                            try_file: None,
+                        },
+                    },
+                ],
+            });
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                routes: vec![route],
+                ..Default::default()
+            };
+            insert_tcp_service("3363-TCP", service_config);
+            start_tcp_proxy(3363, "3363-TCP", None);
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            for _ in 0..5 {
+                let mut client = TcpStream::connect("127.0.0.1:3363").await.unwrap();
+                client.write_all(b"hello").await.unwrap();
+                let (mut inbound, _) = backend.accept().await.unwrap();
+                let mut received = vec![0u8; 5];
+                inbound.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, b"hello");
+                inbound.write_all(b"world").await.unwrap();
+                client.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, b"world");
+            }
+            let metrics = prometheus::gather();
+            let metric_names: Vec<&str> = metrics.iter().map(|item| item.get_name()).collect();
+            assert_eq!(metric_names.contains(&"silverwind_tcp_bytes_total"), true);
+            assert_eq!(
+                metric_names.contains(&"silverwind_tcp_connect_errors_total"),
+                true
+            );
+        });
+    }
+    #[test]
+    fn test_start_proxy_reject_denied_ip() {
+        TOKIO_RUNTIME.block_on(async {
+            let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = backend.local_addr().unwrap().to_string();
+            let mut route = new_tcp_route(endpoint.clone(), None, None);
+            // The client is not in the allowlist,so it is denied by the last rule.
+            route.allow_deny_list = Some(vec![
+                AllowDenyObject {
+                    limit_type: AllowType::ALLOW,
+                    value: Some(String::from("10.0.0.0/8")),
+                },
+                AllowDenyObject {
+                    limit_type: AllowType::DENYALL,
+                    value: None,
+                },
+            ]);
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                routes: vec![route],
+                ..Default::default()
+            };
+            insert_tcp_service("3365-TCP", service_config);
+            start_tcp_proxy(3365, "3365-TCP", None);
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            // The denied connection is closed without being proxied.
+            let mut client = TcpStream::connect("127.0.0.1:3365").await.unwrap();
+            let _ = client.write_all(b"hello").await;
+            let mut received = vec![0u8; 5];
+            assert_eq!(client.read(&mut received).await.unwrap_or(0), 0);
+            let accept_result =
+                tokio::time::timeout(time::Duration::from_millis(200), backend.accept()).await;
+            assert_eq!(accept_result.is_err(), true);
+
+            // The listener keeps accepting the connections after the rejection.
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                routes: vec![new_tcp_route(endpoint, None, None)],
+                ..Default::default()
+            };
+            insert_tcp_service("3365-TCP", service_config);
+            let mut client = TcpStream::connect("127.0.0.1:3365").await.unwrap();
+            client.write_all(b"hello").await.unwrap();
+            let (mut inbound, _) = backend.accept().await.unwrap();
+            inbound.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, b"hello");
+
+            let metrics = prometheus::gather();
+            let metric_names: Vec<&str> = metrics.iter().map(|item| item.get_name()).collect();
+            assert_eq!(
+                metric_names.contains(&"silverwind_tcp_rejected_connections_total"),
+                true
+            );
+        });
+    }
+    #[test]
+    fn test_start_proxy_reject_rate_limited() {
+        TOKIO_RUNTIME.block_on(async {
+            let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut route = new_tcp_route(backend.local_addr().unwrap().to_string(), None, None);
+            route.ratelimit = Some(
+                serde_json::from_str(
+                    r#"{
+                    "type": "FixedWindowRateLimit",
+                    "rate_per_unit": 2,
+                    "unit": {
+                        "type": "Minute"
+                    },
+                    "limit_location": {
+                        "type": "ALL"
+                    },
+                    "key_by": {
+                        "type": "ClientIP"
+                    }
+                }"#,
+                )
+                .unwrap(),
+            );
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                routes: vec![route],
+                ..Default::default()
+            };
+            insert_tcp_service("3366-TCP", service_config);
+            start_tcp_proxy(3366, "3366-TCP", None);
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            let mut clients = vec![];
+            for _ in 0..2 {
+                let mut client = TcpStream::connect("127.0.0.1:3366").await.unwrap();
+                client.write_all(b"hello").await.unwrap();
+                backend.accept().await.unwrap();
+                clients.push(client);
+            }
+            let mut client = TcpStream::connect("127.0.0.1:3366").await.unwrap();
+            let mut received = vec![0u8; 5];
+            assert_eq!(client.read(&mut received).await.unwrap_or(0), 0);
+            let accept_result =
+                tokio::time::timeout(time::Duration::from_millis(200), backend.accept()).await;
+            assert_eq!(accept_result.is_err(), true);
+        });
+    }
+    #[test]
+    fn test_start_proxy_tcp_matcher() {
+        TOKIO_RUNTIME.block_on(async {
+            let internal_backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let default_backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut internal_route = new_tcp_route(
+                internal_backend.local_addr().unwrap().to_string(),
+                None,
+                None,
+            );
+            internal_route.tcp_matcher = Some(TcpMatcher {
+                destination_ports: vec![3364],
+                source_ips: vec![String::from("10.0.0.0/8")],
+            });
+            let service_config = ServiceConfig {
+                server_type: crate::vojo::app_config::ServiceType::TCP,
+                proxy_protocol: Some(ProxyProtocolConfig {
+                    accept: true,
+                    trusted_sources: vec![String::from("127.0.0.0/8")],
+                    send: None,
+                }),
+                routes: vec![
+                    internal_route,
+                    new_tcp_route(
+                        default_backend.local_addr().unwrap().to_string(),
+                        None,
+                        None,
+                    ),
+                ],
+                ..Default::default()
+            };
+            insert_tcp_service("3364-TCP", service_config);
+            start_tcp_proxy(3364, "3364-TCP", None);
+            tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+            for (source_ip, backend) in [
+                ("10.1.2.3", &internal_backend),
+                ("203.0.113.7", &default_backend),
+            ] {
+                let mut client = TcpStream::connect("127.0.0.1:3364").await.unwrap();
+                let proxy_header = format!("PROXY TCP4 {} 127.0.0.1 56324 3364\r\n", source_ip);
+                client.write_all(proxy_header.as_bytes()).await.unwrap();
+                client.write_all(b"hello").await.unwrap();
+                let (mut inbound, _) = backend.accept().await.unwrap();
+                let mut received = vec![0u8; 5];
+                inbound.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, b"hello");
+            }
+        });
+    }
 
     #[test]
     fn test_check_deny_all() {
@@ -217,8 +1257,20 @@ mod tests {
                 sender: sender,
                 service_config: ServiceConfig {
                     key_str: None,
+                    client_ip: None,
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
                     server_type: crate::vojo::app_config::ServiceType::TCP,
                     cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
                     routes: vec![Route {
                         host_name: None,
                         route_id: new_uuid(),
@@ -233,6 +1285,13 @@ mod tests {
                         }]),
                         authentication: None,
                         ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
                     }],
                 },
             };
@@ -244,9 +1303,10 @@ mod tests {
             });
             GLOBAL_CONFIG_MAPPING.insert(String::from("3478-TCP"), api_service_manager);
             let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
-            let res = check(String::from("3478-TCP"), socket);
+            let route = get_route(String::from("3478-TCP"), None, 3478, &socket.ip()).unwrap();
+            let res = check(&route, socket).await;
             assert_eq!(res.is_ok(), true);
-            assert_eq!(res.unwrap(), false);
+            assert_eq!(res.unwrap(), AccessOutcome::IpDenied);
         });
     }
     #[test]
@@ -266,8 +1326,20 @@ mod tests {
                 sender: sender,
                 service_config: ServiceConfig {
                     key_str: None,
+                    client_ip: None,
+                    proxy_protocol: None,
+                    tls_policy: None,
+                    acme: None,
+                    tcp_tls: None,
+                    udp: None,
+                    request_limits: None,
                     server_type: crate::vojo::app_config::ServiceType::TCP,
                     cert_str: None,
+                    cert_file: None,
+                    key_file: None,
+                    key_password: None,
+                    ocsp_file: None,
+                    certificates: vec![],
                     routes: vec![Route {
                         host_name: None,
                         route_id: new_uuid(),
@@ -282,6 +1354,13 @@ mod tests {
                         }]),
                         authentication: None,
                         ratelimit: None,
+                        response_templates: None,
+                        in_flight_limit: None,
+                        waf: None,
+                        max_body_size: None,
+                        server_name: None,
+                        upstream_tls: None,
+                        tcp_matcher: None,
                     }],
                 },
             };
@@ -293,9 +1372,10 @@ mod tests {
             });
             GLOBAL_CONFIG_MAPPING.insert(String::from("3479-TCP"), api_service_manager);
             let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
-            let res = check(String::from("3479-TCP"), socket);
+            let route = get_route(String::from("3479-TCP"), None, 3479, &socket.ip()).unwrap();
+            let res = check(&route, socket).await;
             assert_eq!(res.is_ok(), true);
-            assert_eq!(res.unwrap(), false);
+            assert_eq!(res.unwrap(), AccessOutcome::IpDenied);
         });
     }
 }
@@ -1,8 +1,8 @@
+use crate::proxy::proxy_protocol::ProxyProtocolIncoming;
 use crate::proxy::tls_stream::TlsStream;
 use core::task::{Context, Poll};
 use futures_util::ready;
 use hyper::server::accept::Accept;
-use hyper::server::conn::AddrIncoming;
 use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -11,11 +11,11 @@ use tokio_rustls::rustls::ServerConfig;
 
 pub struct TlsAcceptor {
     config: Arc<ServerConfig>,
-    incoming: AddrIncoming,
+    incoming: ProxyProtocolIncoming,
 }
 
 impl TlsAcceptor {
-    pub fn new(config: Arc<ServerConfig>, incoming: AddrIncoming) -> TlsAcceptor {
+    pub fn new(config: Arc<ServerConfig>, incoming: ProxyProtocolIncoming) -> TlsAcceptor {
         TlsAcceptor { config, incoming }
     }
 }
@@ -41,6 +41,7 @@ impl Accept for TlsAcceptor {
 mod tests {
     use super::*;
     use crate::proxy::tls_acceptor::TlsAcceptor;
+    use hyper::server::conn::AddrIncoming;
     use hyper::service::{make_service_fn, service_fn};
     use hyper::{Body, Response, Server};
     use lazy_static::lazy_static;
@@ -95,7 +96,10 @@ mod tests {
             let listener = TcpListener::bind(&addr).await.unwrap();
             let tls_acceptor = TlsAcceptor {
                 config: tls_cfg,
-                incoming: AddrIncoming::from_listener(listener).unwrap(),
+                incoming: ProxyProtocolIncoming::new(
+                    AddrIncoming::from_listener(listener).unwrap(),
+                    String::from("test"),
+                ),
             };
 
             let make_svc = make_service_fn(|_| async {
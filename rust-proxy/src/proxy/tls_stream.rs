@@ -1,29 +1,44 @@
+use crate::proxy::proxy_protocol::ProxyProtocolStream;
 use core::task::{Context, Poll};
 use futures_util::ready;
-use hyper::server::conn::AddrStream;
 use std::io;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::{future::Future, net::SocketAddr};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio_rustls::rustls::ServerConfig;
 
 enum State {
-    Handshaking(tokio_rustls::Accept<AddrStream>),
-    Streaming(tokio_rustls::server::TlsStream<AddrStream>),
+    Handshaking(tokio_rustls::Accept<ProxyProtocolStream>),
+    Streaming(tokio_rustls::server::TlsStream<ProxyProtocolStream>),
 }
 
+// The SNI name of the connection which is inserted into the extensions of the request.
+#[derive(Debug, Clone)]
+pub struct ServerName(pub String);
 pub struct TlsStream {
     state: State,
+    server_name: Arc<OnceLock<String>>,
 }
 
 impl TlsStream {
-    pub fn new(stream: AddrStream, config: Arc<ServerConfig>) -> TlsStream {
+    pub fn new(stream: ProxyProtocolStream, config: Arc<ServerConfig>) -> TlsStream {
         let accept = tokio_rustls::TlsAcceptor::from(config).accept(stream);
         TlsStream {
             state: State::Handshaking(accept),
+            server_name: Arc::new(OnceLock::new()),
         }
     }
+    // The server name is set after the handshake is finished.
+    pub fn server_name(&self) -> Arc<OnceLock<String>> {
+        self.server_name.clone()
+    }
+    fn set_streaming(&mut self, stream: tokio_rustls::server::TlsStream<ProxyProtocolStream>) {
+        if let Some(server_name) = stream.get_ref().1.sni_hostname() {
+            let _ = self.server_name.set(String::from(server_name));
+        }
+        self.state = State::Streaming(stream);
+    }
     pub fn remote_addr(&self) -> SocketAddr {
         match &self.state {
             State::Handshaking(accept) => {
@@ -38,6 +53,12 @@ impl TlsStream {
             }
         }
     }
+    pub fn local_addr(&self) -> SocketAddr {
+        match &self.state {
+            State::Handshaking(accept) => accept.get_ref().unwrap().local_addr(),
+            State::Streaming(stream) => stream.get_ref().0.local_addr(),
+        }
+    }
 }
 impl AsyncRead for TlsStream {
     fn poll_read(
@@ -50,7 +71,7 @@ impl AsyncRead for TlsStream {
             State::Handshaking(ref mut accept) => match ready!(Pin::new(accept).poll(cx)) {
                 Ok(mut stream) => {
                     let result = Pin::new(&mut stream).poll_read(cx, buf);
-                    pin.state = State::Streaming(stream);
+                    pin.set_streaming(stream);
                     result
                 }
                 Err(err) => Poll::Ready(Err(err)),
@@ -71,7 +92,7 @@ impl AsyncWrite for TlsStream {
             State::Handshaking(ref mut accept) => match ready!(Pin::new(accept).poll(cx)) {
                 Ok(mut stream) => {
                     let result = Pin::new(&mut stream).poll_write(cx, buf);
-                    pin.state = State::Streaming(stream);
+                    pin.set_streaming(stream);
                     result
                 }
                 Err(err) => Poll::Ready(Err(err)),
@@ -97,6 +118,7 @@ impl AsyncWrite for TlsStream {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy::proxy_protocol::ProxyProtocolIncoming;
     use crate::proxy::tls_acceptor::TlsAcceptor;
     use hyper::server::conn::AddrIncoming;
     use hyper::service::{make_service_fn, service_fn};
@@ -151,8 +173,13 @@ mod tests {
             };
             let addr = SocketAddr::from(([127, 0, 0, 1], 7000));
             let listener = TcpListener::bind(&addr).await.unwrap();
-            let tls_acceptor =
-                TlsAcceptor::new(tls_cfg, AddrIncoming::from_listener(listener).unwrap());
+            let tls_acceptor = TlsAcceptor::new(
+                tls_cfg,
+                ProxyProtocolIncoming::new(
+                    AddrIncoming::from_listener(listener).unwrap(),
+                    String::from("test"),
+                ),
+            );
 
             let make_svc = make_service_fn(|_| async {
                 Ok::<_, hyper::Error>(service_fn(|_req| async {
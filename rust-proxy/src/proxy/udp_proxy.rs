@@ -0,0 +1,381 @@
+use crate::configuration_service::app_config_service::GLOBAL_CONFIG_MAPPING;
+use crate::monitor::prometheus_exporter::{
+    inc_udp_dropped_packets, observe_udp_session, set_udp_sessions,
+};
+use crate::proxy::tcp_proxy::get_route;
+use crate::vojo::app_config::{ip_is_allowed, UdpConfig};
+use dashmap::DashMap;
+use http::HeaderMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+const SESSION_CHANNEL_SIZE: usize = 1024;
+
+type Sessions = Arc<DashMap<SocketAddr, mpsc::Sender<Vec<u8>>>>;
+pub struct UdpProxy {
+    pub port: i32,
+    pub mapping_key: String,
+    pub channel: mpsc::Receiver<()>,
+}
+impl UdpProxy {
+    pub async fn start_proxy(&mut self) -> Result<(), anyhow::Error> {
+        let listen_addr = format!("0.0.0.0:{}", self.port);
+        info!("Listening on: {}", listen_addr);
+        let socket = Arc::new(UdpSocket::bind(listen_addr).await?);
+        let sessions: Sessions = Default::default();
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        let reveiver = &mut self.channel;
+        loop {
+            tokio::select! {
+               recv_result=socket.recv_from(&mut buf)=>{
+                match recv_result {
+                    Ok((size, client_addr)) => {
+                        if let Err(err) = dispatch(
+                            socket.clone(),
+                            sessions.clone(),
+                            self.mapping_key.clone(),
+                            client_addr,
+                            &buf[..size],
+                        ) {
+                            error!("Failed to dispatch the udp packet,error is {}", err);
+                        }
+                    }
+                    Err(err) => error!("Receive the udp packet error,{}", err),
+                }
+               },
+               _=reveiver.recv()=>{
+                // The sessions are closed when the senders are dropped.
+                sessions.clear();
+                info!("close the socket!");
+                return Ok(());
+               }
+            };
+        }
+    }
+}
+// The packet is forwarded by the session of the client,the session is created when the client
+// is new or the previous session is closed by the idle timeout.
+fn dispatch(
+    socket: Arc<UdpSocket>,
+    sessions: Sessions,
+    mapping_key: String,
+    client_addr: SocketAddr,
+    packet: &[u8],
+) -> Result<(), anyhow::Error> {
+    let send_result = sessions
+        .get(&client_addr)
+        .map(|sender| sender.try_send(packet.to_vec()));
+    match send_result {
+        Some(Ok(())) => return Ok(()),
+        Some(Err(TrySendError::Full(_))) => {
+            warn!(
+                "The udp session of {} is busy,the packet is dropped.",
+                client_addr
+            );
+            inc_udp_dropped_packets(mapping_key.as_str(), "busy", 1);
+            return Ok(());
+        }
+        Some(Err(TrySendError::Closed(_))) | None => {}
+    }
+    let local_addr = socket.local_addr()?;
+    let client_ip = client_addr.ip().to_canonical();
+    let mut route = get_route(mapping_key.clone(), None, local_addr.port(), &client_ip)?;
+    let is_allowed = ip_is_allowed(route.allow_deny_list.clone(), client_ip.to_string())?;
+    if !is_allowed {
+        info!("The udp packet from {} is denied.", client_addr);
+        inc_udp_dropped_packets(mapping_key.as_str(), "denied", 1);
+        return Ok(());
+    }
+    let udp_config = get_udp_config(mapping_key.as_str());
+    if sessions.len() >= udp_config.max_sessions {
+        warn!(
+            "The udp sessions of {} reach the limit {},the packet from {} is dropped.",
+            mapping_key, udp_config.max_sessions, client_addr
+        );
+        inc_udp_dropped_packets(mapping_key.as_str(), "session_limit", 1);
+        return Ok(());
+    }
+    let endpoint = route.route_cluster.get_route(HeaderMap::new())?.endpoint;
+    let (sender, receiver) = mpsc::channel(SESSION_CHANNEL_SIZE);
+    sender.try_send(packet.to_vec())?;
+    sessions.insert(client_addr, sender);
+    set_udp_sessions(mapping_key.as_str(), sessions.len());
+    tokio::spawn(async move {
+        let idle_timeout = Duration::from_millis(udp_config.idle_timeout_ms);
+        if let Err(err) = transfer(
+            socket,
+            receiver,
+            mapping_key.as_str(),
+            client_addr,
+            endpoint.as_str(),
+            idle_timeout,
+        )
+        .await
+        {
+            error!("The udp session of {} error,{}", client_addr, err);
+        }
+        // The receiver has been dropped,so only the closed session is removed.
+        sessions.remove_if(&client_addr, |_, sender| sender.is_closed());
+        set_udp_sessions(mapping_key.as_str(), sessions.len());
+    });
+    Ok(())
+}
+async fn transfer(
+    socket: Arc<UdpSocket>,
+    mut receiver: mpsc::Receiver<Vec<u8>>,
+    mapping_key: &str,
+    client_addr: SocketAddr,
+    endpoint: &str,
+    idle_timeout: Duration,
+) -> Result<(), anyhow::Error> {
+    let endpoint_addr = lookup_host(endpoint)
+        .await?
+        .next()
+        .ok_or(anyhow!("Can not resolve the endpoint {}", endpoint))?;
+    let bind_addr = match endpoint_addr {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let upstream = UdpSocket::bind(bind_addr).await?;
+    upstream.connect(endpoint_addr).await?;
+
+    let start_time = Instant::now();
+    let mut packets = (0u64, 0u64);
+    let mut bytes = (0u64, 0u64);
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    let result = loop {
+        tokio::select! {
+            packet=receiver.recv()=>{
+                let packet = match packet {
+                    Some(packet) => packet,
+                    None => break Ok(()),
+                };
+                if let Err(err) = upstream.send(&packet).await {
+                    break Err(anyhow!("Can not send to the endpoint {},{}", endpoint, err));
+                }
+                packets.0 += 1;
+                bytes.0 += packet.len() as u64;
+            },
+            recv_result=upstream.recv(&mut buf)=>{
+                let size = match recv_result {
+                    Ok(size) => size,
+                    Err(err) => break Err(anyhow!("Can not receive from the endpoint {},{}", endpoint, err)),
+                };
+                if let Err(err) = socket.send_to(&buf[..size], client_addr).await {
+                    break Err(anyhow!("Can not send to the client {},{}", client_addr, err));
+                }
+                packets.1 += 1;
+                bytes.1 += size as u64;
+            },
+            _=tokio::time::sleep(idle_timeout)=>{
+                break Ok(());
+            }
+        }
+    };
+    // The receiver is closed before the metrics are observed,so the new packets of the client
+    // create a new session.
+    receiver.close();
+    // The packets queued before the close are still forwarded when the upstream is healthy,
+    // otherwise they are counted as dropped.
+    let mut dropped = 0;
+    while let Ok(packet) = receiver.try_recv() {
+        if result.is_err() || upstream.send(&packet).await.is_err() {
+            dropped += 1;
+            continue;
+        }
+        packets.0 += 1;
+        bytes.0 += packet.len() as u64;
+    }
+    if dropped > 0 {
+        warn!(
+            "The udp session of {} is closed,{} queued packets are dropped.",
+            client_addr, dropped
+        );
+        inc_udp_dropped_packets(mapping_key, "session_closed", dropped);
+    }
+    let elapsed_time = start_time.elapsed();
+    observe_udp_session(mapping_key, endpoint, packets, bytes, elapsed_time);
+    info!(
+        "The udp session from {} to {} is closed,{} packets in,{} packets out,{} ms.",
+        client_addr,
+        endpoint,
+        packets.0,
+        packets.1,
+        elapsed_time.as_millis()
+    );
+    result
+}
+fn get_udp_config(mapping_key: &str) -> UdpConfig {
+    GLOBAL_CONFIG_MAPPING
+        .get(mapping_key)
+        .and_then(|api_service_manager| api_service_manager.service_config.udp.clone())
+        .unwrap_or_default()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vojo::allow_deny_ip::{AllowDenyObject, AllowType};
+    use crate::vojo::api_service_manager::ApiServiceManager;
+    use crate::vojo::app_config::{new_uuid, Route, ServiceConfig, ServiceType};
+    use crate::vojo::route::{BaseRoute, PollBaseRoute, PollRoute};
+
+    fn new_udp_route(endpoints: Vec<String>) -> Route {
+        Route {
+            host_name: None,
+            route_id: new_uuid(),
+            matcher: Default::default(),
+            route_cluster: Box::new(PollRoute {
+                routes: endpoints
+                    .into_iter()
+                    .map(|endpoint| PollBaseRoute {
+                        base_route: BaseRoute {
+                            endpoint,
+                            try_file: None,
+                        },
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            allow_deny_list: None,
+            authentication: None,
+            ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
+        }
+    }
+    fn start_udp_proxy(port: i32, route: Route, udp_config: Option<UdpConfig>) {
+        let mapping_key = format!("{}-UDP", port);
+        let (sender, receiver) = mpsc::channel(10);
+        GLOBAL_CONFIG_MAPPING.insert(
+            mapping_key.clone(),
+            ApiServiceManager {
+                sender: sender.clone(),
+                service_config: ServiceConfig {
+                    server_type: ServiceType::UDP,
+                    udp: udp_config,
+                    routes: vec![route],
+                    ..Default::default()
+                },
+            },
+        );
+        let mut udp_proxy = UdpProxy {
+            port,
+            mapping_key,
+            channel: receiver,
+        };
+        tokio::spawn(async move {
+            // The listener is closed when the sender is dropped.
+            let _sender = sender;
+            udp_proxy.start_proxy().await
+        });
+    }
+    async fn recv_timeout(socket: &UdpSocket) -> Option<(Vec<u8>, SocketAddr)> {
+        let mut buf = vec![0u8; 1024];
+        let recv_future = socket.recv_from(&mut buf);
+        match tokio::time::timeout(Duration::from_millis(300), recv_future).await {
+            Ok(Ok((size, addr))) => Some((buf[..size].to_vec(), addr)),
+            _ => None,
+        }
+    }
+    #[tokio::test]
+    async fn test_start_proxy_udp() {
+        let backends = [
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let endpoints = backends
+            .iter()
+            .map(|backend| backend.local_addr().unwrap().to_string())
+            .collect();
+        start_udp_proxy(3370, new_udp_route(endpoints), None);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // The sessions of the two clients are balanced to the two backends.
+        for backend in backends.iter() {
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.send_to(b"hello", "127.0.0.1:3370").await.unwrap();
+            let (packet, session_addr) = recv_timeout(backend).await.unwrap();
+            assert_eq!(packet, b"hello");
+            client.send_to(b"world", "127.0.0.1:3370").await.unwrap();
+            let (packet, addr) = recv_timeout(backend).await.unwrap();
+            assert_eq!(packet, b"world");
+            assert_eq!(addr, session_addr);
+            backend.send_to(b"reply", session_addr).await.unwrap();
+            let (packet, _) = recv_timeout(&client).await.unwrap();
+            assert_eq!(packet, b"reply");
+        }
+    }
+    #[tokio::test]
+    async fn test_start_proxy_udp_idle_timeout() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let route = new_udp_route(vec![backend.local_addr().unwrap().to_string()]);
+        let udp_config = UdpConfig {
+            idle_timeout_ms: 100,
+            ..Default::default()
+        };
+        start_udp_proxy(3371, route, Some(udp_config));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"hello", "127.0.0.1:3371").await.unwrap();
+        let (_, first_session_addr) = recv_timeout(&backend).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        client.send_to(b"hello", "127.0.0.1:3371").await.unwrap();
+        let (_, second_session_addr) = recv_timeout(&backend).await.unwrap();
+        assert_eq!(first_session_addr == second_session_addr, false);
+
+        let metrics = prometheus::gather();
+        let metric_names: Vec<&str> = metrics.iter().map(|item| item.get_name()).collect();
+        assert_eq!(metric_names.contains(&"silverwind_udp_packets_total"), true);
+    }
+    #[tokio::test]
+    async fn test_start_proxy_udp_deny() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut route = new_udp_route(vec![backend.local_addr().unwrap().to_string()]);
+        route.allow_deny_list = Some(vec![AllowDenyObject {
+            limit_type: AllowType::DENY,
+            value: Some(String::from("127.0.0.1")),
+        }]);
+        start_udp_proxy(3372, route, None);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"hello", "127.0.0.1:3372").await.unwrap();
+        assert_eq!(recv_timeout(&backend).await.is_none(), true);
+    }
+    #[tokio::test]
+    async fn test_start_proxy_udp_max_sessions() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let route = new_udp_route(vec![backend.local_addr().unwrap().to_string()]);
+        let udp_config = UdpConfig {
+            max_sessions: 1,
+            ..Default::default()
+        };
+        start_udp_proxy(3373, route, Some(udp_config));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let client1 = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client1.send_to(b"hello", "127.0.0.1:3373").await.unwrap();
+        assert_eq!(recv_timeout(&backend).await.is_some(), true);
+        let client2 = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client2.send_to(b"hello", "127.0.0.1:3373").await.unwrap();
+        assert_eq!(recv_timeout(&backend).await.is_none(), true);
+
+        let metrics = prometheus::gather();
+        let metric_names: Vec<&str> = metrics.iter().map(|item| item.get_name()).collect();
+        assert_eq!(
+            metric_names.contains(&"silverwind_udp_dropped_packets_total"),
+            true
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use crate::proxy::certificate_store::{load_certs, load_private_key};
+use crate::vojo::ip_set::get_modified_time;
+use crate::vojo::upstream_tls::UpstreamTls;
+use dashmap::DashMap;
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+use lazy_static::lazy_static;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use std::convert::TryFrom;
+use std::fs;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+lazy_static! {
+    // The client configs of the tcp routes,keyed by the tls options and the files.
+    static ref GLOBAL_UPSTREAM_CLIENT_CONFIGS: DashMap<UpstreamTlsKey, Arc<ClientConfig>> =
+        Default::default();
+}
+// The tls options and the modified time of the files,the cached client is rebuilt when any of
+// the files is changed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UpstreamTlsKey {
+    upstream_tls: UpstreamTls,
+    modified_times: Vec<Option<SystemTime>>,
+}
+impl UpstreamTlsKey {
+    pub fn new(upstream_tls: &UpstreamTls) -> Self {
+        let modified_times = [
+            &upstream_tls.ca_file,
+            &upstream_tls.cert_file,
+            &upstream_tls.key_file,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|file| get_modified_time(file))
+        .collect();
+        UpstreamTlsKey {
+            upstream_tls: upstream_tls.clone(),
+            modified_times,
+        }
+    }
+}
+// The entry built from the old files of the same tls options is replaced.
+pub fn insert_upstream_cache<T>(cache: &DashMap<UpstreamTlsKey, T>, key: UpstreamTlsKey, value: T) {
+    cache.retain(|item, _| item.upstream_tls != key.upstream_tls);
+    cache.insert(key, value);
+}
+
+// Verify the certificate of the upstream with the configured name instead of the SNI name.
+struct HostnameOverrideVerifier {
+    verifier: WebPkiVerifier,
+    hostname: ServerName,
+}
+impl ServerCertVerifier for HostnameOverrideVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            &self.hostname,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+struct InsecureVerifier;
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+fn read_file(file: &str) -> Result<String, anyhow::Error> {
+    fs::read_to_string(file).map_err(|err| anyhow!("Can not read the file {},{}", file, err))
+}
+fn load_root_store(ca_file: &Option<String>) -> Result<RootCertStore, anyhow::Error> {
+    let mut root_store = RootCertStore::empty();
+    let ca_file = match ca_file {
+        Some(ca_file) => ca_file,
+        None => {
+            root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+                |trust_anchor| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        trust_anchor.subject,
+                        trust_anchor.spki,
+                        trust_anchor.name_constraints,
+                    )
+                },
+            ));
+            return Ok(root_store);
+        }
+    };
+    let certs = load_certs(read_file(ca_file)?.as_str())?;
+    let (added, _) = root_store
+        .add_parsable_certificates(&certs.into_iter().map(|cert| cert.0).collect::<Vec<_>>());
+    if added == 0 {
+        return Err(anyhow!("Can not find the certificate in {}!", ca_file));
+    }
+    Ok(root_store)
+}
+pub fn new_client_config(upstream_tls: &UpstreamTls) -> Result<ClientConfig, anyhow::Error> {
+    upstream_tls.validate()?;
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let builder = if upstream_tls.insecure_skip_verify {
+        warn!("The certificate of the upstream is not verified,the insecure_skip_verify should only be used for testing!");
+        builder.with_custom_certificate_verifier(Arc::new(InsecureVerifier))
+    } else {
+        let root_store = load_root_store(&upstream_tls.ca_file)?;
+        match &upstream_tls.verify_hostname {
+            Some(verify_hostname) => {
+                let hostname = ServerName::try_from(verify_hostname.as_str()).map_err(|err| {
+                    anyhow!("The verify_hostname {} is invalid,{}", verify_hostname, err)
+                })?;
+                builder.with_custom_certificate_verifier(Arc::new(HostnameOverrideVerifier {
+                    verifier: WebPkiVerifier::new(root_store, None),
+                    hostname,
+                }))
+            }
+            None => builder
+                .with_custom_certificate_verifier(Arc::new(WebPkiVerifier::new(root_store, None))),
+        }
+    };
+    match (&upstream_tls.cert_file, &upstream_tls.key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            let certs = load_certs(read_file(cert_file)?.as_str())?;
+            let private_key = load_private_key(
+                read_file(key_file)?.as_str(),
+                upstream_tls.key_password.as_deref(),
+            )?;
+            builder
+                .with_single_cert(certs, private_key)
+                .map_err(|err| anyhow!("The client certificate is invalid,{}", err))
+        }
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+pub fn new_https_connector(
+    upstream_tls: &UpstreamTls,
+) -> Result<HttpsConnector<HttpConnector>, anyhow::Error> {
+    let client_config = new_client_config(upstream_tls)?;
+    if let Some(server_name) = &upstream_tls.server_name {
+        ServerName::try_from(server_name.as_str())
+            .map_err(|err| anyhow!("The server_name {} is invalid,{}", server_name, err))?;
+    }
+    let builder = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(client_config)
+        .https_or_http();
+    let builder = match &upstream_tls.server_name {
+        Some(server_name) => builder.with_server_name(server_name.clone()),
+        None => builder,
+    };
+    Ok(builder.enable_http1().build())
+}
+fn get_client_config(upstream_tls: &UpstreamTls) -> Result<Arc<ClientConfig>, anyhow::Error> {
+    let key = UpstreamTlsKey::new(upstream_tls);
+    if let Some(client_config) = GLOBAL_UPSTREAM_CLIENT_CONFIGS.get(&key) {
+        return Ok(client_config.clone());
+    }
+    let client_config = Arc::new(new_client_config(upstream_tls)?);
+    insert_upstream_cache(&GLOBAL_UPSTREAM_CLIENT_CONFIGS, key, client_config.clone());
+    Ok(client_config)
+}
+// The endpoint of the tcp route is like "host:port" or "[::1]:port".
+fn get_endpoint_host(endpoint: &str) -> &str {
+    let host = endpoint.rsplit_once(':').map_or(endpoint, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+// Originate the tls to the endpoint of the tcp route,the host of the endpoint is sent in the SNI
+// when the server_name is not configured.
+pub async fn connect_tls(
+    stream: TcpStream,
+    endpoint: &str,
+    upstream_tls: &UpstreamTls,
+) -> Result<TlsStream<TcpStream>, anyhow::Error> {
+    let client_config = get_client_config(upstream_tls)?;
+    let server_name = upstream_tls
+        .server_name
+        .as_deref()
+        .unwrap_or(get_endpoint_host(endpoint));
+    let server_name = ServerName::try_from(server_name)
+        .map_err(|err| anyhow!("The server_name {} is invalid,{}", server_name, err))?;
+    TlsConnector::from(client_config)
+        .connect(server_name, stream)
+        .await
+        .map_err(|err| anyhow!("Can not connect {} by tls,{}", endpoint, err))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::service_fn;
+    use hyper::{Body, Client, Request, Response};
+    use rustls::server::AllowAnyAuthenticatedClient;
+    use std::convert::Infallible;
+    use std::env;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    fn new_ca() -> rcgen::Certificate {
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+    fn new_leaf(server_name: &str) -> rcgen::Certificate {
+        rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![String::from(
+            server_name,
+        )]))
+        .unwrap()
+    }
+    fn write_temp_file(name: &str, content: &str) -> String {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, content).unwrap();
+        String::from(path.to_str().unwrap())
+    }
+    // Start a https server which only accepts the client certificates signed by the ca.
+    async fn start_mtls_server(ca: &rcgen::Certificate, server_name: &str) -> SocketAddr {
+        let leaf = new_leaf(server_name);
+        let certs = load_certs(&leaf.serialize_pem_with_signer(ca).unwrap()).unwrap();
+        let private_key = rustls::PrivateKey(leaf.serialize_private_key_der());
+        let mut root_store = RootCertStore::empty();
+        root_store
+            .add(&Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(root_store))
+            .with_single_cert(certs, private_key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(tls_stream) = acceptor.accept(stream).await {
+                        let service = service_fn(|_: Request<Body>| async {
+                            Ok::<_, Infallible>(Response::new(Body::from("upstream")))
+                        });
+                        let _ = hyper::server::conn::Http::new()
+                            .serve_connection(tls_stream, service)
+                            .await;
+                    }
+                });
+            }
+        });
+        addr
+    }
+    async fn request(upstream_tls: &UpstreamTls, addr: SocketAddr) -> Result<String, String> {
+        let connector = new_https_connector(upstream_tls).map_err(|err| err.to_string())?;
+        let client = Client::builder().build::<_, Body>(connector);
+        let uri = format!("https://{}/", addr).parse().unwrap();
+        let res = client.get(uri).await.map_err(|err| err.to_string())?;
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        Ok(String::from_utf8(body.to_vec()).unwrap())
+    }
+    #[tokio::test]
+    async fn test_upstream_mtls() {
+        let ca = new_ca();
+        let addr = start_mtls_server(&ca, "upstream.internal").await;
+        let ca_file = write_temp_file(
+            "silverwind_test_upstream_ca.pem",
+            &ca.serialize_pem().unwrap(),
+        );
+        let client = new_leaf("client.internal");
+        let cert_file = write_temp_file(
+            "silverwind_test_upstream_client.crt",
+            &client.serialize_pem_with_signer(&ca).unwrap(),
+        );
+        let key_file = write_temp_file(
+            "silverwind_test_upstream_client.key",
+            &client.serialize_private_key_pem(),
+        );
+        let upstream_tls = UpstreamTls {
+            ca_file: Some(ca_file.clone()),
+            cert_file: Some(cert_file.clone()),
+            key_file: Some(key_file.clone()),
+            server_name: Some(String::from("upstream.internal")),
+            ..Default::default()
+        };
+        assert_eq!(request(&upstream_tls, addr).await.unwrap(), "upstream");
+
+        let verify_upstream_tls = UpstreamTls {
+            server_name: None,
+            verify_hostname: Some(String::from("upstream.internal")),
+            ..upstream_tls.clone()
+        };
+        assert_eq!(
+            request(&verify_upstream_tls, addr).await.unwrap(),
+            "upstream"
+        );
+        // The ip address of the endpoint does not match the certificate.
+        let no_sni_upstream_tls = UpstreamTls {
+            server_name: None,
+            ..upstream_tls.clone()
+        };
+        assert_eq!(request(&no_sni_upstream_tls, addr).await.is_err(), true);
+        let no_client_cert_upstream_tls = UpstreamTls {
+            cert_file: None,
+            key_file: None,
+            ..upstream_tls.clone()
+        };
+        assert_eq!(
+            request(&no_client_cert_upstream_tls, addr).await.is_err(),
+            true
+        );
+        let webpki_upstream_tls = UpstreamTls {
+            ca_file: None,
+            ..upstream_tls.clone()
+        };
+        assert_eq!(request(&webpki_upstream_tls, addr).await.is_err(), true);
+        let insecure_upstream_tls = UpstreamTls {
+            ca_file: None,
+            server_name: None,
+            insecure_skip_verify: true,
+            ..upstream_tls
+        };
+        assert_eq!(
+            request(&insecure_upstream_tls, addr).await.unwrap(),
+            "upstream"
+        );
+        for file in [ca_file, cert_file, key_file] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+    #[test]
+    fn test_client_config_reloaded_with_file() {
+        let ca_file = write_temp_file(
+            "silverwind_test_reloaded_ca.pem",
+            &new_ca().serialize_pem().unwrap(),
+        );
+        let upstream_tls = UpstreamTls {
+            ca_file: Some(ca_file.clone()),
+            ..Default::default()
+        };
+        let client_config1 = get_client_config(&upstream_tls).unwrap();
+        let client_config2 = get_client_config(&upstream_tls).unwrap();
+        assert_eq!(Arc::ptr_eq(&client_config1, &client_config2), true);
+
+        let modified_time = SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&ca_file)
+            .unwrap()
+            .set_modified(modified_time)
+            .unwrap();
+        let client_config3 = get_client_config(&upstream_tls).unwrap();
+        assert_eq!(Arc::ptr_eq(&client_config1, &client_config3), false);
+        let cached_count = GLOBAL_UPSTREAM_CLIENT_CONFIGS
+            .iter()
+            .filter(|item| item.key().upstream_tls == upstream_tls)
+            .count();
+        assert_eq!(cached_count, 1);
+        fs::remove_file(ca_file).unwrap();
+    }
+    #[test]
+    fn test_new_client_config_error() {
+        let upstream_tls = UpstreamTls {
+            ca_file: Some(String::from("/not/exist/ca.pem")),
+            ..Default::default()
+        };
+        assert_eq!(new_client_config(&upstream_tls).is_err(), true);
+        let upstream_tls = UpstreamTls {
+            server_name: Some(String::from("invalid name")),
+            ..Default::default()
+        };
+        assert_eq!(new_https_connector(&upstream_tls).is_err(), true);
+    }
+}
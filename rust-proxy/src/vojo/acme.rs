@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const ACME_HTTP_CHALLENGE_PREFIX: &str = "/.well-known/acme-challenge/";
+pub const ACME_TLS_ALPN_PROTOCOL: &[u8] = b"acme-tls/1";
+
+fn default_renew_before_days() -> u64 {
+    30
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(tag = "type")]
+pub enum AcmeChallengeType {
+    // The token is served by the http listeners on the path /.well-known/acme-challenge/.
+    #[default]
+    Http01,
+    // The challenge certificate is served by the https listener itself when the client asks for
+    // the acme-tls/1 protocol.
+    TlsAlpn01,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AcmeConfig {
+    pub directory_url: String,
+    #[serde(default)]
+    pub contacts: Vec<String>,
+    pub domains: Vec<String>,
+    #[serde(default)]
+    pub challenge: AcmeChallengeType,
+    // The account key,the certificate and the key are stored in the directory.
+    pub storage_dir: String,
+    #[serde(default = "default_renew_before_days")]
+    pub renew_before_days: u64,
+    // The pem file of the CA which signs the certificate of the acme server,e.g. the minica of
+    // Pebble.
+    #[serde(default)]
+    pub ca_file: Option<String>,
+}
+impl AcmeConfig {
+    fn get_file(&self, name: &str) -> String {
+        Path::new(&self.storage_dir)
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+    fn get_domain_name(&self) -> String {
+        self.domains
+            .first()
+            .cloned()
+            .unwrap_or(String::from("default"))
+    }
+    pub fn get_account_key_file(&self) -> String {
+        self.get_file("account.key")
+    }
+    pub fn get_cert_file(&self) -> String {
+        self.get_file(format!("{}.crt", self.get_domain_name()).as_str())
+    }
+    pub fn get_key_file(&self) -> String {
+        self.get_file(format!("{}.key", self.get_domain_name()).as_str())
+    }
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.domains.is_empty() {
+            return Err(anyhow!(
+                "The domains of the acme config could not be empty!"
+            ));
+        }
+        // The wildcard domain could only be validated by the dns challenge.
+        if self.domains.iter().any(|domain| domain.starts_with("*.")) {
+            return Err(anyhow!(
+                "The wildcard domain is not supported by the acme config!"
+            ));
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acme_config() {
+        let acme_config: AcmeConfig = serde_yaml::from_str(
+            r#"
+directory_url: https://localhost:14000/dir
+domains:
+- www.example.com
+- api.example.com
+storage_dir: /tmp/acme
+"#,
+        )
+        .unwrap();
+        assert_eq!(acme_config.challenge, AcmeChallengeType::Http01);
+        assert_eq!(acme_config.renew_before_days, 30);
+        assert_eq!(acme_config.get_cert_file(), "/tmp/acme/www.example.com.crt");
+        assert_eq!(acme_config.get_key_file(), "/tmp/acme/www.example.com.key");
+        assert_eq!(acme_config.validate().is_ok(), true);
+
+        let mut acme_config = acme_config;
+        acme_config.challenge = AcmeChallengeType::TlsAlpn01;
+        acme_config.domains = vec![String::from("*.example.com")];
+        assert_eq!(acme_config.validate().is_err(), true);
+        acme_config.domains = vec![];
+        assert_eq!(acme_config.validate().is_err(), true);
+    }
+}
@@ -1,11 +1,14 @@
-use ipnet::Ipv4Net;
-use iprange::IpRange;
-use serde::{Deserialize, Serialize};
-use std::net::Ipv4Addr;
+use crate::configuration_service::app_config_service::GLOBAL_IP_SETS;
+use crate::vojo::geoip::{lookup_asn, lookup_country, GeoIpConfig};
+use crate::vojo::identity::Identity;
+use crate::vojo::ip_matcher::{ip_matches, parse_ip, validate_ip_value};
+use crate::vojo::ip_set::IpSet;
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct AllowDenyObject {
     pub limit_type: AllowType,
+    #[serde(default, deserialize_with = "deserialize_allow_deny_value")]
     pub value: Option<String>,
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
@@ -23,7 +26,120 @@ pub enum AllowResult {
     DENY,
     NOTMAPPING,
 }
+const CONSUMER_PREFIX: &str = "consumer:";
+const GROUP_PREFIX: &str = "group:";
+const IP_SET_PREFIX: &str = "ip_set:";
+const COUNTRY_PREFIX: &str = "country:";
+const ASN_PREFIX: &str = "asn:";
+fn deserialize_allow_deny_value<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value_option = Option::<String>::deserialize(deserializer)?;
+    if let Some(value) = &value_option {
+        validate_allow_deny_value(value.as_str()).map_err(serde::de::Error::custom)?;
+    }
+    Ok(value_option)
+}
+fn validate_allow_deny_value(value: &str) -> Result<(), anyhow::Error> {
+    if let Some(asn) = value.strip_prefix(ASN_PREFIX) {
+        asn.parse::<u32>()
+            .map_err(|_| anyhow!("Invalid asn '{}'!", asn))?;
+        return Ok(());
+    }
+    for prefix in [CONSUMER_PREFIX, GROUP_PREFIX, IP_SET_PREFIX, COUNTRY_PREFIX] {
+        if value.starts_with(prefix) {
+            return Ok(());
+        }
+    }
+    validate_ip_value(value)
+}
+fn is_ip_mapped(value: &str, client_ip: &str) -> Result<bool, anyhow::Error> {
+    if let Some(name) = value.strip_prefix(IP_SET_PREFIX) {
+        let ip_set_matcher = GLOBAL_IP_SETS
+            .get(name)
+            .ok_or(anyhow!("Can not find the ip set {}!", name))?;
+        return Ok(ip_set_matcher.contains(&parse_ip(client_ip)?));
+    }
+    if let Some(country) = value.strip_prefix(COUNTRY_PREFIX) {
+        let country_option = lookup_country(parse_ip(client_ip)?)?;
+        return Ok(country_option.is_some_and(|item| item.eq_ignore_ascii_case(country)));
+    }
+    if let Some(asn) = value.strip_prefix(ASN_PREFIX) {
+        let asn = asn.parse::<u32>().map_err(|err| anyhow!(err.to_string()))?;
+        return Ok(lookup_asn(parse_ip(client_ip)?)? == Some(asn));
+    }
+    ip_matches(value, client_ip)
+}
 impl AllowDenyObject {
+    // The ip set and the geoip database used by the rule are resolved on load,so that the
+    // missing one is not found by every request.
+    pub fn validate_references(
+        &self,
+        ip_sets: &[IpSet],
+        geoip: Option<&GeoIpConfig>,
+    ) -> Result<(), anyhow::Error> {
+        let value = match &self.value {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        if let Some(name) = value.strip_prefix(IP_SET_PREFIX) {
+            if !ip_sets.iter().any(|ip_set| ip_set.name == name) {
+                return Err(anyhow!("Can not find the ip set {}!", name));
+            }
+        }
+        if value.starts_with(COUNTRY_PREFIX)
+            && geoip
+                .and_then(|item| item.country_database.as_ref())
+                .is_none()
+        {
+            return Err(anyhow!(
+                "The country database of the geoip is not configured for the rule {}!",
+                value
+            ));
+        }
+        if value.starts_with(ASN_PREFIX)
+            && geoip.and_then(|item| item.asn_database.as_ref()).is_none()
+        {
+            return Err(anyhow!(
+                "The asn database of the geoip is not configured for the rule {}!",
+                value
+            ));
+        }
+        Ok(())
+    }
+    pub fn is_identity_rule(&self) -> bool {
+        self.value.as_ref().is_some_and(|value| {
+            value.starts_with(CONSUMER_PREFIX) || value.starts_with(GROUP_PREFIX)
+        })
+    }
+    pub fn is_identity_allow(
+        &self,
+        identity_option: Option<&Identity>,
+    ) -> Result<AllowResult, anyhow::Error> {
+        let value = self.value.clone().ok_or(anyhow!(
+            "the value could not be none for the identity rule!"
+        ))?;
+        let value_mapped_identity = match identity_option {
+            Some(identity) => {
+                if let Some(consumer_id) = value.strip_prefix(CONSUMER_PREFIX) {
+                    identity.consumer_id == consumer_id
+                } else if let Some(group) = value.strip_prefix(GROUP_PREFIX) {
+                    identity.in_group(group)
+                } else {
+                    false
+                }
+            }
+            None => false,
+        };
+        if value_mapped_identity && self.limit_type == AllowType::ALLOW {
+            return Ok(AllowResult::ALLOW);
+        }
+        if value_mapped_identity && self.limit_type == AllowType::DENY {
+            return Ok(AllowResult::DENY);
+        }
+        Ok(AllowResult::NOTMAPPING)
+    }
     pub fn is_allow(&self, client_ip: String) -> Result<AllowResult, anyhow::Error> {
         if self.limit_type == AllowType::ALLOWALL {
             return Ok(AllowResult::ALLOW);
@@ -37,25 +153,7 @@ impl AllowDenyObject {
             ));
         }
         let config_ip = self.value.clone().unwrap();
-        let value_mapped_ip;
-        if config_ip.contains("/") {
-            let ip_range: IpRange<Ipv4Net> = [config_ip.clone()]
-                .iter()
-                .map(|s| s.parse().unwrap())
-                .collect();
-            let source_ip = client_ip.clone().parse::<Ipv4Addr>().unwrap();
-            if ip_range.contains(&source_ip) {
-                value_mapped_ip = true;
-            } else {
-                value_mapped_ip = false;
-            }
-        } else {
-            if self.value.clone().unwrap() == client_ip {
-                value_mapped_ip = true;
-            } else {
-                value_mapped_ip = false;
-            }
-        }
+        let value_mapped_ip = is_ip_mapped(config_ip.as_str(), client_ip.as_str())?;
         if value_mapped_ip && self.limit_type == AllowType::ALLOW {
             return Ok(AllowResult::ALLOW);
         }
@@ -69,6 +167,11 @@ impl AllowDenyObject {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::configuration_service::app_config_service::GLOBAL_GEOIP_DATABASES;
+    use crate::vojo::geoip::tests::{build_asn_mmdb, build_country_mmdb};
+    use crate::vojo::geoip::{GeoIpDatabase, ASN_DATABASE, COUNTRY_DATABASE};
+    use crate::vojo::ip_set::{IpSet, IpSetMatcher};
+    use std::sync::Arc;
 
     #[test]
     fn test_is_allow_allow_all() {
@@ -159,4 +262,109 @@ mod tests {
         assert_eq!(result.is_ok(), true);
         assert_eq!(result.unwrap(), AllowResult::NOTMAPPING);
     }
+    #[test]
+    fn test_is_identity_allow_consumer() {
+        let allow_object = AllowDenyObject {
+            limit_type: AllowType::DENY,
+            value: Some(String::from("consumer:consumer1")),
+        };
+        assert_eq!(allow_object.is_identity_rule(), true);
+        let identity = Identity::new(String::from("consumer1"));
+        let result = allow_object.is_identity_allow(Some(&identity));
+        assert_eq!(result.unwrap(), AllowResult::DENY);
+        let result2 = allow_object.is_identity_allow(None);
+        assert_eq!(result2.unwrap(), AllowResult::NOTMAPPING);
+    }
+    #[test]
+    fn test_is_identity_allow_group() {
+        let allow_object = AllowDenyObject {
+            limit_type: AllowType::ALLOW,
+            value: Some(String::from("group:admin")),
+        };
+        let mut identity = Identity::new(String::from("consumer1"));
+        let result1 = allow_object.is_identity_allow(Some(&identity));
+        assert_eq!(result1.unwrap(), AllowResult::NOTMAPPING);
+        identity.groups.push(String::from("admin"));
+        let result2 = allow_object.is_identity_allow(Some(&identity));
+        assert_eq!(result2.unwrap(), AllowResult::ALLOW);
+    }
+    #[test]
+    fn test_is_allow_deny_ipv6_range() {
+        let allow_object = AllowDenyObject {
+            limit_type: AllowType::DENY,
+            value: Some(String::from("2001:db8::/32")),
+        };
+        let result1 = allow_object.is_allow(String::from("2001:db8::1"));
+        assert_eq!(result1.unwrap(), AllowResult::DENY);
+        let result2 = allow_object.is_allow(String::from("192.168.0.1"));
+        assert_eq!(result2.unwrap(), AllowResult::NOTMAPPING);
+    }
+    #[test]
+    fn test_allow_deny_object_validate_when_loading() {
+        let res1: Result<AllowDenyObject, _> =
+            serde_yaml::from_str("limit_type: ALLOW\nvalue: 192.168.0.1/40");
+        assert_eq!(res1.is_err(), true);
+        let res2: Result<AllowDenyObject, _> =
+            serde_yaml::from_str("limit_type: DENY\nvalue: consumer:consumer1");
+        assert_eq!(res2.is_ok(), true);
+        let res3: Result<AllowDenyObject, _> = serde_yaml::from_str("limit_type: ALLOWALL");
+        assert_eq!(res3.unwrap().value, None);
+    }
+    #[test]
+    fn test_is_allow_ip_set() {
+        let ip_set = IpSet {
+            name: String::from("test_office"),
+            cidrs: vec![String::from("10.0.0.0/8"), String::from("2001:db8::/32")],
+            file: None,
+        };
+        GLOBAL_IP_SETS.insert(
+            String::from("test_office"),
+            Arc::new(IpSetMatcher::new(ip_set).unwrap()),
+        );
+        let allow_object = AllowDenyObject {
+            limit_type: AllowType::ALLOW,
+            value: Some(String::from("ip_set:test_office")),
+        };
+        let result1 = allow_object.is_allow(String::from("2001:db8::1"));
+        assert_eq!(result1.unwrap(), AllowResult::ALLOW);
+        let result2 = allow_object.is_allow(String::from("192.168.0.1"));
+        assert_eq!(result2.unwrap(), AllowResult::NOTMAPPING);
+        let not_exist_object = AllowDenyObject {
+            limit_type: AllowType::ALLOW,
+            value: Some(String::from("ip_set:not_exist")),
+        };
+        assert_eq!(
+            not_exist_object.is_allow(String::from("10.0.0.1")).is_err(),
+            true
+        );
+    }
+    #[test]
+    fn test_is_allow_country_and_asn() {
+        let path = std::env::temp_dir().join("silverwind_test_allow_country.mmdb");
+        std::fs::write(&path, build_country_mmdb("AU")).unwrap();
+        let database = GeoIpDatabase::open(path.to_str().unwrap()).unwrap();
+        GLOBAL_GEOIP_DATABASES.insert(String::from(COUNTRY_DATABASE), Arc::new(database));
+        let path = std::env::temp_dir().join("silverwind_test_allow_asn.mmdb");
+        std::fs::write(&path, build_asn_mmdb(13335)).unwrap();
+        let database = GeoIpDatabase::open(path.to_str().unwrap()).unwrap();
+        GLOBAL_GEOIP_DATABASES.insert(String::from(ASN_DATABASE), Arc::new(database));
+
+        let country_object = AllowDenyObject {
+            limit_type: AllowType::DENY,
+            value: Some(String::from("country:au")),
+        };
+        let result1 = country_object.is_allow(String::from("1.2.3.4"));
+        assert_eq!(result1.unwrap(), AllowResult::DENY);
+        let result2 = country_object.is_allow(String::from("200.1.1.1"));
+        assert_eq!(result2.unwrap(), AllowResult::NOTMAPPING);
+        let asn_object = AllowDenyObject {
+            limit_type: AllowType::ALLOW,
+            value: Some(String::from("asn:13335")),
+        };
+        let result3 = asn_object.is_allow(String::from("1.2.3.4"));
+        assert_eq!(result3.unwrap(), AllowResult::ALLOW);
+        let res: Result<AllowDenyObject, _> =
+            serde_yaml::from_str("limit_type: DENY\nvalue: asn:cloudflare");
+        assert_eq!(res.is_err(), true);
+    }
 }
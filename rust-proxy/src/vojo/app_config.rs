@@ -1,11 +1,28 @@
 use super::allow_deny_ip::AllowResult;
+use crate::constants::constants::{DEFAULT_UDP_IDLE_TIMEOUT_MILLIS, DEFAULT_UDP_MAX_SESSIONS};
+use crate::vojo::acme::AcmeConfig;
 use crate::vojo::allow_deny_ip::AllowDenyObject;
-use crate::vojo::authentication::AuthenticationStrategy;
-use crate::vojo::rate_limit::RatelimitStrategy;
+use crate::vojo::authentication::{ApiKeyStore, AuthenticationStrategy};
+use crate::vojo::client_ip::ClientIpConfig;
+use crate::vojo::geoip::GeoIpConfig;
+use crate::vojo::in_flight_limit::InFlightLimit;
+use crate::vojo::ip_matcher::{deserialize_ip_value_list, ip_value_contains};
+use crate::vojo::ip_set::IpSet;
+use crate::vojo::rate_limit::{RatelimitQuota, RatelimitStrategy};
+use crate::vojo::request_context::RequestContext;
+use crate::vojo::request_limit::RequestLimits;
 use crate::vojo::route::LoadbalancerStrategy;
+use crate::vojo::tls_policy::TlsPolicy;
+use crate::vojo::upstream_tls::UpstreamTls;
+use crate::vojo::waf::WafPolicy;
 use http::HeaderMap;
 use http::HeaderValue;
 use regex::Regex;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::Deserializer;
+use std::fmt;
+use std::net::IpAddr;
 use uuid::Uuid;
 
 use serde::{Deserialize, Serialize};
@@ -14,6 +31,80 @@ pub struct Matcher {
     pub prefix: String,
     pub prefix_rewrite: String,
 }
+// The matcher of the tcp route,the empty list matches all the connections.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct TcpMatcher {
+    // The destination port is the port of the local address,it is the original port of the
+    // connection redirected by the TPROXY.
+    #[serde(default)]
+    pub destination_ports: Vec<u16>,
+    // The addresses or the cidrs of the clients.
+    #[serde(default, deserialize_with = "deserialize_ip_value_list")]
+    pub source_ips: Vec<String>,
+}
+impl TcpMatcher {
+    pub fn is_matched(
+        &self,
+        destination_port: u16,
+        source_ip: &IpAddr,
+    ) -> Result<bool, anyhow::Error> {
+        if !self.destination_ports.is_empty() && !self.destination_ports.contains(&destination_port)
+        {
+            return Ok(false);
+        }
+        if self.source_ips.is_empty() {
+            return Ok(true);
+        }
+        for source_ip_value in self.source_ips.iter() {
+            if ip_value_contains(source_ip_value, source_ip)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseTemplate {
+    #[serde(
+        default = "default_content_type",
+        deserialize_with = "deserialize_content_type"
+    )]
+    pub content_type: String,
+    pub body: String,
+}
+fn default_content_type() -> String {
+    String::from("application/json")
+}
+fn deserialize_content_type<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let content_type = String::deserialize(deserializer)?;
+    HeaderValue::from_str(&content_type).map_err(|err| {
+        serde::de::Error::custom(format!(
+            "The content_type {} is not a valid header value,{}",
+            content_type, err
+        ))
+    })?;
+    Ok(content_type)
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ResponseTemplates {
+    pub forbidden: Option<ResponseTemplate>,
+    pub unauthorized: Option<ResponseTemplate>,
+    pub too_many_requests: Option<ResponseTemplate>,
+    pub service_unavailable: Option<ResponseTemplate>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessOutcome {
+    Allowed,
+    IpDenied,
+    Unauthenticated(String),
+    Forbidden,
+    RateLimited(RatelimitQuota),
+    Overloaded,
+    WafBlocked(String),
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Route {
     #[serde(default = "new_uuid")]
@@ -23,6 +114,22 @@ pub struct Route {
     pub allow_deny_list: Option<Vec<AllowDenyObject>>,
     pub authentication: Option<Box<dyn AuthenticationStrategy>>,
     pub ratelimit: Option<Box<dyn RatelimitStrategy>>,
+    #[serde(default)]
+    pub response_templates: Option<ResponseTemplates>,
+    #[serde(default)]
+    pub in_flight_limit: Option<InFlightLimit>,
+    #[serde(default)]
+    pub waf: Option<WafPolicy>,
+    #[serde(default)]
+    pub max_body_size: Option<u64>,
+    // The regex matched with the SNI name of the https listener.
+    #[serde(default)]
+    pub server_name: Option<String>,
+    // The tls options of the https endpoints,the webpki roots are trusted when it is none.
+    #[serde(default)]
+    pub upstream_tls: Option<UpstreamTls>,
+    #[serde(default)]
+    pub tcp_matcher: Option<TcpMatcher>,
     pub route_cluster: Box<dyn LoadbalancerStrategy>,
 }
 pub fn new_uuid() -> String {
@@ -69,31 +176,102 @@ impl Route {
         }
         Ok(true)
     }
-    pub fn is_allowed(
+    pub fn is_server_name_matched(&self, server_name: Option<&str>) -> Result<bool, anyhow::Error> {
+        let server_name_regex = match &self.server_name {
+            Some(server_name_regex) => server_name_regex,
+            None => return Ok(true),
+        };
+        match server_name {
+            Some(server_name) => Ok(Regex::new(server_name_regex.as_str())?.is_match(server_name)),
+            None => Ok(false),
+        }
+    }
+    // The tcp route is matched by the SNI name,the destination port and the source address.
+    pub fn is_tcp_matched(
         &self,
-        ip: String,
-        headers_option: Option<HeaderMap<HeaderValue>>,
+        server_name: Option<&str>,
+        destination_port: u16,
+        source_ip: &IpAddr,
     ) -> Result<bool, anyhow::Error> {
-        let mut is_allowed = ip_is_allowed(self.allow_deny_list.clone(), ip.clone())?;
-        if !is_allowed {
-            return Ok(is_allowed);
+        if !self.is_server_name_matched(server_name)? {
+            return Ok(false);
         }
-        if let (Some(header_map), Some(mut authentication_strategy)) =
-            (headers_option.clone(), self.authentication.clone())
-        {
-            is_allowed = authentication_strategy.check_authentication(header_map)?;
-            if !is_allowed {
-                return Ok(is_allowed);
+        match &self.tcp_matcher {
+            Some(tcp_matcher) => tcp_matcher.is_matched(destination_port, source_ip),
+            None => Ok(true),
+        }
+    }
+    pub async fn check_access(
+        &self,
+        request_context: &mut RequestContext,
+    ) -> Result<AccessOutcome, anyhow::Error> {
+        let ip_allowed = ip_is_allowed(
+            self.allow_deny_list.clone(),
+            request_context.remote_ip.clone(),
+        )?;
+        if !ip_allowed {
+            return Ok(AccessOutcome::IpDenied);
+        }
+        if let Some(mut authentication_strategy) = self.authentication.clone() {
+            let identity_option = authentication_strategy.check_authentication(request_context)?;
+            if identity_option.is_none() {
+                return Ok(AccessOutcome::Unauthenticated(
+                    authentication_strategy.get_challenge(),
+                ));
             }
+            request_context.identity = identity_option;
         }
-        if let (Some(header_map), Some(mut ratelimit_strategy)) =
-            (headers_option, self.ratelimit.clone())
-        {
-            is_allowed = !ratelimit_strategy.should_limit(header_map, ip)?;
+        if !identity_is_allowed(self.allow_deny_list.clone(), request_context)? {
+            return Ok(AccessOutcome::Forbidden);
+        }
+        let identity_ratelimit = request_context
+            .identity
+            .as_ref()
+            .and_then(|identity| identity.ratelimit.clone());
+        let mut ratelimit_strategies: Vec<Box<dyn RatelimitStrategy>> = self
+            .ratelimit
+            .clone()
+            .into_iter()
+            .chain(identity_ratelimit)
+            .collect();
+        for index in 0..ratelimit_strategies.len() {
+            if let Some(quota) = ratelimit_strategies[index]
+                .check_limit_async(request_context)
+                .await?
+            {
+                // The route ratelimit is refunded when the identity ratelimit rejects the request.
+                if quota.limited {
+                    for ratelimit_strategy in ratelimit_strategies[..index].iter_mut() {
+                        ratelimit_strategy.refund_async(request_context).await?;
+                    }
+                    return Ok(AccessOutcome::RateLimited(quota));
+                }
+                request_context.ratelimit_quota =
+                    Some(quota.stricter(request_context.ratelimit_quota.take()));
+            }
         }
-        Ok(is_allowed)
+        Ok(AccessOutcome::Allowed)
     }
 }
+pub fn identity_is_allowed(
+    allow_deny_list: Option<Vec<AllowDenyObject>>,
+    request_context: &RequestContext,
+) -> Result<bool, anyhow::Error> {
+    if allow_deny_list.is_none() {
+        return Ok(true);
+    }
+    for item in allow_deny_list.unwrap().iter() {
+        if !item.is_identity_rule() {
+            continue;
+        }
+        match item.is_identity_allow(request_context.identity.as_ref())? {
+            AllowResult::ALLOW => return Ok(true),
+            AllowResult::DENY => return Ok(false),
+            AllowResult::NOTMAPPING => continue,
+        }
+    }
+    Ok(true)
+}
 pub fn ip_is_allowed(
     allow_deny_list: Option<Vec<AllowDenyObject>>,
     ip: String,
@@ -105,6 +283,9 @@ pub fn ip_is_allowed(
     let iter = allow_deny_list.iter();
 
     for item in iter {
+        if item.is_identity_rule() {
+            continue;
+        }
         let is_allow = item.is_allow(ip.clone());
         match is_allow {
             Ok(AllowResult::ALLOW) => {
@@ -114,7 +295,7 @@ pub fn ip_is_allowed(
                 return Ok(false);
             }
             Ok(AllowResult::NOTMAPPING) => {
-                break;
+                continue;
             }
             Err(err) => {
                 return Err(anyhow!(err.to_string()));
@@ -130,14 +311,128 @@ pub enum ServiceType {
     HTTP,
     HTTPS,
     TCP,
+    UDP,
 }
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ServiceConfig {
     pub server_type: ServiceType,
     pub cert_str: Option<String>,
     pub key_str: Option<String>,
+    // The cert_file and the key_file take precedence over the inline pem and are reloaded when
+    // the files are changed.
+    #[serde(default)]
+    pub cert_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+    // The password of the encrypted PKCS#8 key.
+    #[serde(default)]
+    pub key_password: Option<String>,
+    // The DER encoded OCSP response of the certificate,it is stapled in the handshake.
+    #[serde(default)]
+    pub ocsp_file: Option<String>,
+    #[serde(default)]
+    pub certificates: Vec<CertificateConfig>,
+    #[serde(default)]
+    pub client_ip: Option<ClientIpConfig>,
+    #[serde(default)]
+    pub proxy_protocol: Option<ProxyProtocolConfig>,
+    // The tls mode of the tcp listener,the bytes are copied as they are when it is none.
+    #[serde(default)]
+    pub tcp_tls: Option<TcpTlsMode>,
+    #[serde(default)]
+    pub udp: Option<UdpConfig>,
+    #[serde(default)]
+    pub request_limits: Option<RequestLimits>,
+    #[serde(default)]
+    pub tls_policy: Option<TlsPolicy>,
+    // The certificates of the domains are issued and renewed by the acme server.
+    #[serde(default)]
+    pub acme: Option<AcmeConfig>,
     pub routes: Vec<Route>,
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct CertificateConfig {
+    // The server name could be the exact name or the wildcard name like "*.example.com".
+    pub server_names: Vec<String>,
+    #[serde(default)]
+    pub cert_str: Option<String>,
+    #[serde(default)]
+    pub key_str: Option<String>,
+    #[serde(default)]
+    pub cert_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+    #[serde(default)]
+    pub key_password: Option<String>,
+    #[serde(default)]
+    pub ocsp_file: Option<String>,
+    #[serde(default)]
+    pub default: bool,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TcpTlsMode {
+    // The tls is terminated by the certificates of the service and the plaintext is forwarded.
+    Terminate,
+    // The SNI name is peeked from the ClientHello and the tls bytes are forwarded as they are.
+    Passthrough,
+}
+fn default_udp_idle_timeout_ms() -> u64 {
+    DEFAULT_UDP_IDLE_TIMEOUT_MILLIS
+}
+fn default_udp_max_sessions() -> usize {
+    DEFAULT_UDP_MAX_SESSIONS
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UdpConfig {
+    // The session of the client is closed when no packet is transferred in the timeout.
+    #[serde(default = "default_udp_idle_timeout_ms")]
+    pub idle_timeout_ms: u64,
+    // The packets of the new clients are dropped when the sessions reach the limit.
+    #[serde(default = "default_udp_max_sessions")]
+    pub max_sessions: usize,
+}
+impl Default for UdpConfig {
+    fn default() -> Self {
+        UdpConfig {
+            idle_timeout_ms: DEFAULT_UDP_IDLE_TIMEOUT_MILLIS,
+            max_sessions: DEFAULT_UDP_MAX_SESSIONS,
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ProxyProtocolConfig {
+    #[serde(default)]
+    pub accept: bool,
+    #[serde(default, deserialize_with = "deserialize_ip_value_list")]
+    pub trusted_sources: Vec<String>,
+    #[serde(default)]
+    pub send: Option<ProxyProtocolVersion>,
+}
+impl ProxyProtocolConfig {
+    // The header is only read from the trusted load balancers,the other peers could forge the
+    // client address.
+    pub fn is_accepted_from(&self, peer_ip: &IpAddr) -> bool {
+        let peer_ip = peer_ip.to_canonical();
+        self.accept
+            && self
+                .trusted_sources
+                .iter()
+                .any(|trusted_source| ip_value_contains(trusted_source, &peer_ip).unwrap_or(false))
+    }
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.accept && self.trusted_sources.is_empty() {
+            return Err(anyhow!(
+                "The trusted_sources of the proxy_protocol could not be empty when the accept is true!"
+            ));
+        }
+        Ok(())
+    }
+}
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ApiService {
     pub listen_port: i32,
@@ -157,13 +452,136 @@ pub struct StaticConifg {
 pub struct AppConfig {
     pub static_config: StaticConifg,
     pub api_service_config: Vec<ApiService>,
+    #[serde(default)]
+    pub api_key_stores: Vec<ApiKeyStore>,
+    #[serde(default)]
+    pub ip_sets: Vec<IpSet>,
+    #[serde(default)]
+    pub geoip: Option<GeoIpConfig>,
+}
+/**
+*The document accepted by the config file and the POST /appConfig,which could be
+ the plain list of the api services or an object with the shared sections.
+*/
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct AppConfigDocument {
+    pub api_service_config: Vec<ApiService>,
+    pub api_key_stores: Option<Vec<ApiKeyStore>>,
+    pub ip_sets: Option<Vec<IpSet>>,
+    pub geoip: Option<GeoIpConfig>,
+}
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AppConfigObject {
+    api_service_config: Vec<ApiService>,
+    api_key_stores: Option<Vec<ApiKeyStore>>,
+    ip_sets: Option<Vec<IpSet>>,
+    geoip: Option<GeoIpConfig>,
+}
+impl<'de> Deserialize<'de> for AppConfigDocument {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DocumentVisitor;
+        impl<'de> Visitor<'de> for DocumentVisitor {
+            type Value = AppConfigDocument;
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of api services or an app config object")
+            }
+            fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let api_services = Vec::<ApiService>::deserialize(SeqAccessDeserializer::new(seq))?;
+                Ok(AppConfigDocument {
+                    api_service_config: api_services,
+                    api_key_stores: None,
+                    ip_sets: None,
+                    geoip: None,
+                })
+            }
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let object = AppConfigObject::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(AppConfigDocument {
+                    api_service_config: object.api_service_config,
+                    api_key_stores: object.api_key_stores,
+                    ip_sets: object.ip_sets,
+                    geoip: object.geoip,
+                })
+            }
+        }
+        deserializer.deserialize_any(DocumentVisitor)
+    }
+}
+impl AppConfigDocument {
+    // The references to the shared sections are resolved on load,the section missing in the
+    // document is taken from the current app config.
+    pub fn validate(&self, app_config: &AppConfig) -> Result<(), anyhow::Error> {
+        let api_key_stores = self
+            .api_key_stores
+            .as_ref()
+            .unwrap_or(&app_config.api_key_stores);
+        let ip_sets = self.ip_sets.as_ref().unwrap_or(&app_config.ip_sets);
+        let geoip = self.geoip.as_ref().or(app_config.geoip.as_ref());
+        for api_service in self.api_service_config.iter() {
+            for route in api_service.service_config.routes.iter() {
+                for allow_deny_object in route.allow_deny_list.iter().flatten() {
+                    allow_deny_object
+                        .validate_references(ip_sets, geoip)
+                        .map_err(|err| {
+                            anyhow!(
+                                "The allow deny list of the port {} is invalid,{}",
+                                api_service.listen_port,
+                                err
+                            )
+                        })?;
+                }
+                let store_names = route
+                    .authentication
+                    .as_ref()
+                    .map(|authentication| authentication.get_store_names())
+                    .unwrap_or_default();
+                for store_name in store_names {
+                    if !api_key_stores.iter().any(|store| store.name == store_name) {
+                        return Err(anyhow!(
+                            "Can not find the api key store {} of the port {}!",
+                            store_name,
+                            api_service.listen_port
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn apply_to(self, app_config: &mut AppConfig) {
+        app_config.api_service_config = self.api_service_config;
+        if let Some(api_key_stores) = self.api_key_stores {
+            app_config.api_key_stores = api_key_stores;
+        }
+        if let Some(ip_sets) = self.ip_sets {
+            app_config.ip_sets = ip_sets;
+        }
+        if self.geoip.is_some() {
+            app_config.geoip = self.geoip;
+        }
+    }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vojo::allow_deny_ip::AllowType;
+    use crate::vojo::authentication::hash_api_key;
+    use crate::vojo::authentication::ApiKey;
     use crate::vojo::authentication::ApiKeyAuth;
+    use crate::vojo::authentication::ApiKeyStoreAuth;
     use crate::vojo::authentication::AuthenticationStrategy;
     use crate::vojo::authentication::BasicAuth;
+    use crate::vojo::authentication::KeyLocation;
     use crate::vojo::rate_limit::*;
     use crate::vojo::route::BaseRoute;
     use crate::vojo::route::HeaderBasedRoute;
@@ -199,6 +617,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("/"),
                 prefix_rewrite: String::from("ssss"),
@@ -259,6 +684,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -271,7 +703,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -301,6 +745,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -313,7 +764,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -344,6 +807,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -356,8 +826,20 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
 
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -382,6 +864,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -395,7 +884,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -424,6 +925,13 @@ mod tests {
             allow_deny_list: None,
             authentication: Some(basic_auth),
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -436,7 +944,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -448,6 +968,7 @@ mod tests {
         let api_key_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyAuth {
             key: String::from("api_key"),
             value: String::from("test"),
+            consumer_id: None,
         });
         let route = Route {
             host_name: None,
@@ -464,6 +985,13 @@ mod tests {
             }),
             allow_deny_list: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             authentication: Some(api_key_auth),
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
@@ -477,7 +1005,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -495,6 +1035,8 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let ratelimit: Box<dyn RatelimitStrategy> = Box::new(token_bucket_ratelimit);
@@ -514,6 +1056,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: Some(ratelimit),
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -526,7 +1075,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -543,6 +1104,8 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let ratelimit: Box<dyn RatelimitStrategy> = Box::new(fixed_window_ratelimit);
         let route = Route {
@@ -561,6 +1124,13 @@ mod tests {
             allow_deny_list: None,
             authentication: None,
             ratelimit: Some(ratelimit),
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -573,7 +1143,19 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
@@ -603,6 +1185,13 @@ mod tests {
             allow_deny_list: Some(vec![allow_object]),
             authentication: None,
             ratelimit: None,
+            response_templates: None,
+            in_flight_limit: None,
+            waf: None,
+            max_body_size: None,
+            server_name: None,
+            upstream_tls: None,
+            tcp_matcher: None,
             matcher: Some(Matcher {
                 prefix: String::from("ss"),
                 prefix_rewrite: String::from("ssss"),
@@ -615,11 +1204,373 @@ mod tests {
                 routes: vec![route],
                 server_type: Default::default(),
                 cert_str: Default::default(),
+                cert_file: None,
+                key_file: None,
+                key_password: None,
+                ocsp_file: None,
+                certificates: vec![],
                 key_str: Default::default(),
+                client_ip: None,
+                proxy_protocol: None,
+                tls_policy: None,
+                acme: None,
+                tcp_tls: None,
+                udp: None,
+                request_limits: None,
             },
         };
         let t = vec![api_service];
         let yaml = serde_yaml::to_string(&t).unwrap();
         println!("{}", yaml);
     }
+    #[test]
+    fn test_app_config_document_from_list() {
+        let req = r#"[
+            {
+              "listen_port": 4486,
+              "service_config": {
+                "server_type": "HTTP",
+                "routes": [
+                  {
+                    "matcher": {
+                      "prefix": "ss",
+                      "prefix_rewrite": "ssss"
+                    },
+                    "route_cluster": {
+                      "type": "RandomRoute",
+                      "routes": []
+                    }
+                  }
+                ]
+              }
+            }
+          ]"#;
+        let document: AppConfigDocument = serde_json::from_slice(req.as_bytes()).unwrap();
+        assert_eq!(document.api_service_config.len(), 1);
+        assert_eq!(document.api_key_stores.is_none(), true);
+    }
+    #[test]
+    fn test_app_config_document_from_object() {
+        let req = r#"
+api_key_stores:
+- name: partners
+  keys:
+  - name: partner1
+    key_hash: 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+api_service_config:
+- listen_port: 4486
+  service_config:
+    server_type: HTTP
+    routes:
+    - matcher:
+        prefix: /
+        prefix_rewrite: ssss
+      authentication:
+        type: ApiKeyStoreAuth
+        store_name: partners
+      route_cluster:
+        type: RandomRoute
+        routes: []
+"#;
+        let document: AppConfigDocument = serde_yaml::from_str(req).unwrap();
+        assert_eq!(document.api_service_config.len(), 1);
+        let mut app_config: AppConfig = Default::default();
+        assert_eq!(document.validate(&app_config).is_ok(), true);
+        document.apply_to(&mut app_config);
+        assert_eq!(app_config.api_key_stores.len(), 1);
+        assert_eq!(app_config.api_key_stores[0].keys[0].name, "partner1");
+    }
+    #[test]
+    fn test_app_config_document_validate_api_key_store() {
+        let req = r#"
+- listen_port: 4486
+  service_config:
+    server_type: HTTP
+    routes:
+    - matcher:
+        prefix: /
+        prefix_rewrite: ssss
+      authentication:
+        type: CompositeAuth
+        strategies:
+        - type: ApiKeyStoreAuth
+          store_name: partners
+      route_cluster:
+        type: RandomRoute
+        routes: []
+"#;
+        let document: AppConfigDocument = serde_yaml::from_str(req).unwrap();
+        let mut app_config: AppConfig = Default::default();
+        let result = document.validate(&app_config);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(result.unwrap_err().to_string().contains("partners"), true);
+        // The store kept in the current app config is used when the document has no stores.
+        app_config.api_key_stores = vec![ApiKeyStore {
+            name: String::from("partners"),
+            keys: vec![],
+        }];
+        assert_eq!(document.validate(&app_config).is_ok(), true);
+    }
+    #[test]
+    fn test_app_config_document_validate_allow_deny_list() {
+        let new_document = |value: &str| -> AppConfigDocument {
+            let req = format!(
+                r#"
+- listen_port: 4486
+  service_config:
+    server_type: HTTP
+    routes:
+    - matcher:
+        prefix: /
+        prefix_rewrite: ssss
+      allow_deny_list:
+      - limit_type: DENY
+        value: "{}"
+      route_cluster:
+        type: RandomRoute
+        routes: []
+"#,
+                value
+            );
+            serde_yaml::from_str(req.as_str()).unwrap()
+        };
+        let mut app_config: AppConfig = Default::default();
+        for value in ["ip_set:blocked", "country:CN", "asn:64512"] {
+            assert_eq!(new_document(value).validate(&app_config).is_err(), true);
+        }
+        app_config.ip_sets = vec![IpSet {
+            name: String::from("blocked"),
+            cidrs: vec![String::from("10.0.0.0/8")],
+            file: None,
+        }];
+        app_config.geoip = Some(GeoIpConfig {
+            country_database: Some(String::from("GeoLite2-Country.mmdb")),
+            asn_database: None,
+        });
+        assert_eq!(
+            new_document("ip_set:blocked").validate(&app_config).is_ok(),
+            true
+        );
+        assert_eq!(
+            new_document("country:CN").validate(&app_config).is_ok(),
+            true
+        );
+        assert_eq!(
+            new_document("asn:64512").validate(&app_config).is_err(),
+            true
+        );
+        assert_eq!(new_document("10.0.0.1").validate(&app_config).is_ok(), true);
+    }
+    #[test]
+    fn test_ip_is_allowed_multiple_rules() {
+        let allow_deny_list = Some(vec![
+            AllowDenyObject {
+                limit_type: AllowType::ALLOW,
+                value: Some(String::from("10.0.0.0/8")),
+            },
+            AllowDenyObject {
+                limit_type: AllowType::DENYALL,
+                value: None,
+            },
+        ]);
+        let result = ip_is_allowed(allow_deny_list.clone(), String::from("10.1.2.3"));
+        assert_eq!(result.unwrap(), true);
+        let result = ip_is_allowed(allow_deny_list, String::from("192.168.0.1"));
+        assert_eq!(result.unwrap(), false);
+
+        let allow_deny_list = Some(vec![
+            AllowDenyObject {
+                limit_type: AllowType::DENY,
+                value: Some(String::from("192.168.0.1")),
+            },
+            AllowDenyObject {
+                limit_type: AllowType::DENY,
+                value: Some(String::from("192.168.0.2")),
+            },
+        ]);
+        let result = ip_is_allowed(allow_deny_list.clone(), String::from("192.168.0.2"));
+        assert_eq!(result.unwrap(), false);
+        let result = ip_is_allowed(allow_deny_list, String::from("192.168.0.3"));
+        assert_eq!(result.unwrap(), true);
+    }
+    #[test]
+    fn test_udp_service_config() {
+        let service_config: ServiceConfig = serde_yaml::from_str(
+            r#"
+server_type: UDP
+udp:
+  max_sessions: 1000
+routes:
+- route_cluster:
+    type: PollRoute
+    routes: []
+"#,
+        )
+        .unwrap();
+        assert_eq!(service_config.server_type, ServiceType::UDP);
+        let udp_config = service_config.udp.unwrap();
+        assert_eq!(udp_config.idle_timeout_ms, DEFAULT_UDP_IDLE_TIMEOUT_MILLIS);
+        assert_eq!(udp_config.max_sessions, 1000);
+    }
+    #[tokio::test]
+    async fn test_route_is_allowed_with_consumer() {
+        let mut route = create_new_route_with_host_name(None);
+        route.authentication = Some(Box::new(BasicAuth {
+            credentials: String::from("lsk:password"),
+        }));
+        route.allow_deny_list = Some(vec![AllowDenyObject {
+            limit_type: crate::vojo::allow_deny_ip::AllowType::DENY,
+            value: Some(String::from("consumer:lsk")),
+        }]);
+        let mut headermap = HeaderMap::new();
+        headermap.insert("Authorization", "Basic bHNrOnBhc3N3b3Jk".parse().unwrap());
+        let uri: http::Uri = "/test".parse().unwrap();
+        let mut request_context =
+            RequestContext::new(String::from("127.0.0.1"), &uri, headermap.clone());
+        let allow_result = route.check_access(&mut request_context).await;
+        assert_eq!(allow_result.unwrap(), AccessOutcome::Forbidden);
+        assert_eq!(request_context.get_consumer_id(), Some(String::from("lsk")));
+
+        route.allow_deny_list = Some(vec![AllowDenyObject {
+            limit_type: crate::vojo::allow_deny_ip::AllowType::DENY,
+            value: Some(String::from("consumer:other")),
+        }]);
+        let mut request_context2 = RequestContext::new(String::from("127.0.0.1"), &uri, headermap);
+        let allow_result2 = route.check_access(&mut request_context2).await;
+        assert_eq!(allow_result2.unwrap(), AccessOutcome::Allowed);
+    }
+    #[tokio::test]
+    async fn test_route_check_access_refund_route_ratelimit() {
+        let mut route = create_new_route_with_host_name(None);
+        route.ratelimit = Some(
+            serde_json::from_str(
+                r#"{"type": "FixedWindowRateLimit", "rate_per_unit": 2, "unit": {"type": "Day"}, "limit_location": {"type": "ALL"}}"#,
+            )
+            .unwrap(),
+        );
+        route.authentication = Some(Box::new(ApiKeyStoreAuth {
+            key_locations: vec![KeyLocation::Header {
+                name: String::from("x-api-key"),
+            }],
+            keys: vec![ApiKey {
+                name: String::from("consumer1"),
+                key_hash: hash_api_key("key1"),
+                expires_at: None,
+                groups: vec![],
+                metadata: Default::default(),
+                ratelimit: Some(
+                    serde_json::from_str(
+                        r#"{"type": "FixedWindowRateLimit", "rate_per_unit": 1, "unit": {"type": "Day"}, "limit_location": {"type": "ALL"}}"#,
+                    )
+                    .unwrap(),
+                ),
+            }],
+            store_name: None,
+        }));
+        let mut headermap = HeaderMap::new();
+        headermap.insert("x-api-key", "key1".parse().unwrap());
+        let uri: http::Uri = "/test".parse().unwrap();
+        let mut request_context =
+            RequestContext::new(String::from("127.0.0.1"), &uri, headermap.clone());
+        let allow_result = route.check_access(&mut request_context).await;
+        assert_eq!(allow_result.unwrap(), AccessOutcome::Allowed);
+        // The consumer quota is used up,the rejected requests do not drain the route quota.
+        for _ in 0..3 {
+            let mut request_context =
+                RequestContext::new(String::from("127.0.0.1"), &uri, headermap.clone());
+            match route.check_access(&mut request_context).await.unwrap() {
+                AccessOutcome::RateLimited(quota) => assert_eq!(quota.limit, 1),
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }
+    }
+    #[test]
+    fn test_proxy_protocol_config_trusted_sources() {
+        let proxy_protocol: ProxyProtocolConfig = serde_yaml::from_str(
+            r#"
+accept: true
+trusted_sources:
+- 10.0.0.0/8
+"#,
+        )
+        .unwrap();
+        assert_eq!(proxy_protocol.validate().is_ok(), true);
+        let trusted_ip: IpAddr = "10.1.2.3".parse().unwrap();
+        let mapped_ip: IpAddr = "::ffff:10.1.2.3".parse().unwrap();
+        let untrusted_ip: IpAddr = "192.168.0.1".parse().unwrap();
+        assert_eq!(proxy_protocol.is_accepted_from(&trusted_ip), true);
+        assert_eq!(proxy_protocol.is_accepted_from(&mapped_ip), true);
+        assert_eq!(proxy_protocol.is_accepted_from(&untrusted_ip), false);
+
+        let no_trusted_sources = ProxyProtocolConfig {
+            accept: true,
+            ..Default::default()
+        };
+        assert_eq!(no_trusted_sources.validate().is_err(), true);
+        assert_eq!(no_trusted_sources.is_accepted_from(&trusted_ip), false);
+        let invalid_source: Result<ProxyProtocolConfig, _> =
+            serde_yaml::from_str("trusted_sources:\n- 10.0.0.0/33\n");
+        assert_eq!(invalid_source.is_err(), true);
+    }
+    #[test]
+    fn test_route_is_server_name_matched() {
+        let mut route = create_new_route_with_host_name(None);
+        assert_eq!(route.is_server_name_matched(None).unwrap(), true);
+        route.server_name = Some(String::from(r"^api\.example\.com$"));
+        assert_eq!(
+            route
+                .is_server_name_matched(Some("api.example.com"))
+                .unwrap(),
+            true
+        );
+        assert_eq!(
+            route
+                .is_server_name_matched(Some("www.example.com"))
+                .unwrap(),
+            false
+        );
+        assert_eq!(route.is_server_name_matched(None).unwrap(), false);
+    }
+    #[test]
+    fn test_route_is_tcp_matched() {
+        let mut route = create_new_route_with_host_name(None);
+        let source_ip: IpAddr = "10.1.2.3".parse().unwrap();
+        assert_eq!(route.is_tcp_matched(None, 5432, &source_ip).unwrap(), true);
+        route.tcp_matcher = serde_yaml::from_str(
+            r#"
+destination_ports:
+- 5432
+- 5433
+source_ips:
+- 10.1.0.0/16
+- 192.168.1.10
+"#,
+        )
+        .unwrap();
+        assert_eq!(route.is_tcp_matched(None, 5433, &source_ip).unwrap(), true);
+        assert_eq!(route.is_tcp_matched(None, 3306, &source_ip).unwrap(), false);
+        let other_ip: IpAddr = "10.2.0.1".parse().unwrap();
+        assert_eq!(route.is_tcp_matched(None, 5432, &other_ip).unwrap(), false);
+        route.server_name = Some(String::from(r"^db\.example\.com$"));
+        assert_eq!(
+            route
+                .is_tcp_matched(Some("db.example.com"), 5432, &source_ip)
+                .unwrap(),
+            true
+        );
+        assert_eq!(route.is_tcp_matched(None, 5432, &source_ip).unwrap(), false);
+        let res: Result<TcpMatcher, _> = serde_yaml::from_str("source_ips:\n- 10.1.0.0/33\n");
+        assert_eq!(res.is_err(), true);
+    }
+    #[test]
+    fn test_response_template_content_type() {
+        let res: Result<ResponseTemplate, _> = serde_yaml::from_str("body: denied\n");
+        assert_eq!(res.unwrap().content_type, "application/json");
+        let res: Result<ResponseTemplate, _> =
+            serde_yaml::from_str("content_type: text/plain\nbody: denied\n");
+        assert_eq!(res.unwrap().content_type, "text/plain");
+        let res: Result<ResponseTemplate, _> =
+            serde_json::from_str(r#"{"content_type":"text/plain\n","body":"denied"}"#);
+        assert_eq!(res.is_err(), true);
+    }
 }
@@ -1,25 +1,47 @@
+use crate::configuration_service::app_config_service::GLOBAL_API_KEY_STORES;
+use crate::vojo::identity::Identity;
+use crate::vojo::rate_limit::RatelimitStrategy;
+use crate::vojo::request_context::RequestContext;
 use base64::{engine::general_purpose, Engine as _};
 use core::fmt::Debug;
 use dyn_clone::DynClone;
-use http::HeaderMap;
-use http::HeaderValue;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::any::Any;
 
 #[typetag::serde(tag = "type")]
 pub trait AuthenticationStrategy: Sync + Send + DynClone {
     fn check_authentication(
         &mut self,
-        headers: HeaderMap<HeaderValue>,
-    ) -> Result<bool, anyhow::Error>;
+        request_context: &mut RequestContext,
+    ) -> Result<Option<Identity>, anyhow::Error>;
 
+    fn get_challenge(&self) -> String {
+        String::from("ApiKey")
+    }
     fn get_debug(&self) -> String {
         String::from("debug")
     }
+    // The api key stores used by the strategy,the unknown store is rejected on load.
+    fn get_store_names(&self) -> Vec<String> {
+        vec![]
+    }
     fn as_any(&self) -> &dyn Any;
 }
 dyn_clone::clone_trait_object!(AuthenticationStrategy);
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct AuthenticationFailure {
+    pub strategy: String,
+    pub reason: String,
+}
+impl std::fmt::Display for AuthenticationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.strategy, self.reason)
+    }
+}
 
 impl Debug for dyn AuthenticationStrategy {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -35,26 +57,36 @@ pub struct BasicAuth {
 impl AuthenticationStrategy for BasicAuth {
     fn check_authentication(
         &mut self,
-        headers: HeaderMap<HeaderValue>,
-    ) -> Result<bool, anyhow::Error> {
-        if headers.len() == 0 || !headers.contains_key("Authorization") {
-            return Ok(false);
-        }
-        let value = headers
-            .get("Authorization")
-            .unwrap()
-            .to_str()
-            .map_err(|err| anyhow!(err.to_string()))?;
+        request_context: &mut RequestContext,
+    ) -> Result<Option<Identity>, anyhow::Error> {
+        let value = match request_context.get_header("Authorization")? {
+            Some(value) => value,
+            None => {
+                request_context
+                    .add_auth_failure("BasicAuth", "the authorization header is missing");
+                return Ok(None);
+            }
+        };
         let split_list: Vec<_> = value.split(" ").collect();
         if split_list.len() != 2 || split_list[0] != "Basic" {
-            return Ok(false);
+            request_context.add_auth_failure("BasicAuth", "the authorization header is not basic");
+            return Ok(None);
         }
         let encoded: String = general_purpose::STANDARD_NO_PAD.encode(self.credentials.clone());
         if split_list[1] != encoded {
-            return Ok(false);
+            request_context.add_auth_failure("BasicAuth", "the credentials are invalid");
+            return Ok(None);
         }
-
-        Ok(true)
+        let user_name = self
+            .credentials
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        Ok(Some(Identity::new(user_name)))
+    }
+    fn get_challenge(&self) -> String {
+        String::from("Basic realm=\"silverwind\"")
     }
     fn as_any(&self) -> &dyn Any {
         self
@@ -64,23 +96,245 @@ impl AuthenticationStrategy for BasicAuth {
 pub struct ApiKeyAuth {
     pub key: String,
     pub value: String,
+    pub consumer_id: Option<String>,
 }
 
 #[typetag::serde]
 impl AuthenticationStrategy for ApiKeyAuth {
     fn check_authentication(
         &mut self,
-        headers: HeaderMap<HeaderValue>,
-    ) -> Result<bool, anyhow::Error> {
-        if headers.len() == 0 || !headers.contains_key(self.key.clone()) {
-            return Ok(false);
+        request_context: &mut RequestContext,
+    ) -> Result<Option<Identity>, anyhow::Error> {
+        let header_value = match request_context.get_header(self.key.as_str())? {
+            Some(value) => value,
+            None => {
+                request_context.add_auth_failure(
+                    "ApiKeyAuth",
+                    format!("the header {} is missing", self.key).as_str(),
+                );
+                return Ok(None);
+            }
+        };
+        if header_value != self.value {
+            request_context.add_auth_failure("ApiKeyAuth", "the api key is invalid");
+            return Ok(None);
         }
-        let header_value = headers
-            .get(self.key.clone())
-            .unwrap()
-            .to_str()
-            .map_err(|err| anyhow!(err.to_string()))?;
-        Ok(header_value == self.value)
+        let consumer_id = self.consumer_id.clone().unwrap_or(self.key.clone());
+        Ok(Some(Identity::new(consumer_id)))
+    }
+    fn get_challenge(&self) -> String {
+        format!("ApiKey header=\"{}\"", self.key)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum KeyLocation {
+    Header { name: String },
+    Query { name: String },
+    Cookie { name: String },
+}
+impl KeyLocation {
+    fn get_value(&self, request_context: &RequestContext) -> Result<Option<String>, anyhow::Error> {
+        match self {
+            KeyLocation::Header { name } => request_context.get_header(name.as_str()),
+            KeyLocation::Query { name } => Ok(request_context.get_query_param(name.as_str())),
+            KeyLocation::Cookie { name } => Ok(request_context.get_cookie(name.as_str())),
+        }
+    }
+}
+fn default_key_locations() -> Vec<KeyLocation> {
+    vec![KeyLocation::Header {
+        name: String::from("x-api-key"),
+    }]
+}
+// Only the hex encoded sha256 digest of the key is kept in the config,
+// it could be generated by `echo -n $KEY | sha256sum`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key_hash: String,
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub ratelimit: Option<Box<dyn RatelimitStrategy>>,
+}
+impl ApiKey {
+    fn is_expired(&self) -> Result<bool, anyhow::Error> {
+        if let Some(expires_at) = self.expires_at {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| anyhow!(err.to_string()))?
+                .as_secs();
+            return Ok(now >= expires_at);
+        }
+        Ok(false)
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ApiKeyStore {
+    pub name: String,
+    pub keys: Vec<ApiKey>,
+}
+pub fn hash_api_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyStoreAuth {
+    #[serde(default = "default_key_locations")]
+    pub key_locations: Vec<KeyLocation>,
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+    pub store_name: Option<String>,
+}
+impl ApiKeyStoreAuth {
+    fn find_key(&self, key_hash: &str) -> Result<Option<ApiKey>, anyhow::Error> {
+        if let Some(api_key) = self.keys.iter().find(|item| item.key_hash == key_hash) {
+            return Ok(Some(api_key.clone()));
+        }
+        if let Some(store_name) = &self.store_name {
+            let store = GLOBAL_API_KEY_STORES
+                .get(store_name)
+                .ok_or(anyhow!("Can not find the api key store {}!", store_name))?;
+            return Ok(store
+                .keys
+                .iter()
+                .find(|item| item.key_hash == key_hash)
+                .cloned());
+        }
+        Ok(None)
+    }
+}
+#[typetag::serde]
+impl AuthenticationStrategy for ApiKeyStoreAuth {
+    fn check_authentication(
+        &mut self,
+        request_context: &mut RequestContext,
+    ) -> Result<Option<Identity>, anyhow::Error> {
+        let mut presented_key = None;
+        for key_location in self.key_locations.iter() {
+            presented_key = key_location.get_value(request_context)?;
+            if presented_key.is_some() {
+                break;
+            }
+        }
+        let presented_key = match presented_key {
+            Some(value) => value,
+            None => {
+                request_context.add_auth_failure("ApiKeyStoreAuth", "the api key is missing");
+                return Ok(None);
+            }
+        };
+        let api_key = match self.find_key(hash_api_key(presented_key.as_str()).as_str())? {
+            Some(api_key) => api_key,
+            None => {
+                request_context.add_auth_failure("ApiKeyStoreAuth", "the api key is invalid");
+                return Ok(None);
+            }
+        };
+        if api_key.is_expired()? {
+            debug!("The api key {} has expired.", api_key.name);
+            request_context.add_auth_failure("ApiKeyStoreAuth", "the api key has expired");
+            return Ok(None);
+        }
+        Ok(Some(Identity {
+            consumer_id: api_key.name,
+            groups: api_key.groups,
+            claims: api_key.metadata,
+            ratelimit: api_key.ratelimit,
+        }))
+    }
+    fn get_store_names(&self) -> Vec<String> {
+        self.store_name.iter().cloned().collect()
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum CompositeMode {
+    #[default]
+    AnyOf,
+    AllOf,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompositeAuth {
+    #[serde(default)]
+    pub mode: CompositeMode,
+    #[serde(deserialize_with = "deserialize_strategies")]
+    pub strategies: Vec<Box<dyn AuthenticationStrategy>>,
+}
+fn deserialize_strategies<'de, D>(
+    deserializer: D,
+) -> Result<Vec<Box<dyn AuthenticationStrategy>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let strategies = Vec::<Box<dyn AuthenticationStrategy>>::deserialize(deserializer)?;
+    if strategies.is_empty() {
+        return Err(serde::de::Error::custom(
+            "The strategies of the CompositeAuth could not be empty!",
+        ));
+    }
+    Ok(strategies)
+}
+fn merge_identity(identity: &mut Identity, other: Identity) {
+    for group in other.groups {
+        if !identity.in_group(group.as_str()) {
+            identity.groups.push(group);
+        }
+    }
+    for (key, value) in other.claims {
+        identity.claims.entry(key).or_insert(value);
+    }
+    if identity.ratelimit.is_none() {
+        identity.ratelimit = other.ratelimit;
+    }
+}
+#[typetag::serde]
+impl AuthenticationStrategy for CompositeAuth {
+    fn check_authentication(
+        &mut self,
+        request_context: &mut RequestContext,
+    ) -> Result<Option<Identity>, anyhow::Error> {
+        let failure_count = request_context.auth_failures.len();
+        let mut identity_option: Option<Identity> = None;
+        for strategy in self.strategies.iter_mut() {
+            let current_identity = strategy.check_authentication(request_context)?;
+            match (self.mode.clone(), current_identity) {
+                (CompositeMode::AnyOf, Some(identity)) => {
+                    request_context.auth_failures.truncate(failure_count);
+                    return Ok(Some(identity));
+                }
+                (CompositeMode::AnyOf, None) => continue,
+                (CompositeMode::AllOf, Some(identity)) => match identity_option.as_mut() {
+                    Some(current) => merge_identity(current, identity),
+                    None => identity_option = Some(identity),
+                },
+                (CompositeMode::AllOf, None) => return Ok(None),
+            }
+        }
+        Ok(identity_option)
+    }
+    fn get_challenge(&self) -> String {
+        self.strategies
+            .iter()
+            .map(|strategy| strategy.get_challenge())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+    fn get_store_names(&self) -> Vec<String> {
+        self.strategies
+            .iter()
+            .flat_map(|strategy| strategy.get_store_names())
+            .collect()
     }
     fn as_any(&self) -> &dyn Any {
         self
@@ -90,6 +344,17 @@ impl AuthenticationStrategy for ApiKeyAuth {
 mod tests {
     use super::*;
     use crate::vojo::app_config::ApiService;
+    use crate::vojo::rate_limit::{
+        FixedWindowRateLimit, IpRangeBasedRatelimit, LimitLocation, TimeUnit,
+    };
+    use http::HeaderMap;
+    fn new_request_context(headers: HeaderMap) -> RequestContext {
+        RequestContext {
+            remote_ip: String::from("127.0.0.1"),
+            headers,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_basic_auth_error1() {
@@ -98,8 +363,8 @@ mod tests {
         });
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("x-client", "Basic bHNrOjEyMzQ=".parse().unwrap());
-        let res1 = basic_auth.check_authentication(headermap1);
-        assert_eq!(res1.unwrap(), false);
+        let res1 = basic_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), false);
     }
     #[test]
     fn test_basic_auth_error2() {
@@ -108,8 +373,8 @@ mod tests {
         });
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("Authorization", "BasicbHNrOjEyMzQ=".parse().unwrap());
-        let res1 = basic_auth.check_authentication(headermap1);
-        assert_eq!(res1.unwrap(), false);
+        let res1 = basic_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), false);
     }
     #[test]
     fn test_basic_auth_error3() {
@@ -118,8 +383,8 @@ mod tests {
         });
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("Authorization", "Basic test".parse().unwrap());
-        let res1 = basic_auth.check_authentication(headermap1);
-        assert_eq!(res1.unwrap(), false);
+        let res1 = basic_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), false);
     }
     #[test]
     fn test_basic_auth_ok() {
@@ -128,19 +393,20 @@ mod tests {
         });
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("Authorization", "Basic bHNrOnBhc3N3b3Jk".parse().unwrap());
-        let res1 = basic_auth.check_authentication(headermap1);
-        assert_eq!(res1.unwrap(), true);
+        let res1 = basic_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), true);
     }
     #[test]
     fn test_api_key_auth_error() {
         let mut basic_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyAuth {
             key: String::from("sss"),
             value: String::from("test2"),
+            consumer_id: None,
         });
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("Authorization", "Basic bHNrOnBhc3N3b3Jk".parse().unwrap());
-        let res1 = basic_auth.check_authentication(headermap1);
-        assert_eq!(res1.unwrap(), false);
+        let res1 = basic_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), false);
     }
 
     #[test]
@@ -148,11 +414,12 @@ mod tests {
         let mut basic_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyAuth {
             key: String::from("api_key"),
             value: String::from("test2"),
+            consumer_id: None,
         });
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 = basic_auth.check_authentication(headermap1);
-        assert_eq!(res1.unwrap(), true);
+        let res1 = basic_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), true);
     }
     #[test]
     fn test_basic_auth_as_any() {
@@ -263,4 +530,365 @@ mod tests {
         assert_eq!(basic_auth.key, "api_key");
         assert_eq!(basic_auth.value, "test");
     }
+
+    fn new_api_key(name: &str, key: &str, expires_at: Option<u64>) -> ApiKey {
+        ApiKey {
+            name: String::from(name),
+            key_hash: hash_api_key(key),
+            expires_at,
+            groups: vec![],
+            metadata: HashMap::new(),
+            ratelimit: None,
+        }
+    }
+    #[test]
+    fn test_hash_api_key() {
+        assert_eq!(
+            hash_api_key("test"),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+    #[test]
+    fn test_api_key_store_auth_header_ok() {
+        let mut api_key_store_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyStoreAuth {
+            key_locations: default_key_locations(),
+            keys: vec![
+                new_api_key("consumer1", "key1", None),
+                new_api_key("consumer2", "key2", None),
+            ],
+            store_name: None,
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("x-api-key", "key2".parse().unwrap());
+        let res1 = api_key_store_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), true);
+
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("x-api-key", "key3".parse().unwrap());
+        let res2 = api_key_store_auth.check_authentication(&mut new_request_context(headermap2));
+        assert_eq!(res2.unwrap().is_some(), false);
+    }
+    #[test]
+    fn test_api_key_store_auth_query_and_cookie_ok() {
+        let mut api_key_store_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyStoreAuth {
+            key_locations: vec![
+                KeyLocation::Query {
+                    name: String::from("api_key"),
+                },
+                KeyLocation::Cookie {
+                    name: String::from("api_key"),
+                },
+            ],
+            keys: vec![new_api_key("consumer1", "key1", None)],
+            store_name: None,
+        });
+        let mut request_context = new_request_context(HeaderMap::new());
+        request_context.query = Some(String::from("a=1&api_key=key1"));
+        let res1 = api_key_store_auth.check_authentication(&mut request_context);
+        assert_eq!(res1.unwrap().is_some(), true);
+
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("Cookie", "session=1; api_key=key1".parse().unwrap());
+        let res2 = api_key_store_auth.check_authentication(&mut new_request_context(headermap2));
+        assert_eq!(res2.unwrap().is_some(), true);
+
+        let mut headermap3 = HeaderMap::new();
+        headermap3.insert("api_key", "key1".parse().unwrap());
+        let res3 = api_key_store_auth.check_authentication(&mut new_request_context(headermap3));
+        assert_eq!(res3.unwrap().is_some(), false);
+    }
+    #[test]
+    fn test_api_key_store_auth_expired() {
+        let mut api_key_store_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyStoreAuth {
+            key_locations: default_key_locations(),
+            keys: vec![
+                new_api_key("consumer1", "key1", Some(1)),
+                new_api_key("consumer2", "key2", Some(u64::MAX)),
+            ],
+            store_name: None,
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("x-api-key", "key1".parse().unwrap());
+        let res1 = api_key_store_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), false);
+
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("x-api-key", "key2".parse().unwrap());
+        let res2 = api_key_store_auth.check_authentication(&mut new_request_context(headermap2));
+        assert_eq!(res2.unwrap().is_some(), true);
+    }
+    #[test]
+    fn test_api_key_store_auth_global_store() {
+        GLOBAL_API_KEY_STORES.insert(
+            String::from("test_store"),
+            ApiKeyStore {
+                name: String::from("test_store"),
+                keys: vec![new_api_key("consumer1", "global_key", None)],
+            },
+        );
+        let mut api_key_store_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyStoreAuth {
+            key_locations: default_key_locations(),
+            keys: vec![],
+            store_name: Some(String::from("test_store")),
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("x-api-key", "global_key".parse().unwrap());
+        let res1 = api_key_store_auth.check_authentication(&mut new_request_context(headermap1));
+        assert_eq!(res1.unwrap().is_some(), true);
+
+        let mut not_found_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyStoreAuth {
+            key_locations: default_key_locations(),
+            keys: vec![],
+            store_name: Some(String::from("not_found_store")),
+        });
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("x-api-key", "global_key".parse().unwrap());
+        let res2 = not_found_auth.check_authentication(&mut new_request_context(headermap2));
+        assert_eq!(res2.is_err(), true);
+    }
+    #[test]
+    fn test_api_key_store_auth_identity() {
+        let mut api_key = new_api_key("consumer1", "key1", None);
+        api_key.groups = vec![String::from("partner")];
+        api_key
+            .metadata
+            .insert(String::from("team"), String::from("payment"));
+        api_key.ratelimit = Some(Box::new(FixedWindowRateLimit {
+            rate_per_unit: 2,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::IPRANGE(IpRangeBasedRatelimit {
+                value: String::from("127.0.0.1/8"),
+            }),
+            count_map: Default::default(),
+            lock: Default::default(),
+            key_by: None,
+            keyed_map: Default::default(),
+        }));
+        let mut api_key_store_auth: Box<dyn AuthenticationStrategy> = Box::new(ApiKeyStoreAuth {
+            key_locations: default_key_locations(),
+            keys: vec![api_key],
+            store_name: None,
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("x-api-key", "key1".parse().unwrap());
+        let identity = api_key_store_auth
+            .check_authentication(&mut new_request_context(headermap1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.consumer_id, "consumer1");
+        assert_eq!(identity.in_group("partner"), true);
+        assert_eq!(identity.claims.get("team"), Some(&String::from("payment")));
+        assert_eq!(identity.ratelimit.is_some(), true);
+    }
+    #[test]
+    fn test_basic_auth_identity() {
+        let mut basic_auth: Box<dyn AuthenticationStrategy> = Box::new(BasicAuth {
+            credentials: String::from("lsk:password"),
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("Authorization", "Basic bHNrOnBhc3N3b3Jk".parse().unwrap());
+        let identity = basic_auth
+            .check_authentication(&mut new_request_context(headermap1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.consumer_id, "lsk");
+    }
+    #[test]
+    fn test_api_key_store_auth_as_any() {
+        let req = r#"[
+            {
+              "listen_port": 4486,
+              "service_config": {
+                "server_type": "HTTP",
+                "cert_str": null,
+                "key_str": null,
+                "routes": [
+                  {
+                    "matcher": {
+                      "prefix": "ss",
+                      "prefix_rewrite": "ssss"
+                    },
+                    "allow_deny_list": null,
+                    "authentication": {
+                      "type": "ApiKeyStoreAuth",
+                      "key_locations": [
+                        {
+                          "type": "Query",
+                          "name": "api_key"
+                        }
+                      ],
+                      "keys": [
+                        {
+                          "name": "consumer1",
+                          "key_hash": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+                          "expires_at": 1893456000,
+                          "metadata": {
+                            "team": "payment"
+                          }
+                        }
+                      ]
+                    },
+                    "route_cluster": {
+                      "type": "PollRoute",
+                      "routes": [
+                        {
+                            "base_route": {
+                                "endpoint": "http://localhost:8000",
+                                "try_file": null
+                            }
+                        }
+                      ]
+                    }
+                  }
+                ]
+              }
+            }
+          ]"#;
+        let api_services: Vec<ApiService> = serde_json::from_slice(req.as_bytes()).unwrap();
+        let first_api_service = api_services
+            .first()
+            .unwrap()
+            .service_config
+            .routes
+            .first()
+            .unwrap()
+            .clone();
+        let route = first_api_service.authentication.unwrap();
+        let api_key_store_auth: &ApiKeyStoreAuth =
+            match route.as_any().downcast_ref::<ApiKeyStoreAuth>() {
+                Some(b) => b,
+                None => panic!("error!"),
+            };
+
+        assert_eq!(api_key_store_auth.keys.len(), 1);
+        assert_eq!(api_key_store_auth.keys[0].expires_at, Some(1893456000));
+        assert_eq!(api_key_store_auth.store_name, None);
+        assert_eq!(
+            api_key_store_auth.key_locations,
+            vec![KeyLocation::Query {
+                name: String::from("api_key")
+            }]
+        );
+    }
+    #[test]
+    fn test_composite_auth_any_of() {
+        let mut composite_auth: Box<dyn AuthenticationStrategy> = Box::new(CompositeAuth {
+            mode: CompositeMode::AnyOf,
+            strategies: vec![
+                Box::new(BasicAuth {
+                    credentials: String::from("lsk:password"),
+                }),
+                Box::new(ApiKeyStoreAuth {
+                    key_locations: default_key_locations(),
+                    keys: vec![new_api_key("consumer1", "key1", None)],
+                    store_name: None,
+                }),
+            ],
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("x-api-key", "key1".parse().unwrap());
+        let mut request_context1 = new_request_context(headermap1);
+        let identity = composite_auth
+            .check_authentication(&mut request_context1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.consumer_id, "consumer1");
+        assert_eq!(request_context1.auth_failures.is_empty(), true);
+
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("x-api-key", "key2".parse().unwrap());
+        let mut request_context2 = new_request_context(headermap2);
+        let res2 = composite_auth.check_authentication(&mut request_context2);
+        assert_eq!(res2.unwrap().is_some(), false);
+        assert_eq!(
+            request_context2
+                .auth_failures
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                String::from("BasicAuth: the authorization header is missing"),
+                String::from("ApiKeyStoreAuth: the api key is invalid"),
+            ]
+        );
+    }
+    #[test]
+    fn test_composite_auth_all_of() {
+        let mut api_key = new_api_key("consumer1", "key1", None);
+        api_key.groups = vec![String::from("partner")];
+        let mut composite_auth: Box<dyn AuthenticationStrategy> = Box::new(CompositeAuth {
+            mode: CompositeMode::AllOf,
+            strategies: vec![
+                Box::new(BasicAuth {
+                    credentials: String::from("lsk:password"),
+                }),
+                Box::new(ApiKeyStoreAuth {
+                    key_locations: default_key_locations(),
+                    keys: vec![api_key],
+                    store_name: None,
+                }),
+            ],
+        });
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("Authorization", "Basic bHNrOnBhc3N3b3Jk".parse().unwrap());
+        headermap1.insert("x-api-key", "key1".parse().unwrap());
+        let identity = composite_auth
+            .check_authentication(&mut new_request_context(headermap1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.consumer_id, "lsk");
+        assert_eq!(identity.in_group("partner"), true);
+
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("x-api-key", "key1".parse().unwrap());
+        let mut request_context2 = new_request_context(headermap2);
+        let res2 = composite_auth.check_authentication(&mut request_context2);
+        assert_eq!(res2.unwrap().is_some(), false);
+        assert_eq!(request_context2.auth_failures.len(), 1);
+        assert_eq!(request_context2.auth_failures[0].strategy, "BasicAuth");
+    }
+    #[test]
+    fn test_composite_auth_as_any() {
+        let req = r#"{
+            "type": "CompositeAuth",
+            "mode": "AllOf",
+            "strategies": [
+                {
+                    "type": "BasicAuth",
+                    "credentials": "lsk:123456"
+                },
+                {
+                    "type": "ApiKeyAuth",
+                    "key": "api_key",
+                    "value": "test"
+                }
+            ]
+        }"#;
+        let authentication: Box<dyn AuthenticationStrategy> = serde_json::from_str(req).unwrap();
+        let composite_auth: &CompositeAuth =
+            match authentication.as_any().downcast_ref::<CompositeAuth>() {
+                Some(b) => b,
+                None => panic!("error!"),
+            };
+        assert_eq!(composite_auth.mode, CompositeMode::AllOf);
+        assert_eq!(composite_auth.strategies.len(), 2);
+    }
+    #[test]
+    fn test_composite_auth_empty_strategies() {
+        let req = r#"{
+            "type": "CompositeAuth",
+            "mode": "AnyOf",
+            "strategies": []
+        }"#;
+        let result: Result<Box<dyn AuthenticationStrategy>, _> = serde_json::from_str(req);
+        assert_eq!(result.is_err(), true);
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("could not be empty"),
+            true
+        );
+    }
 }
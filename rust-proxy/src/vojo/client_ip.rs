@@ -0,0 +1,153 @@
+use crate::vojo::ip_matcher::{deserialize_ip_value_list, ip_value_contains, parse_ip};
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(tag = "type")]
+pub enum ClientIpSource {
+    #[default]
+    XForwardedFor,
+    Forwarded,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ClientIpConfig {
+    #[serde(default, deserialize_with = "deserialize_ip_value_list")]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub source: ClientIpSource,
+}
+impl ClientIpConfig {
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|trusted_proxy| ip_value_contains(trusted_proxy, ip).unwrap_or(false))
+    }
+    // The forwarded addresses are walked from the right,the first address which is not a
+    // trusted proxy is the client.The headers are ignored when the peer is not trusted.
+    pub fn resolve(&self, remote_ip: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let remote_ip = remote_ip.to_canonical();
+        if !self.is_trusted(&remote_ip) {
+            return remote_ip;
+        }
+        let forwarded_values = match self.source {
+            ClientIpSource::XForwardedFor => get_x_forwarded_for(headers),
+            ClientIpSource::Forwarded => get_forwarded_for(headers),
+        };
+        let mut client_ip = remote_ip;
+        for value in forwarded_values.iter().rev() {
+            match parse_forwarded_ip(value) {
+                Some(ip) => {
+                    client_ip = ip;
+                    if !self.is_trusted(&ip) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        client_ip
+    }
+}
+fn get_header_values(headers: &HeaderMap, key: &str) -> Vec<String> {
+    headers
+        .get_all(key)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| String::from(value.trim()))
+        .collect()
+}
+fn get_x_forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    get_header_values(headers, "X-Forwarded-For")
+}
+fn get_forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    get_header_values(headers, "Forwarded")
+        .iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                if name.trim().eq_ignore_ascii_case("for") {
+                    Some(String::from(value.trim().trim_matches('"')))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+// The forwarded address could be "192.0.2.1","192.0.2.1:4711","2001:db8::1" or "[2001:db8::1]:4711".
+fn parse_forwarded_ip(value: &str) -> Option<IpAddr> {
+    if let Ok(ip) = parse_ip(value) {
+        return Some(ip);
+    }
+    let host = match value.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0,
+        None => value.rsplit_once(':')?.0,
+    };
+    parse_ip(host).ok()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_client_ip_config(source: ClientIpSource) -> ClientIpConfig {
+        ClientIpConfig {
+            trusted_proxies: vec![String::from("10.0.0.0/8"), String::from("fd00::/8")],
+            source,
+        }
+    }
+    #[test]
+    fn test_resolve_x_forwarded_for() {
+        let client_ip_config = new_client_ip_config(ClientIpSource::XForwardedFor);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Forwarded-For",
+            "1.1.1.1, 203.0.113.7, 10.0.0.2".parse().unwrap(),
+        );
+        let remote_ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let client_ip = client_ip_config.resolve(remote_ip, &headers);
+        assert_eq!(client_ip.to_string(), "203.0.113.7");
+    }
+    #[test]
+    fn test_resolve_untrusted_peer() {
+        let client_ip_config = new_client_ip_config(ClientIpSource::XForwardedFor);
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", "203.0.113.7".parse().unwrap());
+        let remote_ip: IpAddr = "192.168.0.1".parse().unwrap();
+        let client_ip = client_ip_config.resolve(remote_ip, &headers);
+        assert_eq!(client_ip.to_string(), "192.168.0.1");
+    }
+    #[test]
+    fn test_resolve_invalid_forwarded_value() {
+        let client_ip_config = new_client_ip_config(ClientIpSource::XForwardedFor);
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", "unknown, 10.0.0.3".parse().unwrap());
+        let remote_ip: IpAddr = "::ffff:10.0.0.1".parse().unwrap();
+        let client_ip = client_ip_config.resolve(remote_ip, &headers);
+        assert_eq!(client_ip.to_string(), "10.0.0.3");
+    }
+    #[test]
+    fn test_resolve_forwarded() {
+        let client_ip_config = new_client_ip_config(ClientIpSource::Forwarded);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Forwarded",
+            "for=192.0.2.60;proto=http, for=\"[2001:db8::1]:4711\", for=\"[fd00::1]\""
+                .parse()
+                .unwrap(),
+        );
+        let remote_ip: IpAddr = "fd00::2".parse().unwrap();
+        let client_ip = client_ip_config.resolve(remote_ip, &headers);
+        assert_eq!(client_ip.to_string(), "2001:db8::1");
+    }
+    #[test]
+    fn test_client_ip_config_validate_when_loading() {
+        let res: Result<ClientIpConfig, _> =
+            serde_yaml::from_str("trusted_proxies:\n- 10.0.0.0/33\n");
+        assert_eq!(res.is_err(), true);
+        let res: Result<ClientIpConfig, _> =
+            serde_yaml::from_str("trusted_proxies:\n- 10.0.0.1\nsource:\n  type: Forwarded\n");
+        assert_eq!(res.unwrap().source, ClientIpSource::Forwarded);
+    }
+}
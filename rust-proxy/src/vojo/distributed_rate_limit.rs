@@ -0,0 +1,1153 @@
+use crate::vojo::keyed_store::KeyedStore;
+use crate::vojo::rate_limit::{
+    get_limit_key, matched, FixedWindowRateLimit, RatelimitQuota, RatelimitStrategy,
+    SlidingWindowLogRateLimit, TokenBucketRateLimit,
+};
+use crate::vojo::request_context::RequestContext;
+use core::fmt::Debug;
+use futures::future;
+use futures::future::BoxFuture;
+use redis::aio::MultiplexedConnection;
+use redis::{Script, ScriptInvocation};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const RECONNECT_MIN_BACKOFF_MS: u64 = 500;
+const RECONNECT_MAX_BACKOFF_MS: u64 = 30_000;
+
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1])
+local ts = tonumber(bucket[2])
+if tokens == nil or ts == nil then
+    tokens = capacity
+    ts = now
+end
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens))
+redis.call('HSET', KEYS[1], 'ts', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate) + 1000)
+return {allowed, math.floor(tokens), math.ceil((1 - math.min(tokens, 1)) / rate)}
+"#;
+const FIXED_WINDOW_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+local ttl = redis.call('PTTL', KEYS[1])
+local allowed = 0
+if count <= tonumber(ARGV[1]) then
+    allowed = 1
+end
+return {allowed, math.max(0, tonumber(ARGV[1]) - count), math.max(0, ttl)}
+"#;
+const SLIDING_WINDOW_LOG_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], 0, now - window)
+local count = redis.call('ZCARD', KEYS[1])
+local allowed = 0
+if count < limit then
+    redis.call('ZADD', KEYS[1], now, now .. '-' .. ARGV[3])
+    count = count + 1
+    allowed = 1
+end
+redis.call('PEXPIRE', KEYS[1], window)
+local reset = window
+local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+if oldest[2] then
+    reset = tonumber(oldest[2]) + window - now
+end
+return {allowed, limit - count, reset}
+"#;
+const TOKEN_BUCKET_RELEASE_SCRIPT: &str = r#"
+local tokens = tonumber(redis.call('HGET', KEYS[1], 'tokens'))
+if tokens ~= nil then
+    redis.call('HSET', KEYS[1], 'tokens', tostring(math.min(tonumber(ARGV[1]), tokens + 1)))
+end
+return {1}
+"#;
+const FIXED_WINDOW_RELEASE_SCRIPT: &str = r#"
+local count = tonumber(redis.call('GET', KEYS[1]))
+if count ~= nil and count > 0 then
+    redis.call('DECR', KEYS[1])
+end
+return {1}
+"#;
+const SLIDING_WINDOW_LOG_RELEASE_SCRIPT: &str = r#"
+redis.call('ZPOPMAX', KEYS[1])
+return {1}
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DistributedAlgorithm {
+    TokenBucket {
+        capacity: u64,
+        rate_per_unit: u64,
+        unit_ms: u64,
+    },
+    FixedWindow {
+        limit: u64,
+        window_ms: u64,
+    },
+    SlidingWindowLog {
+        limit: u64,
+        window_ms: u64,
+    },
+}
+impl DistributedAlgorithm {
+    fn get_name(&self) -> &'static str {
+        match self {
+            DistributedAlgorithm::TokenBucket { .. } => "token_bucket",
+            DistributedAlgorithm::FixedWindow { .. } => "fixed_window",
+            DistributedAlgorithm::SlidingWindowLog { .. } => "sliding_window_log",
+        }
+    }
+    fn get_limit(&self) -> u64 {
+        match self {
+            DistributedAlgorithm::TokenBucket { capacity, .. } => *capacity,
+            DistributedAlgorithm::FixedWindow { limit, .. } => *limit,
+            DistributedAlgorithm::SlidingWindowLog { limit, .. } => *limit,
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StoreDecision {
+    pub allowed: bool,
+    pub remaining: u64,
+    pub reset_after_ms: u64,
+}
+pub trait RatelimitStore: Sync + Send + Debug {
+    fn acquire<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: &'a DistributedAlgorithm,
+    ) -> BoxFuture<'a, Result<StoreDecision, anyhow::Error>>;
+    // Give back the request acquired by the last allowed acquire of the key.
+    fn release<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: &'a DistributedAlgorithm,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>>;
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum RatelimitStoreConfig {
+    Redis {
+        url: String,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+        #[serde(default = "default_key_prefix")]
+        key_prefix: String,
+    },
+    Memory,
+}
+fn default_timeout_ms() -> u64 {
+    100
+}
+fn default_key_prefix() -> String {
+    String::from("silverwind:ratelimit:")
+}
+impl RatelimitStoreConfig {
+    fn create_store(&self) -> Result<Arc<dyn RatelimitStore>, anyhow::Error> {
+        match self {
+            RatelimitStoreConfig::Redis {
+                url,
+                timeout_ms,
+                key_prefix,
+            } => Ok(Arc::new(RedisStore::new(
+                url.as_str(),
+                Duration::from_millis(*timeout_ms),
+                key_prefix.clone(),
+            )?)),
+            RatelimitStoreConfig::Memory => Ok(Arc::new(MemoryStore::default())),
+        }
+    }
+}
+#[derive(Default)]
+struct RedisConnectionState {
+    connection: Option<MultiplexedConnection>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+// The multiplexed connection is shared by all the requests without blocking the runtime.
+pub struct RedisStore {
+    client: redis::Client,
+    timeout: Duration,
+    key_prefix: String,
+    state: Mutex<RedisConnectionState>,
+    token_bucket_script: Script,
+    fixed_window_script: Script,
+    sliding_window_log_script: Script,
+    token_bucket_release_script: Script,
+    fixed_window_release_script: Script,
+    sliding_window_log_release_script: Script,
+}
+impl Debug for RedisStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "RedisStore {{ key_prefix: {} }}", self.key_prefix)
+    }
+}
+impl RedisStore {
+    pub fn new(url: &str, timeout: Duration, key_prefix: String) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(url).map_err(|err| anyhow!(err.to_string()))?;
+        Ok(RedisStore {
+            client,
+            timeout,
+            key_prefix,
+            state: Default::default(),
+            token_bucket_script: Script::new(TOKEN_BUCKET_SCRIPT),
+            fixed_window_script: Script::new(FIXED_WINDOW_SCRIPT),
+            sliding_window_log_script: Script::new(SLIDING_WINDOW_LOG_SCRIPT),
+            token_bucket_release_script: Script::new(TOKEN_BUCKET_RELEASE_SCRIPT),
+            fixed_window_release_script: Script::new(FIXED_WINDOW_RELEASE_SCRIPT),
+            sliding_window_log_release_script: Script::new(SLIDING_WINDOW_LOG_RELEASE_SCRIPT),
+        })
+    }
+    // After a failure the store is unavailable until the backoff expires,so the failure mode
+    // takes effect at once instead of waiting for the connect timeout on every request.
+    async fn get_connection(&self) -> Result<MultiplexedConnection, anyhow::Error> {
+        {
+            let mut state = self.state.lock().map_err(|err| anyhow!(err.to_string()))?;
+            if let Some(connection) = &state.connection {
+                return Ok(connection.clone());
+            }
+            let now = Instant::now();
+            if let Some(retry_at) = state.retry_at {
+                if now < retry_at {
+                    return Err(anyhow!(
+                        "The redis is unavailable,the reconnection is delayed for {} ms",
+                        (retry_at - now).as_millis()
+                    ));
+                }
+            }
+            // The other requests fail fast while this one is connecting.
+            state.retry_at = Some(now + self.timeout);
+        }
+        let connect_result =
+            tokio::time::timeout(self.timeout, self.client.get_multiplexed_tokio_connection())
+                .await;
+        let connection = match connect_result {
+            Ok(Ok(connection)) => connection,
+            Ok(Err(err)) => return Err(self.on_failure(anyhow!(err.to_string()))),
+            Err(_) => return Err(self.on_failure(anyhow!("Connect the redis timeout!"))),
+        };
+        let mut state = self.state.lock().map_err(|err| anyhow!(err.to_string()))?;
+        state.connection = Some(connection.clone());
+        state.failures = 0;
+        state.retry_at = None;
+        Ok(connection)
+    }
+    // Drop the broken connection and delay the next reconnection exponentially.
+    fn on_failure(&self, err: anyhow::Error) -> anyhow::Error {
+        if let Ok(mut state) = self.state.lock() {
+            state.connection = None;
+            state.failures = state.failures.saturating_add(1);
+            let backoff_ms = RECONNECT_MIN_BACKOFF_MS
+                .saturating_mul(1 << (state.failures - 1).min(16))
+                .min(RECONNECT_MAX_BACKOFF_MS);
+            state.retry_at = Some(Instant::now() + Duration::from_millis(backoff_ms));
+        }
+        err
+    }
+    async fn run_script(
+        &self,
+        invocation: ScriptInvocation<'_>,
+    ) -> Result<Vec<i64>, anyhow::Error> {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return Err(anyhow!(
+                "The redis store could only be used in the tokio runtime!"
+            ));
+        }
+        let mut connection = self.get_connection().await?;
+        let invoke_future = invocation.invoke_async::<_, Vec<i64>>(&mut connection);
+        match tokio::time::timeout(self.timeout, invoke_future).await {
+            Ok(Ok(values)) => Ok(values),
+            Ok(Err(err)) => Err(self.on_failure(anyhow!(err.to_string()))),
+            Err(_) => Err(self.on_failure(anyhow!("Invoke the redis script timeout!"))),
+        }
+    }
+    async fn invoke(
+        &self,
+        key: &str,
+        algorithm: &DistributedAlgorithm,
+    ) -> Result<StoreDecision, anyhow::Error> {
+        let redis_key = format!("{}{}", self.key_prefix, key);
+        let invocation = match algorithm {
+            DistributedAlgorithm::TokenBucket {
+                capacity,
+                rate_per_unit,
+                unit_ms,
+            } => {
+                let mut invocation = self.token_bucket_script.key(redis_key);
+                invocation
+                    .arg(*capacity)
+                    .arg(*rate_per_unit as f64 / *unit_ms as f64);
+                invocation
+            }
+            DistributedAlgorithm::FixedWindow { limit, window_ms } => {
+                let mut invocation = self.fixed_window_script.key(redis_key);
+                invocation.arg(*limit).arg(*window_ms);
+                invocation
+            }
+            DistributedAlgorithm::SlidingWindowLog { limit, window_ms } => {
+                let mut invocation = self.sliding_window_log_script.key(redis_key);
+                invocation
+                    .arg(*limit)
+                    .arg(*window_ms)
+                    .arg(rand::random::<u64>());
+                invocation
+            }
+        };
+        let values = self.run_script(invocation).await?;
+        if values.len() != 3 {
+            return Err(anyhow!("The reply of the ratelimit script is invalid!"));
+        }
+        Ok(StoreDecision {
+            allowed: values[0] == 1,
+            remaining: values[1].max(0) as u64,
+            reset_after_ms: values[2].max(0) as u64,
+        })
+    }
+    async fn revoke(
+        &self,
+        key: &str,
+        algorithm: &DistributedAlgorithm,
+    ) -> Result<(), anyhow::Error> {
+        let redis_key = format!("{}{}", self.key_prefix, key);
+        let invocation = match algorithm {
+            DistributedAlgorithm::TokenBucket { capacity, .. } => {
+                let mut invocation = self.token_bucket_release_script.key(redis_key);
+                invocation.arg(*capacity);
+                invocation
+            }
+            DistributedAlgorithm::FixedWindow { .. } => {
+                self.fixed_window_release_script.key(redis_key)
+            }
+            DistributedAlgorithm::SlidingWindowLog { .. } => {
+                self.sliding_window_log_release_script.key(redis_key)
+            }
+        };
+        self.run_script(invocation).await.map(|_| ())
+    }
+}
+impl RatelimitStore for RedisStore {
+    fn acquire<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: &'a DistributedAlgorithm,
+    ) -> BoxFuture<'a, Result<StoreDecision, anyhow::Error>> {
+        Box::pin(self.invoke(key, algorithm))
+    }
+    fn release<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: &'a DistributedAlgorithm,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(self.revoke(key, algorithm))
+    }
+}
+#[derive(Debug)]
+enum MemoryEntry {
+    TokenBucket { tokens: f64, last_refill_ms: u64 },
+    FixedWindow { count: u64, expires_at_ms: u64 },
+    SlidingWindowLog { request_log: VecDeque<u64> },
+}
+// An in-process store with the same semantics as the redis scripts,it is used when
+// there is only one replica and in the tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: KeyedStore<Arc<Mutex<MemoryEntry>>>,
+}
+fn now_millis() -> Result<u64, anyhow::Error> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| anyhow!(err.to_string()))?
+        .as_millis() as u64)
+}
+impl RatelimitStore for MemoryStore {
+    fn acquire<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: &'a DistributedAlgorithm,
+    ) -> BoxFuture<'a, Result<StoreDecision, anyhow::Error>> {
+        Box::pin(future::ready(self.acquire_entry(key, algorithm)))
+    }
+    fn release<'a>(
+        &'a self,
+        key: &'a str,
+        algorithm: &'a DistributedAlgorithm,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(future::ready(self.release_entry(key, algorithm)))
+    }
+}
+impl MemoryStore {
+    fn get_entry(
+        &self,
+        key: &str,
+        algorithm: &DistributedAlgorithm,
+        now: u64,
+    ) -> Result<Arc<Mutex<MemoryEntry>>, anyhow::Error> {
+        // The entry idle longer than the ttl is the same as the new one,so it is evicted.
+        let (ttl_ms, new_entry) = match algorithm {
+            DistributedAlgorithm::TokenBucket {
+                capacity,
+                rate_per_unit,
+                unit_ms,
+            } => (
+                capacity
+                    .saturating_mul(*unit_ms)
+                    .div_ceil((*rate_per_unit).max(1))
+                    .max(*unit_ms),
+                MemoryEntry::TokenBucket {
+                    tokens: *capacity as f64,
+                    last_refill_ms: now,
+                },
+            ),
+            DistributedAlgorithm::FixedWindow { window_ms, .. } => (
+                *window_ms,
+                MemoryEntry::FixedWindow {
+                    count: 0,
+                    expires_at_ms: now + window_ms,
+                },
+            ),
+            DistributedAlgorithm::SlidingWindowLog { window_ms, .. } => (
+                *window_ms,
+                MemoryEntry::SlidingWindowLog {
+                    request_log: VecDeque::new(),
+                },
+            ),
+        };
+        self.entries.get_or_insert_with(
+            format!("{}:{}", algorithm.get_name(), key).as_str(),
+            Duration::from_millis(ttl_ms),
+            || Arc::new(Mutex::new(new_entry)),
+        )
+    }
+    fn release_entry(
+        &self,
+        key: &str,
+        algorithm: &DistributedAlgorithm,
+    ) -> Result<(), anyhow::Error> {
+        let now = now_millis()?;
+        let entry = self.get_entry(key, algorithm, now)?;
+        let mut entry = entry.lock().map_err(|err| anyhow!(err.to_string()))?;
+        match (algorithm, &mut *entry) {
+            (
+                DistributedAlgorithm::TokenBucket { capacity, .. },
+                MemoryEntry::TokenBucket { tokens, .. },
+            ) => *tokens = (*tokens + 1.0).min(*capacity as f64),
+            (
+                DistributedAlgorithm::FixedWindow { .. },
+                MemoryEntry::FixedWindow {
+                    count,
+                    expires_at_ms,
+                },
+            ) if now < *expires_at_ms => *count = count.saturating_sub(1),
+            (
+                DistributedAlgorithm::SlidingWindowLog { .. },
+                MemoryEntry::SlidingWindowLog { request_log },
+            ) => {
+                request_log.pop_back();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    fn acquire_entry(
+        &self,
+        key: &str,
+        algorithm: &DistributedAlgorithm,
+    ) -> Result<StoreDecision, anyhow::Error> {
+        let now = now_millis()?;
+        let entry = self.get_entry(key, algorithm, now)?;
+        let mut entry = entry.lock().map_err(|err| anyhow!(err.to_string()))?;
+        match (algorithm, &mut *entry) {
+            (
+                DistributedAlgorithm::TokenBucket {
+                    capacity,
+                    rate_per_unit,
+                    unit_ms,
+                },
+                MemoryEntry::TokenBucket {
+                    tokens,
+                    last_refill_ms,
+                },
+            ) => {
+                let rate = *rate_per_unit as f64 / *unit_ms as f64;
+                *tokens = (*tokens + now.saturating_sub(*last_refill_ms) as f64 * rate)
+                    .min(*capacity as f64);
+                *last_refill_ms = now;
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Ok(StoreDecision {
+                    allowed,
+                    remaining: *tokens as u64,
+                    reset_after_ms: ((1.0 - tokens.min(1.0)) / rate).ceil() as u64,
+                })
+            }
+            (
+                DistributedAlgorithm::FixedWindow { limit, window_ms },
+                MemoryEntry::FixedWindow {
+                    count,
+                    expires_at_ms,
+                },
+            ) => {
+                if now >= *expires_at_ms {
+                    *count = 0;
+                    *expires_at_ms = now + window_ms;
+                }
+                *count += 1;
+                Ok(StoreDecision {
+                    allowed: *count <= *limit,
+                    remaining: limit.saturating_sub(*count),
+                    reset_after_ms: *expires_at_ms - now,
+                })
+            }
+            (
+                DistributedAlgorithm::SlidingWindowLog { limit, window_ms },
+                MemoryEntry::SlidingWindowLog { request_log },
+            ) => {
+                while request_log
+                    .front()
+                    .is_some_and(|oldest| *oldest + window_ms <= now)
+                {
+                    request_log.pop_front();
+                }
+                let allowed = (request_log.len() as u64) < *limit;
+                if allowed {
+                    request_log.push_back(now);
+                }
+                Ok(StoreDecision {
+                    allowed,
+                    remaining: limit.saturating_sub(request_log.len() as u64),
+                    reset_after_ms: request_log
+                        .front()
+                        .map_or(*window_ms, |oldest| oldest + window_ms - now),
+                })
+            }
+            _ => Err(anyhow!(
+                "The key {} is used by another ratelimit algorithm!",
+                key
+            )),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum FailureMode {
+    #[default]
+    Local,
+    Open,
+    Closed,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistributedRateLimit {
+    pub store: RatelimitStoreConfig,
+    #[serde(default)]
+    pub failure_mode: FailureMode,
+    #[serde(deserialize_with = "deserialize_ratelimit")]
+    pub ratelimit: Box<dyn RatelimitStrategy>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub store_instance: Arc<Mutex<Option<Arc<dyn RatelimitStore>>>>,
+}
+// The algorithm is validated when the config is loaded,the rate of the token bucket could not be 0
+// because the stores divide by it.
+fn deserialize_ratelimit<'de, D>(deserializer: D) -> Result<Box<dyn RatelimitStrategy>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ratelimit = Box::<dyn RatelimitStrategy>::deserialize(deserializer)?;
+    validate_ratelimit(ratelimit.as_ref()).map_err(serde::de::Error::custom)?;
+    Ok(ratelimit)
+}
+fn validate_ratelimit(ratelimit: &dyn RatelimitStrategy) -> Result<(), anyhow::Error> {
+    let ratelimit = ratelimit.as_any();
+    if let Some(token_bucket) = ratelimit.downcast_ref::<TokenBucketRateLimit>() {
+        if token_bucket.rate_per_unit == 0 {
+            return Err(anyhow!(
+                "The rate_per_unit of the distributed TokenBucketRateLimit could not be 0!"
+            ));
+        }
+        return Ok(());
+    }
+    if ratelimit.is::<FixedWindowRateLimit>() || ratelimit.is::<SlidingWindowLogRateLimit>() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "The DistributedRateLimit only supports the TokenBucketRateLimit,the FixedWindowRateLimit and the SlidingWindowLogRateLimit!"
+    ))
+}
+impl DistributedRateLimit {
+    fn get_store(&self) -> Result<Arc<dyn RatelimitStore>, anyhow::Error> {
+        let mut store_option = self
+            .store_instance
+            .lock()
+            .map_err(|err| anyhow!(err.to_string()))?;
+        if let Some(store) = store_option.as_ref() {
+            return Ok(store.clone());
+        }
+        let store = self.store.create_store()?;
+        *store_option = Some(store.clone());
+        Ok(store)
+    }
+    fn get_algorithm(
+        &self,
+        request_context: &RequestContext,
+    ) -> Result<Option<(String, DistributedAlgorithm)>, anyhow::Error> {
+        let ratelimit = self.ratelimit.as_any();
+        if let Some(token_bucket) = ratelimit.downcast_ref::<TokenBucketRateLimit>() {
+            if !matched(token_bucket.limit_location.clone(), request_context)? {
+                return Ok(None);
+            }
+            let key = get_limit_key(
+                &token_bucket.limit_location,
+                &token_bucket.key_by,
+                request_context,
+            )?;
+            return Ok(Some((
+                format!("token_bucket:{}", key),
+                DistributedAlgorithm::TokenBucket {
+                    capacity: token_bucket.capacity.max(0) as u64,
+                    rate_per_unit: token_bucket.rate_per_unit as u64,
+                    unit_ms: token_bucket.unit.get_million_second() as u64,
+                },
+            )));
+        }
+        if let Some(fixed_window) = ratelimit.downcast_ref::<FixedWindowRateLimit>() {
+            if !matched(fixed_window.limit_location.clone(), request_context)? {
+                return Ok(None);
+            }
+            let key = get_limit_key(
+                &fixed_window.limit_location,
+                &fixed_window.key_by,
+                request_context,
+            )?;
+            return Ok(Some((
+                format!("fixed_window:{}", key),
+                DistributedAlgorithm::FixedWindow {
+                    limit: fixed_window.rate_per_unit as u64,
+                    window_ms: fixed_window.unit.get_million_second() as u64,
+                },
+            )));
+        }
+        if let Some(sliding_window) = ratelimit.downcast_ref::<SlidingWindowLogRateLimit>() {
+            if !matched(sliding_window.limit_location.clone(), request_context)? {
+                return Ok(None);
+            }
+            let key = get_limit_key(
+                &sliding_window.limit_location,
+                &sliding_window.key_by,
+                request_context,
+            )?;
+            return Ok(Some((
+                format!("sliding_window_log:{}", key),
+                DistributedAlgorithm::SlidingWindowLog {
+                    limit: sliding_window.rate_per_unit as u64,
+                    window_ms: sliding_window.unit.get_million_second() as u64,
+                },
+            )));
+        }
+        Err(anyhow!(
+            "The DistributedRateLimit only supports the TokenBucketRateLimit,the FixedWindowRateLimit and the SlidingWindowLogRateLimit!"
+        ))
+    }
+    fn on_store_error(
+        &mut self,
+        err: anyhow::Error,
+        algorithm: &DistributedAlgorithm,
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        error!("The ratelimit store is unavailable,the error is {}.", err);
+        match self.failure_mode {
+            FailureMode::Open => Ok(None),
+            FailureMode::Closed => Ok(Some(RatelimitQuota {
+                limited: true,
+                limit: algorithm.get_limit(),
+                remaining: 0,
+                reset_after_secs: 1,
+                rule: None,
+            })),
+            FailureMode::Local => self.ratelimit.check_limit(request_context),
+        }
+    }
+}
+#[typetag::serde]
+impl RatelimitStrategy for DistributedRateLimit {
+    // The store is reached by the async io of the runtime,so blocking on it in the sync check
+    // would stall the runtime.
+    fn check_limit(
+        &mut self,
+        _request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        Err(anyhow!(
+            "The DistributedRateLimit only supports the async check!"
+        ))
+    }
+    fn check_limit_async<'a>(
+        &'a mut self,
+        request_context: &'a RequestContext,
+    ) -> BoxFuture<'a, Result<Option<RatelimitQuota>, anyhow::Error>> {
+        Box::pin(async move {
+            let (key, algorithm) = match self.get_algorithm(request_context)? {
+                Some(item) => item,
+                None => return Ok(None),
+            };
+            let store = match self.get_store() {
+                Ok(store) => store,
+                Err(err) => return self.on_store_error(err, &algorithm, request_context),
+            };
+            let decision = match store.acquire(key.as_str(), &algorithm).await {
+                Ok(decision) => decision,
+                Err(err) => return self.on_store_error(err, &algorithm, request_context),
+            };
+            Ok(Some(RatelimitQuota {
+                limited: !decision.allowed,
+                limit: algorithm.get_limit(),
+                remaining: decision.remaining,
+                reset_after_secs: decision.reset_after_ms.div_ceil(1000),
+                rule: None,
+            }))
+        })
+    }
+    fn refund(&mut self, _request_context: &RequestContext) -> Result<(), anyhow::Error> {
+        Err(anyhow!(
+            "The DistributedRateLimit only supports the async refund!"
+        ))
+    }
+    // The release error is only logged,the request is already rejected by the later rule.
+    fn refund_async<'a>(
+        &'a mut self,
+        request_context: &'a RequestContext,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let (key, algorithm) = match self.get_algorithm(request_context)? {
+                Some(item) => item,
+                None => return Ok(()),
+            };
+            let release_result = match self.get_store() {
+                Ok(store) => store.release(key.as_str(), &algorithm).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = release_result {
+                error!("Can not refund the ratelimit store,the error is {}.", err);
+                if self.failure_mode == FailureMode::Local {
+                    self.ratelimit.refund(request_context)?;
+                }
+            }
+            Ok(())
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::constants::DEFAULT_KEYED_MAP_SIZE;
+    use crate::vojo::rate_limit::{
+        IPBasedRatelimit, KeyBy, LimitLocation, MultipleRateLimit, RatelimitRule, TimeUnit,
+    };
+    use http::HeaderMap;
+
+    fn new_request_context(remote_ip: &str) -> RequestContext {
+        RequestContext {
+            remote_ip: String::from(remote_ip),
+            headers: HeaderMap::new(),
+            ..Default::default()
+        }
+    }
+    fn new_fixed_window_ratelimit(rate_per_unit: u128) -> Box<dyn RatelimitStrategy> {
+        Box::new(FixedWindowRateLimit {
+            rate_per_unit,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::ALL,
+            count_map: Default::default(),
+            lock: Default::default(),
+            key_by: Some(KeyBy::ClientIP),
+            keyed_map: Default::default(),
+        })
+    }
+    fn new_distributed_ratelimit(
+        store: RatelimitStoreConfig,
+        failure_mode: FailureMode,
+        ratelimit: Box<dyn RatelimitStrategy>,
+    ) -> DistributedRateLimit {
+        DistributedRateLimit {
+            store,
+            failure_mode,
+            ratelimit,
+            store_instance: Default::default(),
+        }
+    }
+    #[tokio::test]
+    async fn test_distributed_ratelimit_shared_store() {
+        let ratelimit1 = new_distributed_ratelimit(
+            RatelimitStoreConfig::Memory,
+            FailureMode::Closed,
+            new_fixed_window_ratelimit(3),
+        );
+        // The replicas share the same store but keep their own local state.
+        let mut ratelimit2 = ratelimit1.clone();
+        ratelimit2.ratelimit = new_fixed_window_ratelimit(3);
+        let mut ratelimit1 = ratelimit1;
+        let request_context = new_request_context("192.168.0.1");
+        assert_eq!(is_limited(&mut ratelimit1, &request_context).await, false);
+        assert_eq!(is_limited(&mut ratelimit2, &request_context).await, false);
+        assert_eq!(is_limited(&mut ratelimit1, &request_context).await, false);
+        let quota = ratelimit2
+            .check_limit_async(&request_context)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, true);
+        assert_eq!(quota.limit, 3);
+        assert_eq!(
+            is_limited(&mut ratelimit1, &new_request_context("192.168.0.2")).await,
+            false
+        );
+    }
+    #[test]
+    fn test_distributed_ratelimit_sync_error() {
+        let mut ratelimit = new_distributed_ratelimit(
+            RatelimitStoreConfig::Memory,
+            FailureMode::Local,
+            new_fixed_window_ratelimit(1),
+        );
+        let request_context = new_request_context("192.168.0.1");
+        assert_eq!(ratelimit.check_limit(&request_context).is_err(), true);
+        assert_eq!(ratelimit.should_limit(&request_context).is_err(), true);
+        assert_eq!(ratelimit.refund(&request_context).is_err(), true);
+    }
+    #[tokio::test]
+    async fn test_multiple_ratelimit_with_distributed_rule() {
+        let mut multiple_ratelimit = MultipleRateLimit {
+            rules: vec![
+                RatelimitRule {
+                    name: String::from("local"),
+                    ratelimit: new_fixed_window_ratelimit(2),
+                },
+                RatelimitRule {
+                    name: String::from("shared"),
+                    ratelimit: Box::new(new_distributed_ratelimit(
+                        RatelimitStoreConfig::Memory,
+                        FailureMode::Closed,
+                        new_fixed_window_ratelimit(1),
+                    )),
+                },
+            ],
+        };
+        let request_context = new_request_context("192.168.0.1");
+        // The sync check returns the error instead of blocking the runtime.
+        assert_eq!(
+            multiple_ratelimit.check_limit(&request_context).is_err(),
+            true
+        );
+        let quota = multiple_ratelimit
+            .check_limit_async(&request_context)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, false);
+        let quota = multiple_ratelimit
+            .check_limit_async(&request_context)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, true);
+        assert_eq!(quota.rule, Some(String::from("shared")));
+    }
+    #[tokio::test]
+    async fn test_distributed_ratelimit_refund() {
+        let mut ratelimit = new_distributed_ratelimit(
+            RatelimitStoreConfig::Memory,
+            FailureMode::Closed,
+            new_fixed_window_ratelimit(1),
+        );
+        let request_context = new_request_context("192.168.0.1");
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, false);
+        ratelimit.refund_async(&request_context).await.unwrap();
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, false);
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, true);
+    }
+    #[tokio::test]
+    async fn test_distributed_ratelimit_token_bucket() {
+        let mut ratelimit = new_distributed_ratelimit(
+            RatelimitStoreConfig::Memory,
+            FailureMode::Closed,
+            Box::new(TokenBucketRateLimit {
+                rate_per_unit: 1,
+                unit: TimeUnit::Hour,
+                capacity: 2,
+                limit_location: LimitLocation::IP(IPBasedRatelimit {
+                    value: String::from("192.168.0.1"),
+                }),
+                current_count: Default::default(),
+                lock: Default::default(),
+                last_update_time: Arc::new(std::sync::RwLock::new(SystemTime::now())),
+                key_by: None,
+                keyed_map: Default::default(),
+            }),
+        );
+        let request_context = new_request_context("192.168.0.1");
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, false);
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, false);
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, true);
+        let not_matched = ratelimit
+            .check_limit_async(&new_request_context("192.168.0.2"))
+            .await
+            .unwrap();
+        assert_eq!(not_matched.is_none(), true);
+    }
+    #[tokio::test]
+    async fn test_distributed_ratelimit_sliding_window_log() {
+        let mut ratelimit = new_distributed_ratelimit(
+            RatelimitStoreConfig::Memory,
+            FailureMode::Closed,
+            Box::new(SlidingWindowLogRateLimit {
+                rate_per_unit: 1,
+                unit: TimeUnit::Minute,
+                limit_location: LimitLocation::ALL,
+                key_by: None,
+                keyed_map: Default::default(),
+            }),
+        );
+        let request_context = new_request_context("192.168.0.1");
+        assert_eq!(is_limited(&mut ratelimit, &request_context).await, false);
+        let quota = ratelimit
+            .check_limit_async(&request_context)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, true);
+        assert_eq!(quota.reset_after_secs <= 60, true);
+    }
+    fn unreachable_redis() -> RatelimitStoreConfig {
+        RatelimitStoreConfig::Redis {
+            url: String::from("redis://127.0.0.1:1/"),
+            timeout_ms: 50,
+            key_prefix: default_key_prefix(),
+        }
+    }
+    async fn is_limited(
+        ratelimit: &mut DistributedRateLimit,
+        request_context: &RequestContext,
+    ) -> bool {
+        ratelimit
+            .check_limit_async(request_context)
+            .await
+            .unwrap()
+            .is_some_and(|quota| quota.limited)
+    }
+    #[tokio::test]
+    async fn test_distributed_ratelimit_failure_mode() {
+        let request_context = new_request_context("192.168.0.1");
+        let mut fail_open = new_distributed_ratelimit(
+            unreachable_redis(),
+            FailureMode::Open,
+            new_fixed_window_ratelimit(0),
+        );
+        let quota = fail_open.check_limit_async(&request_context).await.unwrap();
+        assert_eq!(quota, None);
+
+        let mut fail_closed = new_distributed_ratelimit(
+            unreachable_redis(),
+            FailureMode::Closed,
+            new_fixed_window_ratelimit(100),
+        );
+        assert_eq!(is_limited(&mut fail_closed, &request_context).await, true);
+
+        let mut local = new_distributed_ratelimit(
+            unreachable_redis(),
+            FailureMode::Local,
+            new_fixed_window_ratelimit(1),
+        );
+        assert_eq!(is_limited(&mut local, &request_context).await, false);
+        assert_eq!(is_limited(&mut local, &request_context).await, true);
+    }
+    #[tokio::test]
+    async fn test_memory_store_bounded() {
+        let memory_store = MemoryStore::default();
+        let algorithm = DistributedAlgorithm::FixedWindow {
+            limit: 1,
+            window_ms: 60_000,
+        };
+        for index in 0..DEFAULT_KEYED_MAP_SIZE + 10 {
+            let key = format!("192.168.0.{}", index);
+            memory_store
+                .acquire(key.as_str(), &algorithm)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            format!("{:?}", memory_store.entries),
+            format!("KeyedStore {{ len: {} }}", DEFAULT_KEYED_MAP_SIZE)
+        );
+        // The idle entry is expired after the window.
+        let algorithm = DistributedAlgorithm::FixedWindow {
+            limit: 1,
+            window_ms: 10,
+        };
+        let decision = memory_store.acquire("key", &algorithm).await.unwrap();
+        assert_eq!(decision.allowed, true);
+        std::thread::sleep(Duration::from_millis(20));
+        let decision = memory_store.acquire("key", &algorithm).await.unwrap();
+        assert_eq!(decision.allowed, true);
+    }
+    #[test]
+    fn test_distributed_ratelimit_invalid_config() {
+        let req = r#"{
+            "type": "DistributedRateLimit",
+            "store": {
+                "type": "Memory"
+            },
+            "ratelimit": {
+                "type": "TokenBucketRateLimit",
+                "rate_per_unit": 0,
+                "unit": {
+                    "type": "Second"
+                },
+                "capacity": 10,
+                "limit_location": {
+                    "type": "ALL"
+                }
+            }
+        }"#;
+        let result: Result<Box<dyn RatelimitStrategy>, _> = serde_json::from_str(req);
+        assert_eq!(result.is_err(), true);
+        let result: Result<Box<dyn RatelimitStrategy>, _> = serde_json::from_str(
+            req.replace("\"rate_per_unit\": 0", "\"rate_per_unit\": 1")
+                .as_str(),
+        );
+        assert_eq!(result.is_ok(), true);
+        let req = r#"{
+            "type": "DistributedRateLimit",
+            "store": {
+                "type": "Memory"
+            },
+            "ratelimit": {
+                "type": "SlidingWindowCounterRateLimit",
+                "rate_per_unit": 1,
+                "unit": {
+                    "type": "Second"
+                },
+                "limit_location": {
+                    "type": "ALL"
+                }
+            }
+        }"#;
+        let result: Result<Box<dyn RatelimitStrategy>, _> = serde_json::from_str(req);
+        assert_eq!(result.is_err(), true);
+    }
+    #[tokio::test]
+    async fn test_redis_store_reconnect_backoff() {
+        let redis_store = RedisStore::new(
+            "redis://127.0.0.1:1/",
+            Duration::from_millis(50),
+            default_key_prefix(),
+        )
+        .unwrap();
+        let algorithm = DistributedAlgorithm::FixedWindow {
+            limit: 1,
+            window_ms: 60_000,
+        };
+        let first_error = redis_store.acquire("key", &algorithm).await.unwrap_err();
+        assert_eq!(first_error.to_string().contains("delayed"), false);
+        // The store is not reconnected until the backoff expires.
+        let second_error = redis_store.acquire("key", &algorithm).await.unwrap_err();
+        assert_eq!(second_error.to_string().contains("delayed"), true);
+        assert_eq!(redis_store.state.lock().unwrap().failures, 1);
+    }
+    #[test]
+    fn test_distributed_ratelimit_as_any() {
+        let req = r#"{
+            "type": "DistributedRateLimit",
+            "store": {
+                "type": "Redis",
+                "url": "redis://127.0.0.1:6379/"
+            },
+            "failure_mode": "Open",
+            "ratelimit": {
+                "type": "FixedWindowRateLimit",
+                "rate_per_unit": 100,
+                "unit": {
+                    "type": "Minute"
+                },
+                "limit_location": {
+                    "type": "ALL"
+                },
+                "key_by": {
+                    "type": "ClientIP"
+                }
+            }
+        }"#;
+        let ratelimit: Box<dyn RatelimitStrategy> = serde_json::from_str(req).unwrap();
+        let distributed_ratelimit: &DistributedRateLimit =
+            match ratelimit.as_any().downcast_ref::<DistributedRateLimit>() {
+                Some(b) => b,
+                None => panic!("error!"),
+            };
+        assert_eq!(distributed_ratelimit.failure_mode, FailureMode::Open);
+        assert_eq!(
+            distributed_ratelimit.store,
+            RatelimitStoreConfig::Redis {
+                url: String::from("redis://127.0.0.1:6379/"),
+                timeout_ms: 100,
+                key_prefix: default_key_prefix(),
+            }
+        );
+    }
+    #[tokio::test]
+    async fn test_redis_store_with_local_server() {
+        // Runs only when a local redis-server is provided,e.g. SILVERWIND_TEST_REDIS_URL=redis://127.0.0.1:6379/
+        let url = match std::env::var("SILVERWIND_TEST_REDIS_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let key_prefix = format!("silverwind:test:{}:", rand::random::<u64>());
+        let redis_store =
+            RedisStore::new(url.as_str(), Duration::from_millis(500), key_prefix).unwrap();
+        let algorithms = [
+            DistributedAlgorithm::TokenBucket {
+                capacity: 2,
+                rate_per_unit: 1,
+                unit_ms: 3_600_000,
+            },
+            DistributedAlgorithm::FixedWindow {
+                limit: 2,
+                window_ms: 60_000,
+            },
+            DistributedAlgorithm::SlidingWindowLog {
+                limit: 2,
+                window_ms: 60_000,
+            },
+        ];
+        for (index, algorithm) in algorithms.iter().enumerate() {
+            let key = format!("key{}", index);
+            let decision1 = redis_store.acquire(key.as_str(), algorithm).await.unwrap();
+            assert_eq!(decision1.allowed, true);
+            assert_eq!(decision1.remaining, 1);
+            let decision2 = redis_store.acquire(key.as_str(), algorithm).await.unwrap();
+            assert_eq!(decision2.allowed, true);
+            let decision3 = redis_store.acquire(key.as_str(), algorithm).await.unwrap();
+            assert_eq!(decision3.allowed, false);
+            assert_eq!(decision3.reset_after_ms > 0, true);
+        }
+    }
+}
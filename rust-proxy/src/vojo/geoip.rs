@@ -0,0 +1,189 @@
+use crate::configuration_service::app_config_service::GLOBAL_GEOIP_DATABASES;
+use crate::vojo::ip_set::get_modified_time;
+use maxminddb::{geoip2, Reader};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+pub const COUNTRY_DATABASE: &str = "country";
+pub const ASN_DATABASE: &str = "asn";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct GeoIpConfig {
+    pub country_database: Option<String>,
+    pub asn_database: Option<String>,
+}
+pub struct GeoIpDatabase {
+    pub path: String,
+    modified_time: Option<SystemTime>,
+    reader: Reader<Vec<u8>>,
+}
+impl GeoIpDatabase {
+    pub fn open(path: &str) -> Result<Self, anyhow::Error> {
+        let modified_time = get_modified_time(path);
+        let reader = Reader::open_readfile(path)
+            .map_err(|err| anyhow!("Can not open the geoip database {},{}", path, err))?;
+        Ok(GeoIpDatabase {
+            path: String::from(path),
+            modified_time,
+            reader,
+        })
+    }
+    pub fn is_changed(&self, path: &str) -> bool {
+        self.path != path || get_modified_time(path) != self.modified_time
+    }
+    pub fn lookup_country(&self, ip: IpAddr) -> Option<String> {
+        let country: geoip2::Country = self.reader.lookup(ip.to_canonical()).ok()?;
+        country
+            .country
+            .and_then(|country| country.iso_code)
+            .map(String::from)
+    }
+    pub fn lookup_asn(&self, ip: IpAddr) -> Option<u32> {
+        let asn: geoip2::Asn = self.reader.lookup(ip.to_canonical()).ok()?;
+        asn.autonomous_system_number
+    }
+}
+impl fmt::Debug for GeoIpDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GeoIpDatabase {{ path: {} }}", self.path)
+    }
+}
+pub fn lookup_country(ip: IpAddr) -> Result<Option<String>, anyhow::Error> {
+    let database = GLOBAL_GEOIP_DATABASES.get(COUNTRY_DATABASE).ok_or(anyhow!(
+        "The country database of the geoip is not configured!"
+    ))?;
+    Ok(database.lookup_country(ip))
+}
+pub fn lookup_asn(ip: IpAddr) -> Result<Option<u32>, anyhow::Error> {
+    let database = GLOBAL_GEOIP_DATABASES
+        .get(ASN_DATABASE)
+        .ok_or(anyhow!("The asn database of the geoip is not configured!"))?;
+    Ok(database.lookup_asn(ip))
+}
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    pub enum MmdbValue {
+        String(&'static str),
+        Uint16(u16),
+        Uint32(u32),
+        Uint64(u64),
+        Map(BTreeMap<&'static str, MmdbValue>),
+        Array(Vec<MmdbValue>),
+    }
+    fn encode_control(type_number: u8, size: usize, buf: &mut Vec<u8>) {
+        if type_number <= 7 {
+            buf.push((type_number << 5) | size as u8);
+        } else {
+            buf.push(size as u8);
+            buf.push(type_number - 7);
+        }
+    }
+    fn encode_value(value: &MmdbValue, buf: &mut Vec<u8>) {
+        match value {
+            MmdbValue::String(value) => {
+                encode_control(2, value.len(), buf);
+                buf.extend_from_slice(value.as_bytes());
+            }
+            MmdbValue::Uint16(value) => {
+                encode_control(5, 2, buf);
+                buf.extend_from_slice(&value.to_be_bytes());
+            }
+            MmdbValue::Uint32(value) => {
+                encode_control(6, 4, buf);
+                buf.extend_from_slice(&value.to_be_bytes());
+            }
+            MmdbValue::Uint64(value) => {
+                encode_control(9, 8, buf);
+                buf.extend_from_slice(&value.to_be_bytes());
+            }
+            MmdbValue::Map(map) => {
+                encode_control(7, map.len(), buf);
+                for (key, value) in map.iter() {
+                    encode_value(&MmdbValue::String(key), buf);
+                    encode_value(value, buf);
+                }
+            }
+            MmdbValue::Array(items) => {
+                encode_control(11, items.len(), buf);
+                for item in items.iter() {
+                    encode_value(item, buf);
+                }
+            }
+        }
+    }
+    // Build an ipv4 database with a single node,the addresses in 0.0.0.0/1 are mapped to the
+    // record and the others are not found.
+    pub fn build_mmdb(database_type: &'static str, record: MmdbValue) -> Vec<u8> {
+        let node_count: u32 = 1;
+        let data_record = node_count + 16;
+        let mut buf = vec![];
+        buf.extend_from_slice(&data_record.to_be_bytes()[1..]);
+        buf.extend_from_slice(&node_count.to_be_bytes()[1..]);
+        buf.extend_from_slice(&[0u8; 16]);
+        encode_value(&record, &mut buf);
+        buf.extend_from_slice(b"\xAB\xCD\xEFMaxMind.com");
+        let metadata = MmdbValue::Map(BTreeMap::from([
+            ("binary_format_major_version", MmdbValue::Uint16(2)),
+            ("binary_format_minor_version", MmdbValue::Uint16(0)),
+            ("build_epoch", MmdbValue::Uint64(0)),
+            ("database_type", MmdbValue::String(database_type)),
+            ("description", MmdbValue::Map(BTreeMap::new())),
+            ("ip_version", MmdbValue::Uint16(4)),
+            ("languages", MmdbValue::Array(vec![])),
+            ("node_count", MmdbValue::Uint32(node_count)),
+            ("record_size", MmdbValue::Uint16(24)),
+        ]));
+        encode_value(&metadata, &mut buf);
+        buf
+    }
+    pub fn build_country_mmdb(iso_code: &'static str) -> Vec<u8> {
+        build_mmdb(
+            "GeoLite2-Country",
+            MmdbValue::Map(BTreeMap::from([(
+                "country",
+                MmdbValue::Map(BTreeMap::from([("iso_code", MmdbValue::String(iso_code))])),
+            )])),
+        )
+    }
+    pub fn build_asn_mmdb(asn: u32) -> Vec<u8> {
+        build_mmdb(
+            "GeoLite2-ASN",
+            MmdbValue::Map(BTreeMap::from([(
+                "autonomous_system_number",
+                MmdbValue::Uint32(asn),
+            )])),
+        )
+    }
+    #[test]
+    fn test_geoip_database_lookup() {
+        let path = std::env::temp_dir().join("silverwind_test_country.mmdb");
+        std::fs::write(&path, build_country_mmdb("AU")).unwrap();
+        let path = path.to_str().unwrap();
+        let database = GeoIpDatabase::open(path).unwrap();
+        assert_eq!(
+            database.lookup_country("1.2.3.4".parse().unwrap()),
+            Some(String::from("AU"))
+        );
+        assert_eq!(database.lookup_country("200.1.1.1".parse().unwrap()), None);
+        assert_eq!(database.lookup_asn("1.2.3.4".parse().unwrap()), None);
+        assert_eq!(database.is_changed(path), false);
+
+        let path = std::env::temp_dir().join("silverwind_test_asn.mmdb");
+        std::fs::write(&path, build_asn_mmdb(13335)).unwrap();
+        let database = GeoIpDatabase::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            database.lookup_asn("::ffff:1.2.3.4".parse().unwrap()),
+            Some(13335)
+        );
+    }
+    #[test]
+    fn test_geoip_database_open_error() {
+        let res = GeoIpDatabase::open("config/not_exist.mmdb");
+        assert_eq!(res.is_err(), true);
+    }
+}
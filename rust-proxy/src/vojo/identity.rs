@@ -0,0 +1,25 @@
+use crate::vojo::rate_limit::RatelimitStrategy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Identity {
+    pub consumer_id: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub claims: HashMap<String, String>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub ratelimit: Option<Box<dyn RatelimitStrategy>>,
+}
+impl Identity {
+    pub fn new(consumer_id: String) -> Self {
+        Identity {
+            consumer_id,
+            ..Default::default()
+        }
+    }
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|item| item == group)
+    }
+}
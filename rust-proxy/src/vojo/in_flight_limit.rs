@@ -0,0 +1,462 @@
+use crate::vojo::rate_limit::KeyBy;
+use crate::vojo::request_context::RequestContext;
+use futures::stream;
+use hyper::body::HttpBody;
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PerKeyInFlightLimit {
+    pub key_by: KeyBy,
+    pub max_in_flight: usize,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum AdaptiveLimit {
+    // Increase the limit by one for every fast response and multiply it by the backoff_ratio
+    // for every slow response.
+    AIMD {
+        min_limit: usize,
+        max_limit: usize,
+        latency_threshold_ms: u64,
+        #[serde(default = "default_backoff_ratio")]
+        backoff_ratio: f64,
+    },
+    // Scale the limit by the ratio between the lowest latency and the current latency.
+    Gradient {
+        min_limit: usize,
+        max_limit: usize,
+        #[serde(default = "default_smoothing")]
+        smoothing: f64,
+    },
+}
+fn default_backoff_ratio() -> f64 {
+    0.9
+}
+fn default_smoothing() -> f64 {
+    0.2
+}
+fn default_queue_timeout_ms() -> u64 {
+    1000
+}
+#[derive(Debug, Default)]
+struct InFlightCore {
+    in_flight: usize,
+    queued: usize,
+    limit: Option<f64>,
+    min_latency_ms: Option<f64>,
+    key_in_flight: HashMap<String, usize>,
+}
+#[derive(Debug, Default)]
+pub struct InFlightState {
+    core: Mutex<InFlightCore>,
+    notify: Notify,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InFlightLimit {
+    pub max_in_flight: usize,
+    #[serde(default)]
+    pub per_key: Option<PerKeyInFlightLimit>,
+    #[serde(default)]
+    pub max_queue: usize,
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveLimit>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub state: Arc<InFlightState>,
+}
+pub struct InFlightPermit {
+    in_flight_limit: InFlightLimit,
+    key: Option<String>,
+    start_time: Instant,
+}
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        let latency_ms = self.start_time.elapsed().as_millis() as f64;
+        if let Err(err) = self.in_flight_limit.release(self.key.take(), latency_ms) {
+            error!(
+                "Cause error when releasing the in-flight permit,the error is {}.",
+                err
+            );
+        }
+    }
+}
+impl InFlightPermit {
+    // Hold the permit until the body is sent or broken,so the latency of the adaptive limit
+    // includes streaming the response body.
+    pub fn attach_to_body(self, body: Body) -> Body {
+        if body.is_end_stream() {
+            return body;
+        }
+        let body_stream = stream::unfold(Some((body, self)), |state| async move {
+            let (mut body, permit) = state?;
+            match body.data().await {
+                Some(Ok(chunk)) => Some((Ok(chunk), Some((body, permit)))),
+                // The permit is dropped with the state when the body is broken or finished.
+                Some(Err(err)) => Some((Err(err), None)),
+                None => None,
+            }
+        });
+        Body::wrap_stream(body_stream)
+    }
+}
+impl InFlightLimit {
+    fn current_limit(&self, core: &mut InFlightCore) -> usize {
+        let limit = *core.limit.get_or_insert(self.max_in_flight as f64);
+        (limit.floor() as usize).max(1)
+    }
+    fn try_acquire(&self, key: &Option<String>) -> Result<bool, anyhow::Error> {
+        let mut core = self
+            .state
+            .core
+            .lock()
+            .map_err(|err| anyhow!(err.to_string()))?;
+        if core.in_flight >= self.current_limit(&mut core) {
+            return Ok(false);
+        }
+        if let (Some(key), Some(per_key)) = (key, &self.per_key) {
+            let key_in_flight = core.key_in_flight.get(key).copied().unwrap_or(0);
+            if key_in_flight >= per_key.max_in_flight {
+                return Ok(false);
+            }
+            core.key_in_flight.insert(key.clone(), key_in_flight + 1);
+        }
+        core.in_flight += 1;
+        Ok(true)
+    }
+    pub async fn acquire(
+        &self,
+        request_context: &RequestContext,
+    ) -> Result<Option<InFlightPermit>, anyhow::Error> {
+        let key = match &self.per_key {
+            Some(per_key) => Some(per_key.key_by.get_key(request_context)?),
+            None => None,
+        };
+        let deadline = Instant::now() + Duration::from_millis(self.queue_timeout_ms);
+        let mut queued = false;
+        let result = loop {
+            let notified = self.state.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.try_acquire(&key)? {
+                break Some(self.new_permit(key.clone()));
+            }
+            if !queued {
+                let mut core = self
+                    .state
+                    .core
+                    .lock()
+                    .map_err(|err| anyhow!(err.to_string()))?;
+                if core.queued >= self.max_queue {
+                    break None;
+                }
+                core.queued += 1;
+                queued = true;
+            }
+            let now = Instant::now();
+            if now >= deadline
+                || tokio::time::timeout(deadline - now, notified)
+                    .await
+                    .is_err()
+            {
+                break None;
+            }
+        };
+        if queued {
+            let mut core = self
+                .state
+                .core
+                .lock()
+                .map_err(|err| anyhow!(err.to_string()))?;
+            core.queued -= 1;
+        }
+        Ok(result)
+    }
+    fn new_permit(&self, key: Option<String>) -> InFlightPermit {
+        InFlightPermit {
+            in_flight_limit: self.clone(),
+            key,
+            start_time: Instant::now(),
+        }
+    }
+    fn release(&self, key: Option<String>, latency_ms: f64) -> Result<(), anyhow::Error> {
+        let mut core = self
+            .state
+            .core
+            .lock()
+            .map_err(|err| anyhow!(err.to_string()))?;
+        core.in_flight = core.in_flight.saturating_sub(1);
+        if let Some(key) = key {
+            let key_in_flight = core.key_in_flight.get(&key).copied().unwrap_or(0);
+            if key_in_flight <= 1 {
+                core.key_in_flight.remove(&key);
+            } else {
+                core.key_in_flight.insert(key, key_in_flight - 1);
+            }
+        }
+        if let Some(adaptive) = &self.adaptive {
+            let limit = self.current_limit(&mut core) as f64;
+            let new_limit = match adaptive {
+                AdaptiveLimit::AIMD {
+                    min_limit,
+                    max_limit,
+                    latency_threshold_ms,
+                    backoff_ratio,
+                } => {
+                    let new_limit = if latency_ms > *latency_threshold_ms as f64 {
+                        limit * backoff_ratio
+                    } else {
+                        limit + 1.0
+                    };
+                    new_limit.clamp(*min_limit as f64, *max_limit as f64)
+                }
+                AdaptiveLimit::Gradient {
+                    min_limit,
+                    max_limit,
+                    smoothing,
+                } => {
+                    let sample_ms = latency_ms.max(1.0);
+                    let min_latency_ms = core.min_latency_ms.unwrap_or(sample_ms).min(sample_ms);
+                    core.min_latency_ms = Some(min_latency_ms);
+                    let gradient = (min_latency_ms / sample_ms).clamp(0.5, 1.0);
+                    let target = limit * gradient + limit.sqrt();
+                    (limit * (1.0 - smoothing) + target * smoothing)
+                        .clamp(*min_limit as f64, *max_limit as f64)
+                }
+            };
+            core.limit = Some(new_limit);
+        }
+        drop(core);
+        self.state.notify.notify_waiters();
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vojo::app_config::ApiService;
+    use http::HeaderMap;
+    use tokio::runtime::Runtime;
+
+    fn new_request_context(remote_ip: &str) -> RequestContext {
+        RequestContext {
+            remote_ip: String::from(remote_ip),
+            headers: HeaderMap::new(),
+            ..Default::default()
+        }
+    }
+    fn get_limit(in_flight_limit: &InFlightLimit) -> usize {
+        let mut core = in_flight_limit.state.core.lock().unwrap();
+        in_flight_limit.current_limit(&mut core)
+    }
+    fn new_in_flight_limit(max_in_flight: usize, max_queue: usize) -> InFlightLimit {
+        InFlightLimit {
+            max_in_flight,
+            per_key: None,
+            max_queue,
+            queue_timeout_ms: 50,
+            adaptive: None,
+            state: Default::default(),
+        }
+    }
+    #[test]
+    fn test_in_flight_limit_reject() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let in_flight_limit = new_in_flight_limit(1, 0);
+            let request_context = new_request_context("192.168.0.1");
+            let permit1 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit1.is_some(), true);
+            let permit2 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit2.is_none(), true);
+            drop(permit1);
+            let permit3 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit3.is_some(), true);
+        });
+    }
+    #[test]
+    fn test_in_flight_limit_queue() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut in_flight_limit = new_in_flight_limit(1, 1);
+            in_flight_limit.queue_timeout_ms = 1000;
+            let request_context = new_request_context("192.168.0.1");
+            let permit1 = in_flight_limit.acquire(&request_context).await.unwrap();
+            let in_flight_limit_clone = in_flight_limit.clone();
+            let task = tokio::spawn(async move {
+                in_flight_limit_clone
+                    .acquire(&new_request_context("192.168.0.1"))
+                    .await
+                    .unwrap()
+                    .is_some()
+            });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            // The queue is full,so the third request is rejected at once.
+            let permit3 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit3.is_none(), true);
+            drop(permit1);
+            assert_eq!(task.await.unwrap(), true);
+        });
+    }
+    #[test]
+    fn test_in_flight_limit_queue_timeout() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let in_flight_limit = new_in_flight_limit(1, 10);
+            let request_context = new_request_context("192.168.0.1");
+            let _permit1 = in_flight_limit.acquire(&request_context).await.unwrap();
+            let start_time = Instant::now();
+            let permit2 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit2.is_none(), true);
+            assert_eq!(start_time.elapsed() >= Duration::from_millis(50), true);
+        });
+    }
+    #[test]
+    fn test_in_flight_limit_per_key() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut in_flight_limit = new_in_flight_limit(10, 0);
+            in_flight_limit.per_key = Some(PerKeyInFlightLimit {
+                key_by: KeyBy::ClientIP,
+                max_in_flight: 1,
+            });
+            let _permit1 = in_flight_limit
+                .acquire(&new_request_context("192.168.0.1"))
+                .await
+                .unwrap();
+            let permit2 = in_flight_limit
+                .acquire(&new_request_context("192.168.0.1"))
+                .await
+                .unwrap();
+            assert_eq!(permit2.is_none(), true);
+            let permit3 = in_flight_limit
+                .acquire(&new_request_context("192.168.0.2"))
+                .await
+                .unwrap();
+            assert_eq!(permit3.is_some(), true);
+        });
+    }
+    #[test]
+    fn test_in_flight_permit_attach_to_body() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let in_flight_limit = new_in_flight_limit(1, 0);
+            let request_context = new_request_context("192.168.0.1");
+            let permit = in_flight_limit.acquire(&request_context).await.unwrap();
+            let (mut sender, body) = Body::channel();
+            let mut body = permit.unwrap().attach_to_body(body);
+            sender.send_data("hello".into()).await.unwrap();
+            assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+            // The permit is held while the body is still streaming.
+            let permit2 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit2.is_none(), true);
+            drop(sender);
+            assert_eq!(body.data().await.is_none(), true);
+            let permit3 = in_flight_limit.acquire(&request_context).await.unwrap();
+            assert_eq!(permit3.is_some(), true);
+        });
+    }
+    #[test]
+    fn test_in_flight_limit_aimd() {
+        let mut in_flight_limit = new_in_flight_limit(10, 0);
+        in_flight_limit.adaptive = Some(AdaptiveLimit::AIMD {
+            min_limit: 2,
+            max_limit: 11,
+            latency_threshold_ms: 100,
+            backoff_ratio: 0.5,
+        });
+        in_flight_limit.release(None, 10.0).unwrap();
+        assert_eq!(get_limit(&in_flight_limit), 11);
+        in_flight_limit.release(None, 10.0).unwrap();
+        assert_eq!(get_limit(&in_flight_limit), 11);
+        in_flight_limit.release(None, 200.0).unwrap();
+        assert_eq!(get_limit(&in_flight_limit), 5);
+        in_flight_limit.release(None, 200.0).unwrap();
+        in_flight_limit.release(None, 200.0).unwrap();
+        assert_eq!(get_limit(&in_flight_limit), 2);
+    }
+    #[test]
+    fn test_in_flight_limit_gradient() {
+        let mut in_flight_limit = new_in_flight_limit(20, 0);
+        in_flight_limit.adaptive = Some(AdaptiveLimit::Gradient {
+            min_limit: 5,
+            max_limit: 100,
+            smoothing: 1.0,
+        });
+        in_flight_limit.release(None, 10.0).unwrap();
+        let limit1 = get_limit(&in_flight_limit);
+        assert_eq!(limit1 > 20, true);
+        for _ in 0..10 {
+            in_flight_limit.release(None, 1000.0).unwrap();
+        }
+        assert_eq!(get_limit(&in_flight_limit) < limit1, true);
+    }
+    #[test]
+    fn test_in_flight_limit_from_config() {
+        let req = r#"[
+            {
+              "listen_port": 4486,
+              "service_config": {
+                "server_type": "HTTP",
+                "cert_str": null,
+                "key_str": null,
+                "routes": [
+                  {
+                    "matcher": {
+                      "prefix": "ss",
+                      "prefix_rewrite": "ssss"
+                    },
+                    "allow_deny_list": null,
+                    "in_flight_limit": {
+                      "max_in_flight": 100,
+                      "max_queue": 10,
+                      "per_key": {
+                        "key_by": {
+                          "type": "ClientIP"
+                        },
+                        "max_in_flight": 5
+                      },
+                      "adaptive": {
+                        "type": "AIMD",
+                        "min_limit": 10,
+                        "max_limit": 200,
+                        "latency_threshold_ms": 500
+                      }
+                    },
+                    "route_cluster": {
+                      "type": "PollRoute",
+                      "routes": [
+                        {
+                            "base_route": {
+                                "endpoint": "http://localhost:8000",
+                                "try_file": null
+                            }
+                        }
+                      ]
+                    }
+                  }
+                ]
+              }
+            }
+          ]"#;
+        let api_services: Vec<ApiService> = serde_json::from_slice(req.as_bytes()).unwrap();
+        let route = api_services[0].service_config.routes[0].clone();
+        let in_flight_limit = route.in_flight_limit.unwrap();
+        assert_eq!(in_flight_limit.max_in_flight, 100);
+        assert_eq!(in_flight_limit.queue_timeout_ms, 1000);
+        assert_eq!(
+            in_flight_limit.adaptive,
+            Some(AdaptiveLimit::AIMD {
+                min_limit: 10,
+                max_limit: 200,
+                latency_threshold_ms: 500,
+                backoff_ratio: 0.9,
+            })
+        );
+    }
+}
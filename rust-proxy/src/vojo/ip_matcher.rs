@@ -0,0 +1,104 @@
+use ipnet::{IpNet, Ipv4Net};
+use serde::{Deserialize, Deserializer};
+use std::net::IpAddr;
+
+// The ipv4-mapped ipv6 address is normalized to the ipv4 address,so "::ffff:10.0.0.1"
+// and "10.0.0.1" are the same client.
+pub fn parse_ip(value: &str) -> Result<IpAddr, anyhow::Error> {
+    let ip = value
+        .trim()
+        .parse::<IpAddr>()
+        .map_err(|err| anyhow!("Invalid ip address '{}': {}", value, err))?;
+    Ok(ip.to_canonical())
+}
+pub fn parse_ip_net(value: &str) -> Result<IpNet, anyhow::Error> {
+    let ip_net = value
+        .trim()
+        .parse::<IpNet>()
+        .map_err(|err| anyhow!("Invalid ip range '{}': {}", value, err))?;
+    match ip_net {
+        IpNet::V6(ipv6_net) if ipv6_net.prefix_len() >= 96 => {
+            match ipv6_net.addr().to_ipv4_mapped() {
+                Some(ipv4) => Ipv4Net::new(ipv4, ipv6_net.prefix_len() - 96)
+                    .map(IpNet::V4)
+                    .map_err(|err| anyhow!(err.to_string())),
+                None => Ok(ip_net),
+            }
+        }
+        _ => Ok(ip_net),
+    }
+}
+// The value is a single address or a cidr of either family.
+pub fn validate_ip_value(value: &str) -> Result<(), anyhow::Error> {
+    if value.contains('/') {
+        parse_ip_net(value)?;
+    } else {
+        parse_ip(value)?;
+    }
+    Ok(())
+}
+pub fn ip_matches(value: &str, client_ip: &str) -> Result<bool, anyhow::Error> {
+    ip_value_contains(value, &parse_ip(client_ip)?)
+}
+pub fn ip_value_contains(value: &str, ip: &IpAddr) -> Result<bool, anyhow::Error> {
+    if value.contains('/') {
+        return Ok(parse_ip_net(value)?.contains(ip));
+    }
+    Ok(parse_ip(value)? == *ip)
+}
+pub fn deserialize_ip_value<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    validate_ip_value(value.as_str()).map_err(serde::de::Error::custom)?;
+    Ok(value)
+}
+pub fn deserialize_ip_net_value<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_ip_net(value.as_str()).map_err(serde::de::Error::custom)?;
+    Ok(value)
+}
+pub fn deserialize_ip_value_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = Vec::<String>::deserialize(deserializer)?;
+    for value in values.iter() {
+        validate_ip_value(value.as_str()).map_err(serde::de::Error::custom)?;
+    }
+    Ok(values)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_matches_ipv6() {
+        assert_eq!(ip_matches("2001:db8::1", "2001:db8:0:0::1").unwrap(), true);
+        assert_eq!(
+            ip_matches("2001:db8::/32", "2001:db8:ffff::1").unwrap(),
+            true
+        );
+        assert_eq!(ip_matches("2001:db8::/32", "2001:db9::1").unwrap(), false);
+        assert_eq!(ip_matches("192.168.0.0/16", "2001:db8::1").unwrap(), false);
+    }
+    #[test]
+    fn test_ip_matches_ipv4_mapped() {
+        assert_eq!(ip_matches("10.0.0.1", "::ffff:10.0.0.1").unwrap(), true);
+        assert_eq!(ip_matches("10.0.0.0/8", "::ffff:10.1.2.3").unwrap(), true);
+        assert_eq!(ip_matches("::ffff:10.0.0.0/104", "10.1.2.3").unwrap(), true);
+        assert_eq!(ip_matches("::ffff:10.0.0.1", "10.0.0.1").unwrap(), true);
+    }
+    #[test]
+    fn test_validate_ip_value_error() {
+        assert_eq!(validate_ip_value("192.168.0.256").is_err(), true);
+        assert_eq!(validate_ip_value("192.168.0.1/33").is_err(), true);
+        assert_eq!(validate_ip_value("2001:db8::/129").is_err(), true);
+        assert_eq!(validate_ip_value("localhost").is_err(), true);
+        assert_eq!(ip_matches("192.168.0.1", "test").is_err(), true);
+    }
+}
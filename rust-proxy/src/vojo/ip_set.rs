@@ -0,0 +1,137 @@
+use crate::vojo::ip_matcher::{deserialize_ip_value_list, parse_ip, parse_ip_net};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use iprange::IpRange;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct IpSet {
+    pub name: String,
+    #[serde(default, deserialize_with = "deserialize_ip_value_list")]
+    pub cidrs: Vec<String>,
+    // The file contains one address or cidr per line and the line started with '#' is ignored.
+    #[serde(default)]
+    pub file: Option<String>,
+}
+// The ranges are merged into the prefix tries once when the ip set is loaded.
+#[derive(Debug)]
+pub struct IpSetMatcher {
+    pub ip_set: IpSet,
+    file_modified_time: Option<SystemTime>,
+    ipv4_range: IpRange<Ipv4Net>,
+    ipv6_range: IpRange<Ipv6Net>,
+}
+pub fn get_modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+impl IpSetMatcher {
+    pub fn new(ip_set: IpSet) -> Result<Self, anyhow::Error> {
+        let mut values = ip_set.cidrs.clone();
+        let mut file_modified_time = None;
+        if let Some(file) = &ip_set.file {
+            file_modified_time = get_modified_time(file);
+            let content = fs::read_to_string(file)
+                .map_err(|err| anyhow!("Can not read the ip set file {},{}", file, err))?;
+            values.extend(
+                content
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from),
+            );
+        }
+        let mut ipv4_range = IpRange::new();
+        let mut ipv6_range = IpRange::new();
+        for value in values.iter() {
+            let ip_net = if value.contains('/') {
+                parse_ip_net(value)?
+            } else {
+                IpNet::from(parse_ip(value)?)
+            };
+            match ip_net {
+                IpNet::V4(ipv4_net) => {
+                    ipv4_range.add(ipv4_net);
+                }
+                IpNet::V6(ipv6_net) => {
+                    ipv6_range.add(ipv6_net);
+                }
+            }
+        }
+        ipv4_range.simplify();
+        ipv6_range.simplify();
+        Ok(IpSetMatcher {
+            ip_set,
+            file_modified_time,
+            ipv4_range,
+            ipv6_range,
+        })
+    }
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match ip.to_canonical() {
+            IpAddr::V4(ipv4) => self.ipv4_range.contains(&ipv4),
+            IpAddr::V6(ipv6) => self.ipv6_range.contains(&ipv6),
+        }
+    }
+    pub fn is_changed(&self, ip_set: &IpSet) -> bool {
+        if self.ip_set != *ip_set {
+            return true;
+        }
+        match &ip_set.file {
+            Some(file) => get_modified_time(file) != self.file_modified_time,
+            None => false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_ip_set_matcher_contains() {
+        let ip_set = IpSet {
+            name: String::from("office"),
+            cidrs: vec![
+                String::from("10.0.0.0/8"),
+                String::from("192.168.1.1"),
+                String::from("2001:db8::/32"),
+            ],
+            file: None,
+        };
+        let ip_set_matcher = IpSetMatcher::new(ip_set.clone()).unwrap();
+        let contains = |ip: &str| ip_set_matcher.contains(&ip.parse().unwrap());
+        assert_eq!(contains("10.1.2.3"), true);
+        assert_eq!(contains("::ffff:10.1.2.3"), true);
+        assert_eq!(contains("192.168.1.1"), true);
+        assert_eq!(contains("192.168.1.2"), false);
+        assert_eq!(contains("2001:db8::1"), true);
+        assert_eq!(ip_set_matcher.is_changed(&ip_set), false);
+    }
+    #[test]
+    fn test_ip_set_matcher_reload_file() {
+        let file = env::temp_dir().join("silverwind_test_ip_set.txt");
+        std::fs::write(&file, "# office\n10.0.0.0/8\n\n2001:db8::1\n").unwrap();
+        let ip_set = IpSet {
+            name: String::from("office"),
+            cidrs: vec![],
+            file: Some(String::from(file.to_str().unwrap())),
+        };
+        let ip_set_matcher = IpSetMatcher::new(ip_set.clone()).unwrap();
+        assert_eq!(ip_set_matcher.contains(&"10.0.0.1".parse().unwrap()), true);
+        assert_eq!(
+            ip_set_matcher.contains(&"2001:db8::1".parse().unwrap()),
+            true
+        );
+
+        let modified_time = SystemTime::now() + std::time::Duration::from_secs(10);
+        let fd = std::fs::File::options().write(true).open(&file).unwrap();
+        fd.set_modified(modified_time).unwrap();
+        assert_eq!(ip_set_matcher.is_changed(&ip_set), true);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(IpSetMatcher::new(ip_set).is_err(), true);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::constants::constants::DEFAULT_KEYED_MAP_SIZE;
+use lru::LruCache;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct KeyedEntry<T> {
+    value: T,
+    last_access: Instant,
+}
+// A bounded map which keeps the state of every dynamic key,the least recently used key
+// is evicted when the map is full and the key idle longer than the ttl is recreated.
+pub struct KeyedStore<T> {
+    cache: Mutex<LruCache<String, KeyedEntry<T>>>,
+}
+impl<T: Clone> KeyedStore<T> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        KeyedStore {
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+    pub fn get_or_insert_with<F>(&self, key: &str, ttl: Duration, f: F) -> Result<T, anyhow::Error>
+    where
+        F: FnOnce() -> T,
+    {
+        let mut cache = self.cache.lock().map_err(|err| anyhow!(err.to_string()))?;
+        let now = Instant::now();
+        if let Some(entry) = cache.get_mut(key) {
+            if now.duration_since(entry.last_access) <= ttl {
+                entry.last_access = now;
+                return Ok(entry.value.clone());
+            }
+        }
+        let value = f();
+        cache.put(
+            String::from(key),
+            KeyedEntry {
+                value: value.clone(),
+                last_access: now,
+            },
+        );
+        Ok(value)
+    }
+}
+impl<T: Clone> Default for KeyedStore<T> {
+    fn default() -> Self {
+        KeyedStore::new(DEFAULT_KEYED_MAP_SIZE)
+    }
+}
+impl<T> fmt::Debug for KeyedStore<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.cache.lock().map(|cache| cache.len()).unwrap_or(0);
+        write!(f, "KeyedStore {{ len: {} }}", len)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time};
+
+    #[test]
+    fn test_keyed_store_evict_least_recently_used() {
+        let keyed_store: KeyedStore<i32> = KeyedStore::new(2);
+        let ttl = Duration::from_secs(60);
+        keyed_store.get_or_insert_with("a", ttl, || 1).unwrap();
+        keyed_store.get_or_insert_with("b", ttl, || 2).unwrap();
+        keyed_store.get_or_insert_with("a", ttl, || 3).unwrap();
+        keyed_store.get_or_insert_with("c", ttl, || 4).unwrap();
+        assert_eq!(keyed_store.cache.lock().unwrap().len(), 2);
+        assert_eq!(keyed_store.get_or_insert_with("a", ttl, || 5).unwrap(), 1);
+        assert_eq!(keyed_store.get_or_insert_with("b", ttl, || 6).unwrap(), 6);
+    }
+    #[test]
+    fn test_keyed_store_expired() {
+        let keyed_store: KeyedStore<i32> = KeyedStore::new(2);
+        let ttl = Duration::from_millis(10);
+        keyed_store.get_or_insert_with("a", ttl, || 1).unwrap();
+        thread::sleep(time::Duration::from_millis(20));
+        assert_eq!(keyed_store.get_or_insert_with("a", ttl, || 2).unwrap(), 2);
+    }
+}
@@ -1,8 +1,22 @@
+pub mod acme;
 pub mod api_service_manager;
 pub mod app_config;
 
 pub mod allow_deny_ip;
 pub mod authentication;
+pub mod client_ip;
+pub mod distributed_rate_limit;
+pub mod geoip;
+pub mod identity;
+pub mod in_flight_limit;
+pub mod ip_matcher;
+pub mod ip_set;
+pub mod keyed_store;
 pub mod rate_limit;
+pub mod request_context;
+pub mod request_limit;
 pub mod route;
+pub mod tls_policy;
+pub mod upstream_tls;
 pub mod vojo;
+pub mod waf;
@@ -1,27 +1,75 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::constants::constants::DEFAULT_FIXEDWINDOW_MAP_SIZE;
+use crate::vojo::ip_matcher::{
+    deserialize_ip_net_value, deserialize_ip_value, ip_matches, parse_ip, parse_ip_net,
+};
+use crate::vojo::keyed_store::KeyedStore;
+use crate::vojo::request_context::RequestContext;
 use core::fmt::Debug;
 use dashmap::DashMap;
 use dyn_clone::DynClone;
-use http::HeaderMap;
-use http::HeaderValue;
-use ipnet::Ipv4Net;
-use iprange::IpRange;
+use futures::future;
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::net::Ipv4Addr;
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicIsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
+use std::time::{Duration, Instant};
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RatelimitQuota {
+    pub limited: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_after_secs: u64,
+    pub rule: Option<String>,
+}
+impl RatelimitQuota {
+    // The limited quota is the strictest one,otherwise the quota with the fewest remaining requests.
+    pub fn stricter(self, other: Option<RatelimitQuota>) -> RatelimitQuota {
+        match other {
+            Some(other) if (other.limited, self.remaining) > (self.limited, other.remaining) => {
+                other
+            }
+            _ => self,
+        }
+    }
+}
 #[typetag::serde(tag = "type")]
 pub trait RatelimitStrategy: Sync + Send + DynClone {
-    fn should_limit(
+    fn check_limit(
         &mut self,
-        headers: HeaderMap<HeaderValue>,
-        remote_ip: String,
-    ) -> Result<bool, anyhow::Error>;
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error>;
+
+    // The proxy checks the limit asynchronously,so the strategies with the remote store do not
+    // block the runtime.
+    fn check_limit_async<'a>(
+        &'a mut self,
+        request_context: &'a RequestContext,
+    ) -> BoxFuture<'a, Result<Option<RatelimitQuota>, anyhow::Error>> {
+        let result = self.check_limit(request_context);
+        Box::pin(future::ready(result))
+    }
+    // Give back the request consumed by the last check,it is called when a later rule rejects the
+    // request so that the rejected request is not charged.
+    fn refund(&mut self, request_context: &RequestContext) -> Result<(), anyhow::Error>;
+
+    fn refund_async<'a>(
+        &'a mut self,
+        request_context: &'a RequestContext,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        let result = self.refund(request_context);
+        Box::pin(future::ready(result))
+    }
+
+    fn should_limit(&mut self, request_context: &RequestContext) -> Result<bool, anyhow::Error> {
+        Ok(self
+            .check_limit(request_context)?
+            .is_some_and(|quota| quota.limited))
+    }
 
     fn get_debug(&self) -> String {
         String::from("debug")
@@ -38,6 +86,7 @@ impl Debug for dyn RatelimitStrategy {
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IPBasedRatelimit {
+    #[serde(deserialize_with = "deserialize_ip_value")]
     pub value: String,
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -52,6 +101,11 @@ impl HeaderBasedRatelimit {
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IpRangeBasedRatelimit {
+    #[serde(deserialize_with = "deserialize_ip_net_value")]
+    pub value: String,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsumerBasedRatelimit {
     pub value: String,
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -60,16 +114,48 @@ pub enum LimitLocation {
     IP(IPBasedRatelimit),
     Header(HeaderBasedRatelimit),
     IPRANGE(IpRangeBasedRatelimit),
+    Consumer(ConsumerBasedRatelimit),
+    ALL,
 }
 impl LimitLocation {
     pub fn get_key(&self) -> String {
         match self {
+            LimitLocation::ALL => String::from("all"),
             LimitLocation::Header(headers) => headers.get_key(),
             LimitLocation::IP(ip) => ip.value.clone(),
             LimitLocation::IPRANGE(ip_range) => ip_range.value.clone(),
+            LimitLocation::Consumer(consumer) => format!("consumer:{}", consumer.value),
         }
     }
 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum KeyBy {
+    ClientIP,
+    Header { name: String },
+    Consumer,
+    Path,
+}
+impl KeyBy {
+    pub fn get_key(&self, request_context: &RequestContext) -> Result<String, anyhow::Error> {
+        let key = match self {
+            KeyBy::ClientIP => format!("ip:{}", request_context.remote_ip),
+            KeyBy::Header { name } => format!(
+                "header:{}:{}",
+                name,
+                request_context
+                    .get_header(name.as_str())?
+                    .unwrap_or_default()
+            ),
+            KeyBy::Consumer => format!(
+                "consumer:{}",
+                request_context.get_consumer_id().unwrap_or_default()
+            ),
+            KeyBy::Path => format!("path:{}", request_context.path),
+        };
+        Ok(key)
+    }
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum TimeUnit {
@@ -89,6 +175,9 @@ impl TimeUnit {
             Self::Day => 86_400_000,
         }
     }
+    fn get_duration(&self) -> Duration {
+        Duration::from_millis(self.get_million_second() as u64)
+    }
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenBucketRateLimit {
@@ -102,6 +191,15 @@ pub struct TokenBucketRateLimit {
     pub lock: Arc<Mutex<i32>>,
     #[serde(skip_serializing, skip_deserializing, default = "default_time")]
     pub last_update_time: Arc<RwLock<SystemTime>>,
+    #[serde(default)]
+    pub key_by: Option<KeyBy>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub keyed_map: Arc<KeyedStore<Arc<Mutex<TokenBucketState>>>>,
+}
+#[derive(Debug)]
+pub struct TokenBucketState {
+    tokens: f64,
+    last_refill_time: Instant,
 }
 
 fn default_time() -> Arc<RwLock<SystemTime>> {
@@ -124,52 +222,98 @@ fn get_time_key(time_unit: TimeUnit) -> Result<String, anyhow::Error> {
     Ok(key_u64.to_string())
 }
 
-fn matched(
+fn get_window_reset_secs(time_unit: TimeUnit) -> Result<u64, anyhow::Error> {
+    let in_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| anyhow!(err.to_string()))?
+        .as_millis();
+    let unit_ms = time_unit.get_million_second();
+    let left_ms = unit_ms - in_ms % unit_ms;
+    Ok(left_ms.div_ceil(1000) as u64)
+}
+pub fn matched(
     limit_location: LimitLocation,
-    headers: HeaderMap<HeaderValue>,
-    remote_ip: String,
+    request_context: &RequestContext,
 ) -> Result<bool, anyhow::Error> {
+    let remote_ip = request_context.remote_ip.clone();
     return match limit_location {
         LimitLocation::IP(ip_based_ratelimit) => {
-            Ok(ip_based_ratelimit.clone().value.clone() == remote_ip)
+            ip_matches(ip_based_ratelimit.value.as_str(), remote_ip.as_str())
         }
         LimitLocation::Header(header_based_ratelimit) => {
-            if !headers.contains_key(header_based_ratelimit.key.clone()) {
+            let header_value_option =
+                request_context.get_header(header_based_ratelimit.key.as_str())?;
+            if header_value_option.is_none() {
                 return Ok(false);
             }
-            let header_value = headers.get(header_based_ratelimit.key.clone()).unwrap();
-            let header_value_str = header_value
-                .to_str()
-                .map_err(|err| anyhow!(err.to_string()))?;
-
-            return Ok(header_value_str == header_based_ratelimit.value);
+            return Ok(header_value_option.unwrap() == header_based_ratelimit.value);
         }
+        LimitLocation::Consumer(consumer_based_ratelimit) => {
+            Ok(request_context.get_consumer_id() == Some(consumer_based_ratelimit.value))
+        }
+        LimitLocation::ALL => Ok(true),
         LimitLocation::IPRANGE(ip_range_based_ratelimit) => {
             if !ip_range_based_ratelimit.value.contains("/") {
                 return Err(anyhow!("The Ip Range should contain '/'."));
             }
-            let ip_range: IpRange<Ipv4Net> = [ip_range_based_ratelimit.value.clone()]
-                .iter()
-                .map(|s| s.parse().unwrap())
-                .collect();
-            let source_ip = remote_ip
-                .clone()
-                .parse::<Ipv4Addr>()
-                .map_err(|err| anyhow!(err.to_string()))?;
-            return Ok(ip_range.contains(&source_ip));
+            let source_ip = parse_ip(remote_ip.as_str())?;
+            Ok(parse_ip_net(ip_range_based_ratelimit.value.as_str())?.contains(&source_ip))
         }
     };
 }
+impl TokenBucketRateLimit {
+    fn get_keyed_state(&self, key: &str) -> Result<Arc<Mutex<TokenBucketState>>, anyhow::Error> {
+        let capacity = self.capacity.max(0) as f64;
+        let rate_per_millis = self.rate_per_unit as f64 / self.unit.get_million_second() as f64;
+        let refill_all =
+            Duration::from_millis((capacity / rate_per_millis.max(f64::EPSILON)) as u64);
+        self.keyed_map
+            .get_or_insert_with(key, refill_all.max(self.unit.get_duration()), || {
+                Arc::new(Mutex::new(TokenBucketState {
+                    tokens: capacity,
+                    last_refill_time: Instant::now(),
+                }))
+            })
+    }
+    fn check_keyed_limit(&self, key: String) -> Result<RatelimitQuota, anyhow::Error> {
+        let capacity = self.capacity.max(0) as f64;
+        let rate_per_millis = self.rate_per_unit as f64 / self.unit.get_million_second() as f64;
+        let state = self.get_keyed_state(key.as_str())?;
+        let mut state = state.lock().map_err(|err| anyhow!(err.to_string()))?;
+        let now = Instant::now();
+        let elapsed_millis = now.duration_since(state.last_refill_time).as_millis() as f64;
+        state.tokens = (state.tokens + elapsed_millis * rate_per_millis).min(capacity);
+        state.last_refill_time = now;
+        if state.tokens < 1.0 {
+            return Ok(self.get_quota(true, 0));
+        }
+        state.tokens -= 1.0;
+        Ok(self.get_quota(false, state.tokens as isize))
+    }
+    fn get_quota(&self, limited: bool, remaining: isize) -> RatelimitQuota {
+        let refill_millis = self.unit.get_million_second() / self.rate_per_unit.max(1);
+        RatelimitQuota {
+            limited,
+            limit: self.capacity.max(0) as u64,
+            remaining: remaining.max(0) as u64,
+            reset_after_secs: refill_millis.div_ceil(1000) as u64,
+            rule: None,
+        }
+    }
+}
 #[typetag::serde]
 impl RatelimitStrategy for TokenBucketRateLimit {
-    fn should_limit(
+    fn check_limit(
         &mut self,
-        headers: HeaderMap<HeaderValue>,
-        remote_ip: String,
-    ) -> Result<bool, anyhow::Error> {
-        let match_or_not = matched(self.limit_location.clone(), headers, remote_ip)?;
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        let match_or_not = matched(self.limit_location.clone(), request_context)?;
         if !match_or_not {
-            return Ok(false);
+            return Ok(None);
+        }
+        if let Some(key_by) = &self.key_by {
+            let key = key_by.get_key(request_context)?;
+            return self.check_keyed_limit(key).map(Some);
         }
         let read_lock = self.current_count.read().unwrap();
         let current_value = read_lock.fetch_sub(1, Ordering::SeqCst);
@@ -185,7 +329,7 @@ impl RatelimitStrategy for TokenBucketRateLimit {
                 elapsed_millis * self.rate_per_unit / self.unit.get_million_second();
 
             if added_count == 0 {
-                return Ok(true);
+                return Ok(Some(self.get_quota(true, 0)));
             }
             drop(read_lock);
             let mut write_lock = self.current_count.write().unwrap();
@@ -204,10 +348,32 @@ impl RatelimitStrategy for TokenBucketRateLimit {
                 .unwrap()
                 .fetch_sub(1, Ordering::SeqCst);
             if current_value <= 0 {
-                return Ok(true);
+                return Ok(Some(self.get_quota(true, 0)));
             }
+            return Ok(Some(self.get_quota(false, current_value - 1)));
         }
-        return Ok(false);
+        return Ok(Some(self.get_quota(false, current_value - 1)));
+    }
+    fn refund(&mut self, request_context: &RequestContext) -> Result<(), anyhow::Error> {
+        if !matched(self.limit_location.clone(), request_context)? {
+            return Ok(());
+        }
+        if let Some(key_by) = &self.key_by {
+            let key = key_by.get_key(request_context)?;
+            let state = self.get_keyed_state(key.as_str())?;
+            let mut state = state.lock().map_err(|err| anyhow!(err.to_string()))?;
+            state.tokens = (state.tokens + 1.0).min(self.capacity.max(0) as f64);
+            return Ok(());
+        }
+        let capacity = self.capacity as isize;
+        let _ = self
+            .current_count
+            .read()
+            .map_err(|err| anyhow!(err.to_string()))?
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < capacity).then_some(count + 1)
+            });
+        Ok(())
     }
     fn as_any(&self) -> &dyn Any {
         self
@@ -222,17 +388,52 @@ pub struct FixedWindowRateLimit {
     pub count_map: Arc<DashMap<String, Arc<AtomicIsize>>>,
     #[serde(skip_serializing, skip_deserializing)]
     pub lock: Arc<Mutex<i32>>,
+    #[serde(default)]
+    pub key_by: Option<KeyBy>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub keyed_map: Arc<KeyedStore<Arc<AtomicIsize>>>,
+}
+// The counter is not decreased below 0 when the window is changed after the check.
+fn decrease_counter(counter: &AtomicIsize) {
+    let _ = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+        (count > 0).then_some(count - 1)
+    });
+}
+impl FixedWindowRateLimit {
+    fn get_keyed_counter(&self, key: &str) -> Result<Arc<AtomicIsize>, anyhow::Error> {
+        let time_unit_key = get_time_key(self.unit.clone())?;
+        self.keyed_map.get_or_insert_with(
+            format!("{}:{}", key, time_unit_key).as_str(),
+            self.unit.get_duration(),
+            || Arc::new(AtomicIsize::new(0)),
+        )
+    }
+    fn check_keyed_limit(&self, key: String) -> Result<RatelimitQuota, anyhow::Error> {
+        let counter = self.get_keyed_counter(key.as_str())?;
+        let res = counter.fetch_add(1, Ordering::SeqCst);
+        let limit = self.rate_per_unit as u64;
+        Ok(RatelimitQuota {
+            limited: res as i32 >= self.rate_per_unit as i32,
+            limit,
+            remaining: limit.saturating_sub(res.max(0) as u64 + 1),
+            reset_after_secs: get_window_reset_secs(self.unit.clone())?,
+            rule: None,
+        })
+    }
 }
 #[typetag::serde]
 impl RatelimitStrategy for FixedWindowRateLimit {
-    fn should_limit(
+    fn check_limit(
         &mut self,
-        headers: HeaderMap<HeaderValue>,
-        remote_ip: String,
-    ) -> Result<bool, anyhow::Error> {
-        let match_or_not = matched(self.limit_location.clone(), headers, remote_ip)?;
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        let match_or_not = matched(self.limit_location.clone(), request_context)?;
         if !match_or_not {
-            return Ok(false);
+            return Ok(None);
+        }
+        if let Some(key_by) = &self.key_by {
+            let key = key_by.get_key(request_context)?;
+            return self.check_keyed_limit(key).map(Some);
         }
         let time_unit_key = get_time_key(self.unit.clone())?;
         let location_key = self.limit_location.get_key();
@@ -240,12 +441,9 @@ impl RatelimitStrategy for FixedWindowRateLimit {
         if !self.count_map.contains_key(key.clone().as_str()) {
             let _lock = self.lock.lock().map_err(|err| anyhow!(err.to_string()))?;
             if !self.count_map.contains_key(key.clone().as_str()) {
-                if self.count_map.len() > DEFAULT_FIXEDWINDOW_MAP_SIZE as usize {
-                    let first = self.count_map.iter().next().unwrap();
-                    let first_key = first.key().clone();
-                    drop(first);
-                    self.count_map.remove(first_key.as_str());
-                }
+                let current_suffix = format!(":{}", time_unit_key);
+                self.count_map
+                    .retain(|item_key, _| item_key.ends_with(current_suffix.as_str()));
                 self.count_map
                     .insert(key.clone(), Arc::new(AtomicIsize::new(0)));
             }
@@ -254,10 +452,290 @@ impl RatelimitStrategy for FixedWindowRateLimit {
             "Can not find the key in the map of FixedWindowRateLimit!"
         ))?;
         let res = atomic_isize.fetch_add(1, Ordering::SeqCst);
-        if res as i32 >= self.rate_per_unit as i32 {
-            return Ok(true);
+        let limit = self.rate_per_unit as u64;
+        Ok(Some(RatelimitQuota {
+            limited: res as i32 >= self.rate_per_unit as i32,
+            limit,
+            remaining: limit.saturating_sub(res.max(0) as u64 + 1),
+            reset_after_secs: get_window_reset_secs(self.unit.clone())?,
+            rule: None,
+        }))
+    }
+    fn refund(&mut self, request_context: &RequestContext) -> Result<(), anyhow::Error> {
+        if !matched(self.limit_location.clone(), request_context)? {
+            return Ok(());
+        }
+        if let Some(key_by) = &self.key_by {
+            let key = key_by.get_key(request_context)?;
+            let counter = self.get_keyed_counter(key.as_str())?;
+            decrease_counter(&counter);
+            return Ok(());
         }
-        return Ok(false);
+        let time_unit_key = get_time_key(self.unit.clone())?;
+        let key = format!("{}:{}", self.limit_location.get_key(), time_unit_key);
+        if let Some(counter) = self.count_map.get(key.as_str()) {
+            decrease_counter(&counter);
+        }
+        Ok(())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+pub fn get_limit_key(
+    limit_location: &LimitLocation,
+    key_by: &Option<KeyBy>,
+    request_context: &RequestContext,
+) -> Result<String, anyhow::Error> {
+    match key_by {
+        Some(key_by) => key_by.get_key(request_context),
+        None => Ok(limit_location.get_key()),
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlidingWindowLogRateLimit {
+    pub rate_per_unit: u128,
+    pub unit: TimeUnit,
+    pub limit_location: LimitLocation,
+    #[serde(default)]
+    pub key_by: Option<KeyBy>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub keyed_map: Arc<KeyedStore<Arc<Mutex<VecDeque<Instant>>>>>,
+}
+#[typetag::serde]
+impl RatelimitStrategy for SlidingWindowLogRateLimit {
+    fn check_limit(
+        &mut self,
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        if !matched(self.limit_location.clone(), request_context)? {
+            return Ok(None);
+        }
+        let key = get_limit_key(&self.limit_location, &self.key_by, request_context)?;
+        let window = self.unit.get_duration();
+        let request_log =
+            self.keyed_map
+                .get_or_insert_with(key.as_str(), window, Default::default)?;
+        let mut request_log = request_log.lock().map_err(|err| anyhow!(err.to_string()))?;
+        let now = Instant::now();
+        while let Some(oldest) = request_log.front() {
+            if now.duration_since(*oldest) < window {
+                break;
+            }
+            request_log.pop_front();
+        }
+        let limit = self.rate_per_unit as u64;
+        let limited = request_log.len() as u64 >= limit;
+        if !limited {
+            request_log.push_back(now);
+        }
+        let reset_after = request_log
+            .front()
+            .map(|oldest| window.saturating_sub(now.duration_since(*oldest)))
+            .unwrap_or(window);
+        Ok(Some(RatelimitQuota {
+            limited,
+            limit,
+            remaining: limit.saturating_sub(request_log.len() as u64),
+            reset_after_secs: (reset_after.as_millis() as u64).div_ceil(1000),
+            rule: None,
+        }))
+    }
+    fn refund(&mut self, request_context: &RequestContext) -> Result<(), anyhow::Error> {
+        if !matched(self.limit_location.clone(), request_context)? {
+            return Ok(());
+        }
+        let key = get_limit_key(&self.limit_location, &self.key_by, request_context)?;
+        let request_log = self.keyed_map.get_or_insert_with(
+            key.as_str(),
+            self.unit.get_duration(),
+            Default::default,
+        )?;
+        request_log
+            .lock()
+            .map_err(|err| anyhow!(err.to_string()))?
+            .pop_back();
+        Ok(())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+#[derive(Debug, Default)]
+pub struct SlidingWindowCounter {
+    window_index: u128,
+    current_count: u64,
+    previous_count: u64,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlidingWindowCounterRateLimit {
+    pub rate_per_unit: u128,
+    pub unit: TimeUnit,
+    pub limit_location: LimitLocation,
+    #[serde(default)]
+    pub key_by: Option<KeyBy>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub keyed_map: Arc<KeyedStore<Arc<Mutex<SlidingWindowCounter>>>>,
+}
+#[typetag::serde]
+impl RatelimitStrategy for SlidingWindowCounterRateLimit {
+    fn check_limit(
+        &mut self,
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        if !matched(self.limit_location.clone(), request_context)? {
+            return Ok(None);
+        }
+        let key = get_limit_key(&self.limit_location, &self.key_by, request_context)?;
+        let window = self.unit.get_duration();
+        let counter =
+            self.keyed_map
+                .get_or_insert_with(key.as_str(), window * 2, Default::default)?;
+        let mut counter = counter.lock().map_err(|err| anyhow!(err.to_string()))?;
+        let in_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| anyhow!(err.to_string()))?
+            .as_millis();
+        let unit_ms = self.unit.get_million_second();
+        let window_index = in_ms / unit_ms;
+        if window_index != counter.window_index {
+            counter.previous_count = if window_index == counter.window_index + 1 {
+                counter.current_count
+            } else {
+                0
+            };
+            counter.current_count = 0;
+            counter.window_index = window_index;
+        }
+        // The count of the previous window is weighted by its overlap with the sliding window.
+        let elapsed_ratio = (in_ms % unit_ms) as f64 / unit_ms as f64;
+        let estimated_count =
+            counter.previous_count as f64 * (1.0 - elapsed_ratio) + counter.current_count as f64;
+        let limit = self.rate_per_unit as u64;
+        let limited = estimated_count >= limit as f64;
+        if !limited {
+            counter.current_count += 1;
+        }
+        let used = (estimated_count as u64) + if limited { 0 } else { 1 };
+        Ok(Some(RatelimitQuota {
+            limited,
+            limit,
+            remaining: limit.saturating_sub(used),
+            reset_after_secs: (unit_ms - in_ms % unit_ms).div_ceil(1000) as u64,
+            rule: None,
+        }))
+    }
+    fn refund(&mut self, request_context: &RequestContext) -> Result<(), anyhow::Error> {
+        if !matched(self.limit_location.clone(), request_context)? {
+            return Ok(());
+        }
+        let key = get_limit_key(&self.limit_location, &self.key_by, request_context)?;
+        let counter = self.keyed_map.get_or_insert_with(
+            key.as_str(),
+            self.unit.get_duration() * 2,
+            Default::default,
+        )?;
+        let mut counter = counter.lock().map_err(|err| anyhow!(err.to_string()))?;
+        counter.current_count = counter.current_count.saturating_sub(1);
+        Ok(())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RatelimitRule {
+    pub name: String,
+    pub ratelimit: Box<dyn RatelimitStrategy>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipleRateLimit {
+    pub rules: Vec<RatelimitRule>,
+}
+#[typetag::serde]
+impl RatelimitStrategy for MultipleRateLimit {
+    fn check_limit(
+        &mut self,
+        request_context: &RequestContext,
+    ) -> Result<Option<RatelimitQuota>, anyhow::Error> {
+        let mut strictest_quota = None;
+        for index in 0..self.rules.len() {
+            let rule = &mut self.rules[index];
+            let result = rule.ratelimit.check_limit(request_context);
+            let quota = match result {
+                Ok(Some(quota)) => RatelimitQuota {
+                    rule: Some(rule.name.clone()),
+                    ..quota
+                },
+                Ok(None) => continue,
+                Err(err) => {
+                    for rule in self.rules[..index].iter_mut() {
+                        rule.ratelimit.refund(request_context)?;
+                    }
+                    return Err(err);
+                }
+            };
+            if quota.limited {
+                for rule in self.rules[..index].iter_mut() {
+                    rule.ratelimit.refund(request_context)?;
+                }
+                return Ok(Some(quota));
+            }
+            strictest_quota = Some(quota.stricter(strictest_quota));
+        }
+        Ok(strictest_quota)
+    }
+    fn check_limit_async<'a>(
+        &'a mut self,
+        request_context: &'a RequestContext,
+    ) -> BoxFuture<'a, Result<Option<RatelimitQuota>, anyhow::Error>> {
+        Box::pin(async move {
+            let mut strictest_quota = None;
+            for index in 0..self.rules.len() {
+                let rule = &mut self.rules[index];
+                let result = rule.ratelimit.check_limit_async(request_context).await;
+                let quota = match result {
+                    Ok(Some(quota)) => RatelimitQuota {
+                        rule: Some(rule.name.clone()),
+                        ..quota
+                    },
+                    Ok(None) => continue,
+                    Err(err) => {
+                        for rule in self.rules[..index].iter_mut() {
+                            rule.ratelimit.refund_async(request_context).await?;
+                        }
+                        return Err(err);
+                    }
+                };
+                // The rules after the tripped or failed one are not consumed and the rules before
+                // it are refunded,so the rejected request is not charged.
+                if quota.limited {
+                    for rule in self.rules[..index].iter_mut() {
+                        rule.ratelimit.refund_async(request_context).await?;
+                    }
+                    return Ok(Some(quota));
+                }
+                strictest_quota = Some(quota.stricter(strictest_quota));
+            }
+            Ok(strictest_quota)
+        })
+    }
+    fn refund(&mut self, request_context: &RequestContext) -> Result<(), anyhow::Error> {
+        for rule in self.rules.iter_mut() {
+            rule.ratelimit.refund(request_context)?;
+        }
+        Ok(())
+    }
+    fn refund_async<'a>(
+        &'a mut self,
+        request_context: &'a RequestContext,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            for rule in self.rules.iter_mut() {
+                rule.ratelimit.refund_async(request_context).await?;
+            }
+            Ok(())
+        })
     }
     fn as_any(&self) -> &dyn Any {
         self
@@ -267,7 +745,16 @@ impl RatelimitStrategy for FixedWindowRateLimit {
 mod tests {
     use super::*;
     use crate::vojo::app_config::ApiService;
+    use crate::vojo::identity::Identity;
+    use http::HeaderMap;
     use std::{thread, time};
+    fn new_request_context(headers: HeaderMap, remote_ip: &str) -> RequestContext {
+        RequestContext {
+            remote_ip: String::from(remote_ip),
+            headers,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_token_bucket_rate_limit_ok1() {
@@ -280,22 +767,24 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res1 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res2 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res3 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res4 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -309,22 +798,24 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("245.0.0.1"));
+        let res1 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "245.0.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("245.255.0.1"));
+        let res2 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "245.255.0.1"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("245.255.255.1"));
+        let res3 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "245.255.255.1"));
         assert_eq!(res3.unwrap(), false);
 
         let res4 = token_bucket_ratelimit
-            .should_limit(headermap1.clone(), String::from("245.255.255.255"));
+            .should_limit(&new_request_context(headermap1.clone(), "245.255.255.255"));
         assert_eq!(res4.unwrap(), true);
     }
 
@@ -340,18 +831,24 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("lsk", "test".parse().unwrap());
-        let res1 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res1 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res1.unwrap(), false);
-        let res2 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res2 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res2.unwrap(), false);
-        let res3 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res3 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res4 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -366,18 +863,24 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("lsk", "test1".parse().unwrap());
-        let res1 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res1 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res1.unwrap(), false);
-        let res2 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res2 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res2.unwrap(), false);
-        let res3 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res3 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 = token_bucket_ratelimit.should_limit(headermap1.clone(), String::from(""));
+        let res4 =
+            token_bucket_ratelimit.should_limit(&new_request_context(headermap1.clone(), ""));
         assert_eq!(res4.unwrap(), false);
     }
     #[test]
@@ -391,21 +894,23 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("246.0.0.1"));
+        let res1 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "246.0.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("246.255.0.1"));
+        let res2 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "246.255.0.1"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("246.255.255.1"));
+        let res3 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "246.255.255.1"));
         assert_eq!(res3.unwrap(), false);
         let res4 = token_bucket_ratelimit
-            .should_limit(headermap1.clone(), String::from("246.255.255.255"));
+            .should_limit(&new_request_context(headermap1.clone(), "246.255.255.255"));
         assert_eq!(res4.unwrap(), false);
     }
     #[test]
@@ -419,22 +924,24 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res1 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res2 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res3 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res4 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res4.unwrap(), false);
     }
     #[test]
@@ -448,24 +955,26 @@ mod tests {
             }),
             current_count: Arc::new(RwLock::new(AtomicIsize::new(3))),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
             last_update_time: Arc::new(RwLock::new(SystemTime::now())),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res1 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res2 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res3 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res3.unwrap(), false);
 
         let one_second = time::Duration::from_secs(1);
         thread::sleep(one_second);
-        let res4 =
-            token_bucket_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res4 = token_bucket_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res4.unwrap(), false);
     }
     #[test]
@@ -491,21 +1000,23 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res4.unwrap(), true);
     }
 
@@ -519,33 +1030,35 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res3.unwrap(), false);
 
         let one_second = time::Duration::from_secs(1);
         thread::sleep(one_second);
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res4.unwrap(), false);
-        let res5 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res5 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res5.unwrap(), false);
-        let res6 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res6 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res6.unwrap(), false);
 
-        let res7 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res7 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res7.unwrap(), true);
     }
     #[test]
@@ -559,21 +1072,23 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.0"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.0"));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -586,21 +1101,23 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.2"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.2"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.3"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.3"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.4"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.4"));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -613,21 +1130,23 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.2"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.2"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.3"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.3"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.4"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.4"));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -640,21 +1159,23 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.2"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.2"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.3"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.3"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.4"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.4"));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -667,19 +1188,21 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
         for _n in 0..100 {
             let _res1 = fixed_window_ratelimit
-                .should_limit(headermap1.clone(), String::from("192.168.0.1"));
+                .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
             let _res2 = fixed_window_ratelimit
-                .should_limit(headermap1.clone(), String::from("192.168.0.2"));
+                .should_limit(&new_request_context(headermap1.clone(), "192.168.0.2"));
             let _res3 = fixed_window_ratelimit
-                .should_limit(headermap1.clone(), String::from("192.168.0.3"));
+                .should_limit(&new_request_context(headermap1.clone(), "192.168.0.3"));
 
             let _res4 = fixed_window_ratelimit
-                .should_limit(headermap1.clone(), String::from("192.168.0.4"));
+                .should_limit(&new_request_context(headermap1.clone(), "192.168.0.4"));
             let sleep_time = time::Duration::from_millis(2);
             thread::sleep(sleep_time);
         }
@@ -694,21 +1217,23 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let mut headermap1 = HeaderMap::new();
         headermap1.insert("api_key", "test2".parse().unwrap());
-        let res1 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.1"));
+        let res1 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.1"));
         assert_eq!(res1.unwrap(), false);
-        let res2 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.2"));
+        let res2 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.2"));
         assert_eq!(res2.unwrap(), false);
-        let res3 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.3"));
+        let res3 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.3"));
         assert_eq!(res3.unwrap(), false);
 
-        let res4 =
-            fixed_window_ratelimit.should_limit(headermap1.clone(), String::from("192.168.0.4"));
+        let res4 = fixed_window_ratelimit
+            .should_limit(&new_request_context(headermap1.clone(), "192.168.0.4"));
         assert_eq!(res4.unwrap(), true);
     }
     #[test]
@@ -721,6 +1246,8 @@ mod tests {
             }),
             count_map: Arc::new(DashMap::new()),
             lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
         };
         let weight_route: Box<dyn RatelimitStrategy> = Box::new(fixed_window_ratelimit);
         assert_eq!(format!("{:?}", weight_route), "{debug}");
@@ -850,4 +1377,413 @@ mod tests {
 
         assert_eq!(fixed_window_ratelimit.rate_per_unit, 3);
     }
+    #[test]
+    fn test_fixed_window_ratelimit_consumer() {
+        let mut fixed_window_ratelimit = FixedWindowRateLimit {
+            rate_per_unit: 2,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::Consumer(ConsumerBasedRatelimit {
+                value: String::from("consumer1"),
+            }),
+            count_map: Arc::new(DashMap::new()),
+            lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
+        };
+        let mut request_context1 = new_request_context(HeaderMap::new(), "192.168.0.1");
+        request_context1.identity = Some(Identity::new(String::from("consumer1")));
+        let mut request_context2 = new_request_context(HeaderMap::new(), "192.168.0.1");
+        request_context2.identity = Some(Identity::new(String::from("consumer2")));
+        let request_context3 = new_request_context(HeaderMap::new(), "192.168.0.1");
+        for _ in 0..2 {
+            let res = fixed_window_ratelimit.should_limit(&request_context1);
+            assert_eq!(res.unwrap(), false);
+        }
+        let res1 = fixed_window_ratelimit.should_limit(&request_context1);
+        assert_eq!(res1.unwrap(), true);
+        let res2 = fixed_window_ratelimit.should_limit(&request_context2);
+        assert_eq!(res2.unwrap(), false);
+        let res3 = fixed_window_ratelimit.should_limit(&request_context3);
+        assert_eq!(res3.unwrap(), false);
+    }
+    #[test]
+    fn test_fixed_window_ratelimit_quota() {
+        let mut fixed_window_ratelimit = FixedWindowRateLimit {
+            rate_per_unit: 2,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::IP(IPBasedRatelimit {
+                value: String::from("192.168.0.1"),
+            }),
+            count_map: Arc::new(DashMap::new()),
+            lock: Arc::new(Mutex::new(0)),
+            key_by: None,
+            keyed_map: Default::default(),
+        };
+        let request_context = new_request_context(HeaderMap::new(), "192.168.0.1");
+        let quota1 = fixed_window_ratelimit
+            .check_limit(&request_context)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota1.limited, false);
+        assert_eq!(quota1.limit, 2);
+        assert_eq!(quota1.remaining, 1);
+        assert_eq!(quota1.reset_after_secs <= 3600, true);
+        fixed_window_ratelimit
+            .check_limit(&request_context)
+            .unwrap();
+        let quota3 = fixed_window_ratelimit
+            .check_limit(&request_context)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota3.limited, true);
+        assert_eq!(quota3.remaining, 0);
+        let not_matched = fixed_window_ratelimit
+            .check_limit(&new_request_context(HeaderMap::new(), "192.168.0.2"))
+            .unwrap();
+        assert_eq!(not_matched.is_none(), true);
+    }
+    #[test]
+    fn test_token_bucket_ratelimit_key_by_client_ip() {
+        let mut token_bucket_ratelimit = TokenBucketRateLimit {
+            rate_per_unit: 1,
+            capacity: 2,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::ALL,
+            current_count: Default::default(),
+            lock: Default::default(),
+            last_update_time: Arc::new(RwLock::new(SystemTime::now())),
+            key_by: Some(KeyBy::ClientIP),
+            keyed_map: Default::default(),
+        };
+        for _ in 0..2 {
+            let res = token_bucket_ratelimit
+                .should_limit(&new_request_context(HeaderMap::new(), "192.168.0.1"));
+            assert_eq!(res.unwrap(), false);
+        }
+        let res1 = token_bucket_ratelimit
+            .should_limit(&new_request_context(HeaderMap::new(), "192.168.0.1"));
+        assert_eq!(res1.unwrap(), true);
+        let res2 = token_bucket_ratelimit
+            .should_limit(&new_request_context(HeaderMap::new(), "192.168.0.2"));
+        assert_eq!(res2.unwrap(), false);
+    }
+    #[test]
+    fn test_fixed_window_ratelimit_key_by_header() {
+        let mut fixed_window_ratelimit = FixedWindowRateLimit {
+            rate_per_unit: 1,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::ALL,
+            count_map: Default::default(),
+            lock: Default::default(),
+            key_by: Some(KeyBy::Header {
+                name: String::from("x-client"),
+            }),
+            keyed_map: Default::default(),
+        };
+        let mut headermap1 = HeaderMap::new();
+        headermap1.insert("x-client", "client1".parse().unwrap());
+        let mut headermap2 = HeaderMap::new();
+        headermap2.insert("x-client", "client2".parse().unwrap());
+        let res1 =
+            fixed_window_ratelimit.should_limit(&new_request_context(headermap1.clone(), "ip"));
+        assert_eq!(res1.unwrap(), false);
+        let res2 = fixed_window_ratelimit.should_limit(&new_request_context(headermap1, "ip"));
+        assert_eq!(res2.unwrap(), true);
+        let res3 = fixed_window_ratelimit.should_limit(&new_request_context(headermap2, "ip"));
+        assert_eq!(res3.unwrap(), false);
+    }
+    #[test]
+    fn test_key_by_as_any() {
+        let req = r#"{
+            "type": "FixedWindowRateLimit",
+            "rate_per_unit": 100,
+            "unit": {
+                "type": "Minute"
+            },
+            "limit_location": {
+                "type": "ALL"
+            },
+            "key_by": {
+                "type": "ClientIP"
+            }
+        }"#;
+        let ratelimit: Box<dyn RatelimitStrategy> = serde_json::from_str(req).unwrap();
+        let fixed_window_ratelimit: &FixedWindowRateLimit =
+            match ratelimit.as_any().downcast_ref::<FixedWindowRateLimit>() {
+                Some(b) => b,
+                None => panic!("error!"),
+            };
+        assert_eq!(fixed_window_ratelimit.key_by, Some(KeyBy::ClientIP));
+        assert_eq!(fixed_window_ratelimit.limit_location, LimitLocation::ALL);
+    }
+    #[test]
+    fn test_fixed_window_ratelimit_expire_old_window() {
+        let count_map: Arc<DashMap<String, Arc<AtomicIsize>>> = Arc::new(DashMap::new());
+        count_map.insert(String::from("192.168.0.1:1"), Arc::new(AtomicIsize::new(1)));
+        count_map.insert(String::from("192.168.0.1:2"), Arc::new(AtomicIsize::new(1)));
+        let mut fixed_window_ratelimit = FixedWindowRateLimit {
+            rate_per_unit: 2,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::IP(IPBasedRatelimit {
+                value: String::from("192.168.0.1"),
+            }),
+            count_map: count_map.clone(),
+            lock: Default::default(),
+            key_by: None,
+            keyed_map: Default::default(),
+        };
+        let res = fixed_window_ratelimit
+            .should_limit(&new_request_context(HeaderMap::new(), "192.168.0.1"));
+        assert_eq!(res.unwrap(), false);
+        assert_eq!(count_map.len(), 1);
+    }
+    #[test]
+    fn test_sliding_window_log_ratelimit() {
+        let mut sliding_window_log_ratelimit = SlidingWindowLogRateLimit {
+            rate_per_unit: 2,
+            unit: TimeUnit::Second,
+            limit_location: LimitLocation::ALL,
+            key_by: Some(KeyBy::ClientIP),
+            keyed_map: Default::default(),
+        };
+        let request_context1 = new_request_context(HeaderMap::new(), "192.168.0.1");
+        for _ in 0..2 {
+            let res = sliding_window_log_ratelimit.should_limit(&request_context1);
+            assert_eq!(res.unwrap(), false);
+        }
+        let quota = sliding_window_log_ratelimit
+            .check_limit(&request_context1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, true);
+        assert_eq!(quota.reset_after_secs, 1);
+        let res2 = sliding_window_log_ratelimit
+            .should_limit(&new_request_context(HeaderMap::new(), "192.168.0.2"));
+        assert_eq!(res2.unwrap(), false);
+        thread::sleep(time::Duration::from_millis(1100));
+        let res3 = sliding_window_log_ratelimit.should_limit(&request_context1);
+        assert_eq!(res3.unwrap(), false);
+    }
+    #[test]
+    fn test_sliding_window_counter_ratelimit() {
+        let mut sliding_window_counter_ratelimit = SlidingWindowCounterRateLimit {
+            rate_per_unit: 3,
+            unit: TimeUnit::Hour,
+            limit_location: LimitLocation::IP(IPBasedRatelimit {
+                value: String::from("192.168.0.1"),
+            }),
+            key_by: None,
+            keyed_map: Default::default(),
+        };
+        let request_context1 = new_request_context(HeaderMap::new(), "192.168.0.1");
+        for _ in 0..3 {
+            let res = sliding_window_counter_ratelimit.should_limit(&request_context1);
+            assert_eq!(res.unwrap(), false);
+        }
+        let res1 = sliding_window_counter_ratelimit.should_limit(&request_context1);
+        assert_eq!(res1.unwrap(), true);
+        let res2 = sliding_window_counter_ratelimit
+            .should_limit(&new_request_context(HeaderMap::new(), "192.168.0.2"));
+        assert_eq!(res2.unwrap(), false);
+    }
+    #[test]
+    fn test_sliding_window_counter_weight_previous_window() {
+        let mut sliding_window_counter_ratelimit = SlidingWindowCounterRateLimit {
+            rate_per_unit: 10,
+            unit: TimeUnit::Day,
+            limit_location: LimitLocation::ALL,
+            key_by: None,
+            keyed_map: Default::default(),
+        };
+        let request_context1 = new_request_context(HeaderMap::new(), "192.168.0.1");
+        let in_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let counter = sliding_window_counter_ratelimit
+            .keyed_map
+            .get_or_insert_with("all", Duration::from_secs(60), Default::default)
+            .unwrap();
+        {
+            let mut counter = counter.lock().unwrap();
+            counter.window_index = in_ms / 86_400_000;
+            counter.current_count = 0;
+            counter.previous_count = 100_000;
+        }
+        let res = sliding_window_counter_ratelimit.should_limit(&request_context1);
+        assert_eq!(res.unwrap(), true);
+    }
+    #[test]
+    fn test_sliding_window_as_any() {
+        let req = r#"{
+            "type": "SlidingWindowLogRateLimit",
+            "rate_per_unit": 100,
+            "unit": {
+                "type": "Minute"
+            },
+            "limit_location": {
+                "type": "ALL"
+            }
+        }"#;
+        let ratelimit: Box<dyn RatelimitStrategy> = serde_json::from_str(req).unwrap();
+        let sliding_window_log_ratelimit: &SlidingWindowLogRateLimit =
+            match ratelimit
+                .as_any()
+                .downcast_ref::<SlidingWindowLogRateLimit>()
+            {
+                Some(b) => b,
+                None => panic!("error!"),
+            };
+        assert_eq!(sliding_window_log_ratelimit.rate_per_unit, 100);
+        assert_eq!(sliding_window_log_ratelimit.key_by, None);
+    }
+    #[test]
+    fn test_multiple_ratelimit_report_tripped_rule() {
+        let req = r#"{
+            "type": "MultipleRateLimit",
+            "rules": [
+                {
+                    "name": "burst",
+                    "ratelimit": {
+                        "type": "SlidingWindowLogRateLimit",
+                        "rate_per_unit": 3,
+                        "unit": {
+                            "type": "Second"
+                        },
+                        "limit_location": {
+                            "type": "ALL"
+                        }
+                    }
+                },
+                {
+                    "name": "quota",
+                    "ratelimit": {
+                        "type": "FixedWindowRateLimit",
+                        "rate_per_unit": 2,
+                        "unit": {
+                            "type": "Day"
+                        },
+                        "limit_location": {
+                            "type": "ALL"
+                        }
+                    }
+                }
+            ]
+        }"#;
+        let mut ratelimit: Box<dyn RatelimitStrategy> = serde_json::from_str(req).unwrap();
+        let multiple_ratelimit: &MultipleRateLimit =
+            match ratelimit.as_any().downcast_ref::<MultipleRateLimit>() {
+                Some(b) => b,
+                None => panic!("error!"),
+            };
+        assert_eq!(multiple_ratelimit.rules.len(), 2);
+        let request_context = new_request_context(HeaderMap::new(), "192.168.0.1");
+        let quota = ratelimit.check_limit(&request_context).unwrap().unwrap();
+        assert_eq!(quota.limited, false);
+        assert_eq!(quota.rule, Some(String::from("quota")));
+        assert_eq!(quota.remaining, 1);
+        ratelimit.check_limit(&request_context).unwrap();
+        let quota = ratelimit.check_limit(&request_context).unwrap().unwrap();
+        assert_eq!(quota.limited, true);
+        assert_eq!(quota.rule, Some(String::from("quota")));
+    }
+    #[test]
+    fn test_multiple_ratelimit_refund_earlier_rules() {
+        let req = r#"{
+            "rules": [
+                {
+                    "name": "quota",
+                    "ratelimit": {
+                        "type": "FixedWindowRateLimit",
+                        "rate_per_unit": 2,
+                        "unit": {
+                            "type": "Day"
+                        },
+                        "limit_location": {
+                            "type": "ALL"
+                        }
+                    }
+                },
+                {
+                    "name": "burst",
+                    "ratelimit": {
+                        "type": "SlidingWindowLogRateLimit",
+                        "rate_per_unit": 1,
+                        "unit": {
+                            "type": "Minute"
+                        },
+                        "limit_location": {
+                            "type": "ALL"
+                        }
+                    }
+                }
+            ]
+        }"#;
+        let mut multiple_ratelimit: MultipleRateLimit = serde_json::from_str(req).unwrap();
+        let request_context = new_request_context(HeaderMap::new(), "192.168.0.1");
+        let quota = multiple_ratelimit
+            .check_limit(&request_context)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, false);
+        for _ in 0..3 {
+            let quota = multiple_ratelimit
+                .check_limit(&request_context)
+                .unwrap()
+                .unwrap();
+            assert_eq!(quota.limited, true);
+            assert_eq!(quota.rule, Some(String::from("burst")));
+        }
+        let quota = multiple_ratelimit.rules[0]
+            .ratelimit
+            .check_limit(&request_context)
+            .unwrap()
+            .unwrap();
+        assert_eq!(quota.limited, false);
+        assert_eq!(quota.remaining, 0);
+    }
+    #[test]
+    fn test_ratelimit_refund() {
+        let request_context = new_request_context(HeaderMap::new(), "192.168.0.1");
+        let strategies = [
+            r#"{"type": "TokenBucketRateLimit", "rate_per_unit": 1, "capacity": 1, "unit": {"type": "Hour"}, "limit_location": {"type": "ALL"}, "key_by": {"type": "ClientIP"}}"#,
+            r#"{"type": "FixedWindowRateLimit", "rate_per_unit": 1, "unit": {"type": "Hour"}, "limit_location": {"type": "ALL"}}"#,
+            r#"{"type": "FixedWindowRateLimit", "rate_per_unit": 1, "unit": {"type": "Hour"}, "limit_location": {"type": "ALL"}, "key_by": {"type": "ClientIP"}}"#,
+            r#"{"type": "SlidingWindowLogRateLimit", "rate_per_unit": 1, "unit": {"type": "Hour"}, "limit_location": {"type": "ALL"}}"#,
+            r#"{"type": "SlidingWindowCounterRateLimit", "rate_per_unit": 1, "unit": {"type": "Hour"}, "limit_location": {"type": "ALL"}}"#,
+        ];
+        for strategy in strategies {
+            let mut ratelimit: Box<dyn RatelimitStrategy> = serde_json::from_str(strategy).unwrap();
+            let quota = ratelimit.check_limit(&request_context).unwrap().unwrap();
+            assert_eq!(quota.limited, false);
+            ratelimit.refund(&request_context).unwrap();
+            let quota = ratelimit.check_limit(&request_context).unwrap().unwrap();
+            assert_eq!(quota.limited, false);
+            let quota = ratelimit.check_limit(&request_context).unwrap().unwrap();
+            assert_eq!(quota.limited, true);
+        }
+    }
+    #[test]
+    fn test_ratelimit_quota_stricter() {
+        let quota1 = RatelimitQuota {
+            limit: 10,
+            remaining: 5,
+            ..Default::default()
+        };
+        let quota2 = RatelimitQuota {
+            limit: 1000,
+            remaining: 2,
+            ..Default::default()
+        };
+        assert_eq!(quota1.clone().stricter(Some(quota2.clone())), quota2);
+        assert_eq!(quota2.clone().stricter(Some(quota1.clone())), quota2);
+        assert_eq!(quota1.clone().stricter(None), quota1);
+        let limited_quota = RatelimitQuota {
+            limited: true,
+            limit: 10,
+            remaining: 5,
+            ..Default::default()
+        };
+        assert_eq!(quota2.stricter(Some(limited_quota.clone())), limited_quota);
+    }
 }
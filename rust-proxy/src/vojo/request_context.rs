@@ -0,0 +1,97 @@
+use crate::vojo::authentication::AuthenticationFailure;
+use crate::vojo::identity::Identity;
+use crate::vojo::rate_limit::RatelimitQuota;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Uri;
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub remote_ip: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: HeaderMap<HeaderValue>,
+    pub identity: Option<Identity>,
+    pub auth_failures: Vec<AuthenticationFailure>,
+    pub ratelimit_quota: Option<RatelimitQuota>,
+}
+impl RequestContext {
+    pub fn new(remote_ip: String, uri: &Uri, headers: HeaderMap<HeaderValue>) -> Self {
+        RequestContext {
+            remote_ip,
+            path: String::from(uri.path()),
+            query: uri.query().map(String::from),
+            headers,
+            identity: None,
+            auth_failures: vec![],
+            ratelimit_quota: None,
+        }
+    }
+    pub fn add_auth_failure(&mut self, strategy: &str, reason: &str) {
+        self.auth_failures.push(AuthenticationFailure {
+            strategy: String::from(strategy),
+            reason: String::from(reason),
+        });
+    }
+    pub fn get_consumer_id(&self) -> Option<String> {
+        self.identity
+            .as_ref()
+            .map(|identity| identity.consumer_id.clone())
+    }
+    pub fn get_header(&self, key: &str) -> Result<Option<String>, anyhow::Error> {
+        match self.headers.get(key) {
+            Some(value) => {
+                let value_str = value.to_str().map_err(|err| anyhow!(err.to_string()))?;
+                Ok(Some(String::from(value_str)))
+            }
+            None => Ok(None),
+        }
+    }
+    pub fn get_query_param(&self, key: &str) -> Option<String> {
+        let query = self.query.as_ref()?;
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.into_owned())
+    }
+    pub fn get_cookie(&self, key: &str) -> Option<String> {
+        self.headers
+            .get_all(http::header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| String::from(value.trim_matches('"')))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_context_from_uri() {
+        let uri: Uri = "http://localhost:8080/get?api_key=test&b=2"
+            .parse()
+            .unwrap();
+        let request_context =
+            RequestContext::new(String::from("127.0.0.1"), &uri, HeaderMap::new());
+        assert_eq!(request_context.path, "/get");
+        assert_eq!(
+            request_context.get_query_param("api_key"),
+            Some(String::from("test"))
+        );
+        assert_eq!(request_context.get_query_param("c"), None);
+    }
+    #[test]
+    fn test_request_context_get_cookie() {
+        let mut headermap = HeaderMap::new();
+        headermap.insert("Cookie", "a=1; api_key=\"test\"; c=3".parse().unwrap());
+        let uri: Uri = "/get".parse().unwrap();
+        let request_context = RequestContext::new(String::from("127.0.0.1"), &uri, headermap);
+        assert_eq!(
+            request_context.get_cookie("api_key"),
+            Some(String::from("test"))
+        );
+        assert_eq!(request_context.get_cookie("d"), None);
+    }
+}
@@ -0,0 +1,277 @@
+use futures::stream;
+use http::{HeaderMap, StatusCode};
+use hyper::body::HttpBody;
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+fn default_grace_period_ms() -> u64 {
+    5000
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinDataRate {
+    pub bytes_per_second: u64,
+    #[serde(default = "default_grace_period_ms")]
+    pub grace_period_ms: u64,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct RequestLimits {
+    #[serde(default)]
+    pub max_body_size: Option<u64>,
+    #[serde(default)]
+    pub max_header_count: Option<usize>,
+    #[serde(default)]
+    pub max_header_size: Option<usize>,
+    // The whole request header must be received in the timeout,the listener must be restarted
+    // for the change to take effect.
+    #[serde(default)]
+    pub header_read_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub min_body_rate: Option<MinDataRate>,
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+    #[serde(default)]
+    pub max_connections_per_ip: Option<usize>,
+}
+impl RequestLimits {
+    pub fn is_header_too_large(&self, headers: &HeaderMap) -> bool {
+        if let Some(max_header_count) = self.max_header_count {
+            if headers.len() > max_header_count {
+                return true;
+            }
+        }
+        if let Some(max_header_size) = self.max_header_size {
+            let header_size: usize = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len() + 4)
+                .sum();
+            if header_size > max_header_size {
+                return true;
+            }
+        }
+        false
+    }
+}
+// Record why the body stream is aborted,so that the proxy could reply 413 or 408 instead of
+// the upstream error.
+#[derive(Debug, Default)]
+pub struct BodyGuard {
+    too_large: AtomicBool,
+    too_slow: AtomicBool,
+}
+impl BodyGuard {
+    pub fn get_status(&self) -> Option<StatusCode> {
+        if self.too_large.load(Ordering::SeqCst) {
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        } else if self.too_slow.load(Ordering::SeqCst) {
+            Some(StatusCode::REQUEST_TIMEOUT)
+        } else {
+            None
+        }
+    }
+}
+struct GuardedBody {
+    body: Body,
+    received: u64,
+    start_time: Instant,
+    body_guard: Arc<BodyGuard>,
+}
+pub fn guard_body(
+    body: Body,
+    max_body_size: Option<u64>,
+    min_body_rate: Option<MinDataRate>,
+) -> (Body, Arc<BodyGuard>) {
+    let body_guard = Arc::new(BodyGuard::default());
+    let guarded_body = GuardedBody {
+        body,
+        received: 0,
+        start_time: Instant::now(),
+        body_guard: body_guard.clone(),
+    };
+    let body_stream = stream::unfold(Some(guarded_body), move |state| {
+        let min_body_rate = min_body_rate.clone();
+        async move {
+            let mut guarded_body = state?;
+            let chunk_option = match &min_body_rate {
+                // The client must have sent bytes_per_second bytes for every second after the
+                // grace period.
+                Some(min_body_rate) if min_body_rate.bytes_per_second > 0 => {
+                    let deadline = guarded_body.start_time
+                        + Duration::from_millis(min_body_rate.grace_period_ms)
+                        + Duration::from_secs_f64(
+                            guarded_body.received as f64 / min_body_rate.bytes_per_second as f64,
+                        );
+                    match tokio::time::timeout_at(deadline, guarded_body.body.data()).await {
+                        Ok(chunk_option) => chunk_option,
+                        Err(_) => {
+                            guarded_body
+                                .body_guard
+                                .too_slow
+                                .store(true, Ordering::SeqCst);
+                            let err = io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "The request body is sent too slowly!",
+                            );
+                            return Some((Err(err), None));
+                        }
+                    }
+                }
+                _ => guarded_body.body.data().await,
+            };
+            let chunk = match chunk_option? {
+                Ok(chunk) => chunk,
+                Err(err) => return Some((Err(io::Error::other(err)), None)),
+            };
+            guarded_body.received += chunk.len() as u64;
+            if let Some(max_body_size) = max_body_size {
+                if guarded_body.received > max_body_size {
+                    guarded_body
+                        .body_guard
+                        .too_large
+                        .store(true, Ordering::SeqCst);
+                    let err = io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "The request body is too large!",
+                    );
+                    return Some((Err(err), None));
+                }
+            }
+            Some((Ok(chunk), Some(guarded_body)))
+        }
+    });
+    (Body::wrap_stream(body_stream), body_guard)
+}
+#[derive(Debug, Default)]
+struct ConnectionCore {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+#[derive(Debug, Default)]
+pub struct ConnectionLimiter {
+    core: Mutex<ConnectionCore>,
+}
+pub struct ConnectionPermit {
+    connection_limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if let Ok(mut core) = self.connection_limiter.core.lock() {
+            core.total = core.total.saturating_sub(1);
+            if let Some(count) = core.per_ip.get_mut(&self.ip) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    core.per_ip.remove(&self.ip);
+                }
+            }
+        }
+    }
+}
+impl ConnectionLimiter {
+    pub fn acquire(
+        self: &Arc<Self>,
+        ip: IpAddr,
+        request_limits: &RequestLimits,
+    ) -> Result<Option<ConnectionPermit>, anyhow::Error> {
+        let mut core = self.core.lock().map_err(|err| anyhow!(err.to_string()))?;
+        if let Some(max_connections) = request_limits.max_connections {
+            if core.total >= max_connections {
+                return Ok(None);
+            }
+        }
+        let ip_count = core.per_ip.get(&ip).copied().unwrap_or(0);
+        if let Some(max_connections_per_ip) = request_limits.max_connections_per_ip {
+            if ip_count >= max_connections_per_ip {
+                return Ok(None);
+            }
+        }
+        core.total += 1;
+        core.per_ip.insert(ip, ip_count + 1);
+        Ok(Some(ConnectionPermit {
+            connection_limiter: self.clone(),
+            ip,
+        }))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use hyper::body::Bytes;
+
+    #[test]
+    fn test_is_header_too_large() {
+        let request_limits = RequestLimits {
+            max_header_count: Some(2),
+            max_header_size: Some(64),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("Host", "localhost".parse().unwrap());
+        assert_eq!(request_limits.is_header_too_large(&headers), false);
+        headers.insert("X-Test", "a".repeat(64).parse().unwrap());
+        assert_eq!(request_limits.is_header_too_large(&headers), true);
+        headers.insert("X-Test", "a".parse().unwrap());
+        headers.insert("X-Test2", "b".parse().unwrap());
+        assert_eq!(request_limits.is_header_too_large(&headers), true);
+    }
+    #[tokio::test]
+    async fn test_guard_body_too_large() {
+        let (body, body_guard) = guard_body(Body::from("0123456789"), Some(4), None);
+        let res = hyper::body::to_bytes(body).await;
+        assert_eq!(res.is_err(), true);
+        assert_eq!(body_guard.get_status(), Some(StatusCode::PAYLOAD_TOO_LARGE));
+
+        let (body, body_guard) = guard_body(Body::from("0123"), Some(4), None);
+        let body_bytes = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(body_bytes, "0123");
+        assert_eq!(body_guard.get_status(), None);
+    }
+    #[tokio::test]
+    async fn test_guard_body_too_slow() {
+        let slow_stream = stream::once(async { Ok::<Bytes, io::Error>(Bytes::from("01")) }).chain(
+            stream::once(async {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                Ok::<Bytes, io::Error>(Bytes::from("23"))
+            }),
+        );
+        let min_body_rate = MinDataRate {
+            bytes_per_second: 100,
+            grace_period_ms: 50,
+        };
+        let (body, body_guard) =
+            guard_body(Body::wrap_stream(slow_stream), None, Some(min_body_rate));
+        let res = hyper::body::to_bytes(body).await;
+        assert_eq!(res.is_err(), true);
+        assert_eq!(body_guard.get_status(), Some(StatusCode::REQUEST_TIMEOUT));
+    }
+    #[test]
+    fn test_connection_limiter() {
+        let connection_limiter = Arc::new(ConnectionLimiter::default());
+        let request_limits = RequestLimits {
+            max_connections: Some(2),
+            max_connections_per_ip: Some(1),
+            ..Default::default()
+        };
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+        let ip3: IpAddr = "10.0.0.3".parse().unwrap();
+        let permit1 = connection_limiter.acquire(ip1, &request_limits).unwrap();
+        assert_eq!(permit1.is_some(), true);
+        let permit2 = connection_limiter.acquire(ip1, &request_limits).unwrap();
+        assert_eq!(permit2.is_none(), true);
+        let permit3 = connection_limiter.acquire(ip2, &request_limits).unwrap();
+        assert_eq!(permit3.is_some(), true);
+        let permit4 = connection_limiter.acquire(ip3, &request_limits).unwrap();
+        assert_eq!(permit4.is_none(), true);
+        drop(permit1);
+        let permit5 = connection_limiter.acquire(ip3, &request_limits).unwrap();
+        assert_eq!(permit5.is_some(), true);
+    }
+}
@@ -16,6 +16,9 @@ use std::sync::RwLock;
 #[typetag::serde(tag = "type")]
 pub trait LoadbalancerStrategy: Sync + Send + DynClone {
     fn get_route(&mut self, headers: HeaderMap<HeaderValue>) -> Result<BaseRoute, anyhow::Error>;
+    // All the endpoints in the order of the config,the tcp proxy falls back to the next endpoint
+    // when the selected one could not be connected.
+    fn get_base_routes(&self) -> Vec<BaseRoute>;
 
     fn get_debug(&self) -> String {
         String::from("debug")
@@ -87,6 +90,12 @@ impl LoadbalancerStrategy for HeaderBasedRoute {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn get_base_routes(&self) -> Vec<BaseRoute> {
+        self.routes
+            .iter()
+            .map(|item| item.base_route.clone())
+            .collect()
+    }
     fn get_route(&mut self, headers: HeaderMap<HeaderValue>) -> Result<BaseRoute, anyhow::Error> {
         for item in self.routes.iter() {
             let headers_contais_key = headers.contains_key(item.header_key.clone());
@@ -150,6 +159,12 @@ impl LoadbalancerStrategy for RandomRoute {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn get_base_routes(&self) -> Vec<BaseRoute> {
+        self.routes
+            .iter()
+            .map(|item| item.base_route.clone())
+            .collect()
+    }
     fn get_route(&mut self, _headers: HeaderMap<HeaderValue>) -> Result<BaseRoute, anyhow::Error> {
         let mut rng = thread_rng();
         let index = rng.gen_range(0..self.routes.len());
@@ -174,6 +189,12 @@ impl LoadbalancerStrategy for PollRoute {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn get_base_routes(&self) -> Vec<BaseRoute> {
+        self.routes
+            .iter()
+            .map(|item| item.base_route.clone())
+            .collect()
+    }
     fn get_route(&mut self, _headers: HeaderMap<HeaderValue>) -> Result<BaseRoute, anyhow::Error> {
         let older = self.current_index.fetch_add(1, Ordering::SeqCst);
         let len = self.routes.len();
@@ -198,6 +219,12 @@ impl LoadbalancerStrategy for WeightBasedRoute {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn get_base_routes(&self) -> Vec<BaseRoute> {
+        self.routes
+            .iter()
+            .map(|item| item.base_route.clone())
+            .collect()
+    }
     fn get_route(&mut self, _headers: HeaderMap<HeaderValue>) -> Result<BaseRoute, anyhow::Error> {
         let indexs = self.indexs.read().unwrap();
         for (pos, e) in indexs.iter().enumerate() {
@@ -0,0 +1,223 @@
+use rustls::server::{
+    NoServerSessionStorage, ResolvesServerCert, ResolvesServerCertUsingSni,
+    ServerSessionMemoryCache,
+};
+use rustls::{
+    ServerConfig, SupportedCipherSuite, SupportedKxGroup, SupportedProtocolVersion, Ticketer,
+    ALL_CIPHER_SUITES, ALL_KX_GROUPS, DEFAULT_CIPHER_SUITES,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+fn default_session_cache_size() -> usize {
+    256
+}
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "TLSv1.2")]
+    Tls12,
+    #[serde(rename = "TLSv1.3")]
+    Tls13,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionResumption {
+    // The session ids are cached in memory,the resumption by the session id is disabled when it
+    // is 0.
+    #[serde(default = "default_session_cache_size")]
+    pub session_cache_size: usize,
+    #[serde(default)]
+    pub session_tickets: bool,
+}
+impl Default for SessionResumption {
+    fn default() -> Self {
+        SessionResumption {
+            session_cache_size: default_session_cache_size(),
+            session_tickets: false,
+        }
+    }
+}
+// The listener must be restarted for the change to take effect,the default policy is the same
+// as the safe defaults of the rustls.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct TlsPolicy {
+    #[serde(default)]
+    pub min_version: Option<TlsVersion>,
+    #[serde(default)]
+    pub max_version: Option<TlsVersion>,
+    // The names of the cipher suites like TLS13_AES_128_GCM_SHA256 in the order of the
+    // preference,all the supported cipher suites are enabled when it is empty.
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+    // The names of the key exchange groups,X25519,secp256r1 and secp384r1 are supported.
+    #[serde(default)]
+    pub kx_groups: Vec<String>,
+    #[serde(default)]
+    pub alpn_protocols: Vec<String>,
+    #[serde(default)]
+    pub session_resumption: SessionResumption,
+}
+impl TlsPolicy {
+    fn get_protocol_versions(
+        &self,
+    ) -> Result<Vec<&'static SupportedProtocolVersion>, anyhow::Error> {
+        let min_version = self.min_version.clone().unwrap_or(TlsVersion::Tls12);
+        let max_version = self.max_version.clone().unwrap_or(TlsVersion::Tls13);
+        let versions: Vec<&'static SupportedProtocolVersion> = [
+            (TlsVersion::Tls12, &rustls::version::TLS12),
+            (TlsVersion::Tls13, &rustls::version::TLS13),
+        ]
+        .into_iter()
+        .filter(|(version, _)| *version >= min_version && *version <= max_version)
+        .map(|(_, supported_version)| supported_version)
+        .collect();
+        if versions.is_empty() {
+            return Err(anyhow!(
+                "The min_version {:?} is greater than the max_version {:?}!",
+                min_version,
+                max_version
+            ));
+        }
+        Ok(versions)
+    }
+    fn get_cipher_suites(&self) -> Result<Vec<SupportedCipherSuite>, anyhow::Error> {
+        if self.cipher_suites.is_empty() {
+            return Ok(DEFAULT_CIPHER_SUITES.to_vec());
+        }
+        self.cipher_suites
+            .iter()
+            .map(|name| {
+                ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|suite| format!("{:?}", suite.suite()).eq_ignore_ascii_case(name))
+                    .copied()
+                    .ok_or(anyhow!("The cipher suite {} is not supported!", name))
+            })
+            .collect()
+    }
+    fn get_kx_groups(&self) -> Result<Vec<&'static SupportedKxGroup>, anyhow::Error> {
+        if self.kx_groups.is_empty() {
+            return Ok(ALL_KX_GROUPS.to_vec());
+        }
+        self.kx_groups
+            .iter()
+            .map(|name| {
+                ALL_KX_GROUPS
+                    .iter()
+                    .find(|group| format!("{:?}", group.name).eq_ignore_ascii_case(name))
+                    .copied()
+                    .ok_or(anyhow!("The key exchange group {} is not supported!", name))
+            })
+            .collect()
+    }
+    fn get_alpn_protocols(&self) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        self.alpn_protocols
+            .iter()
+            .map(|protocol| match protocol.len() {
+                1..=255 => Ok(protocol.as_bytes().to_vec()),
+                _ => Err(anyhow!("The alpn protocol {:?} is invalid!", protocol)),
+            })
+            .collect()
+    }
+    pub fn new_server_config(
+        &self,
+        cert_resolver: Arc<dyn ResolvesServerCert>,
+    ) -> Result<ServerConfig, anyhow::Error> {
+        let mut server_config = ServerConfig::builder()
+            .with_cipher_suites(&self.get_cipher_suites()?)
+            .with_kx_groups(&self.get_kx_groups()?)
+            .with_protocol_versions(&self.get_protocol_versions()?)
+            .map_err(|err| anyhow!("The tls policy is invalid,{}", err))?
+            .with_no_client_auth()
+            .with_cert_resolver(cert_resolver);
+        server_config.alpn_protocols = self.get_alpn_protocols()?;
+        server_config.session_storage = match self.session_resumption.session_cache_size {
+            0 => Arc::new(NoServerSessionStorage {}),
+            session_cache_size => ServerSessionMemoryCache::new(session_cache_size),
+        };
+        if self.session_resumption.session_tickets {
+            server_config.ticketer =
+                Ticketer::new().map_err(|err| anyhow!("Can not create the ticketer,{}", err))?;
+        }
+        Ok(server_config)
+    }
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        self.new_server_config(Arc::new(ResolvesServerCertUsingSni::new()))
+            .map(|_| ())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_policy() {
+        let tls_policy: TlsPolicy = serde_yaml::from_str(
+            r#"
+min_version: TLSv1.3
+cipher_suites:
+- TLS13_AES_256_GCM_SHA384
+- tls13_chacha20_poly1305_sha256
+kx_groups:
+- X25519
+alpn_protocols:
+- h2
+- http/1.1
+session_resumption:
+  session_tickets: true
+"#,
+        )
+        .unwrap();
+        let server_config = tls_policy
+            .new_server_config(Arc::new(ResolvesServerCertUsingSni::new()))
+            .unwrap();
+        assert_eq!(
+            server_config.alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+        assert_eq!(server_config.ticketer.enabled(), true);
+        assert_eq!(tls_policy.get_protocol_versions().unwrap().len(), 1);
+        assert_eq!(tls_policy.get_cipher_suites().unwrap().len(), 2);
+        assert_eq!(tls_policy.session_resumption.session_cache_size, 256);
+    }
+    #[test]
+    fn test_default_tls_policy() {
+        let tls_policy = TlsPolicy::default();
+        assert_eq!(tls_policy.validate().is_ok(), true);
+        assert_eq!(tls_policy.get_protocol_versions().unwrap().len(), 2);
+        assert_eq!(
+            tls_policy.get_cipher_suites().unwrap().len(),
+            DEFAULT_CIPHER_SUITES.len()
+        );
+    }
+    #[test]
+    fn test_tls_policy_error() {
+        let tls_policy = TlsPolicy {
+            min_version: Some(TlsVersion::Tls13),
+            max_version: Some(TlsVersion::Tls12),
+            ..Default::default()
+        };
+        assert_eq!(tls_policy.validate().is_err(), true);
+        let tls_policy = TlsPolicy {
+            cipher_suites: vec![String::from("TLS_RSA_WITH_RC4_128_SHA")],
+            ..Default::default()
+        };
+        assert_eq!(tls_policy.validate().is_err(), true);
+        // The TLS1.2 cipher suite could not be used by the TLS1.3 only listener.
+        let tls_policy = TlsPolicy {
+            min_version: Some(TlsVersion::Tls13),
+            cipher_suites: vec![String::from("TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256")],
+            ..Default::default()
+        };
+        assert_eq!(tls_policy.validate().is_err(), true);
+        let tls_policy = TlsPolicy {
+            kx_groups: vec![String::from("ffdhe2048")],
+            ..Default::default()
+        };
+        assert_eq!(tls_policy.validate().is_err(), true);
+        let tls_policy = TlsPolicy {
+            alpn_protocols: vec![String::new()],
+            ..Default::default()
+        };
+        assert_eq!(tls_policy.validate().is_err(), true);
+    }
+}
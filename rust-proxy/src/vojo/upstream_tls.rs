@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+// The tls options used to connect the https endpoints of the route,each distinct option owns a
+// pooled client.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub struct UpstreamTls {
+    // The pem bundle of the CAs which sign the certificates of the upstream,the webpki roots are
+    // trusted when it is none.
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    // The client certificate and the key sent to the upstream which requires the mutual tls.
+    #[serde(default)]
+    pub cert_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+    #[serde(default)]
+    pub key_password: Option<String>,
+    // The name sent in the SNI,the host of the endpoint is sent when it is none.
+    #[serde(default)]
+    pub server_name: Option<String>,
+    // The name verified with the certificate of the upstream,the SNI name is verified when it is
+    // none.
+    #[serde(default)]
+    pub verify_hostname: Option<String>,
+    // Only for testing,the certificate of the upstream is not verified at all.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+impl UpstreamTls {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.cert_file.is_some() != self.key_file.is_some() {
+            return Err(anyhow!(
+                "The cert_file and the key_file of the upstream tls must be configured together!"
+            ));
+        }
+        if self.insecure_skip_verify && (self.ca_file.is_some() || self.verify_hostname.is_some()) {
+            return Err(anyhow!(
+                "The ca_file and the verify_hostname could not be used with the insecure_skip_verify!"
+            ));
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_tls() {
+        let upstream_tls: UpstreamTls = serde_yaml::from_str(
+            r#"
+ca_file: /etc/silverwind/internal-ca.pem
+cert_file: /etc/silverwind/client.crt
+key_file: /etc/silverwind/client.key
+server_name: api.internal
+"#,
+        )
+        .unwrap();
+        assert_eq!(upstream_tls.insecure_skip_verify, false);
+        assert_eq!(upstream_tls.verify_hostname, None);
+        assert_eq!(upstream_tls.validate().is_ok(), true);
+
+        let mut upstream_tls = upstream_tls;
+        upstream_tls.key_file = None;
+        assert_eq!(upstream_tls.validate().is_err(), true);
+        let upstream_tls = UpstreamTls {
+            ca_file: Some(String::from("/etc/silverwind/internal-ca.pem")),
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        assert_eq!(upstream_tls.validate().is_err(), true);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::vojo::request_context::RequestContext;
+use futures::stream;
+use futures::StreamExt;
+use hyper::body::{Bytes, HttpBody};
+use hyper::Body;
+use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+lazy_static! {
+    static ref CORE_RULES: Vec<WafRule> = vec![
+        new_core_rule(
+            "core-sqli",
+            r"(?i)(\bunion\b\s+(all\s+)?select\b|'\s*(or|and)\s+['\d]|\b(or|and)\s+\d+\s*=\s*\d+|;\s*(drop|delete|insert|update|alter)\s+|\b(sleep|benchmark|pg_sleep)\s*\()",
+        ),
+        new_core_rule(
+            "core-xss",
+            r"(?i)(<\s*script\b|javascript\s*:|\bon(error|load|click|mouseover|focus)\s*=|<\s*iframe\b|document\.cookie)",
+        ),
+        new_core_rule(
+            "core-path-traversal",
+            r"(?i)(\.\./|\.\.\\|/etc/passwd|\\windows\\win\.ini)",
+        ),
+    ];
+}
+fn new_core_rule(id: &str, pattern: &str) -> WafRule {
+    WafRule {
+        id: String::from(id),
+        targets: vec![WafTarget::Path, WafTarget::Query, WafTarget::Body],
+        condition: WafCondition::Regex {
+            value: WafPattern(Regex::new(pattern).unwrap()),
+        },
+    }
+}
+fn default_true() -> bool {
+    true
+}
+fn default_max_body_size() -> usize {
+    64 * 1024
+}
+// The regex is compiled once when the config is loaded and the invalid regex is rejected.
+#[derive(Clone)]
+pub struct WafPattern(pub Regex);
+impl fmt::Debug for WafPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0.as_str())
+    }
+}
+impl PartialEq for WafPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+impl Serialize for WafPattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+impl<'de> Deserialize<'de> for WafPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(pattern.as_str())
+            .map(WafPattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(tag = "type")]
+pub enum WafMode {
+    #[default]
+    Block,
+    DetectOnly,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WafTarget {
+    Path,
+    Query,
+    Header { name: String },
+    Body,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WafCondition {
+    Regex { value: WafPattern },
+    Contains { value: String },
+    MaxSize { value: usize },
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WafRule {
+    pub id: String,
+    pub targets: Vec<WafTarget>,
+    pub condition: WafCondition,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct WafViolation {
+    pub rule_id: String,
+    pub target: String,
+}
+pub struct WafRequest<'a> {
+    pub request_context: &'a RequestContext,
+    pub body: &'a [u8],
+}
+impl<'a> WafRequest<'a> {
+    // Return the raw size and the decoded value of the target.
+    fn get_values(&self, target: &WafTarget) -> Vec<(usize, String)> {
+        match target {
+            WafTarget::Path => {
+                let path = self.request_context.path.as_str();
+                vec![(path.len(), decode(path))]
+            }
+            WafTarget::Query => self.request_context.query.as_ref().map_or(vec![], |query| {
+                vec![(query.len(), decode(query.replace('+', " ").as_str()))]
+            }),
+            WafTarget::Header { name } => self
+                .request_context
+                .headers
+                .get_all(name.as_str())
+                .iter()
+                .map(|value| {
+                    (
+                        value.len(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect(),
+            WafTarget::Body => {
+                let content_length = self
+                    .request_context
+                    .get_header("Content-Length")
+                    .ok()
+                    .flatten()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let body = String::from_utf8_lossy(self.body);
+                // The form body is encoded the same way as the query.
+                let value = if self.is_form_body() {
+                    decode(body.replace('+', " ").as_str())
+                } else {
+                    body.into_owned()
+                };
+                vec![(content_length.max(self.body.len()), value)]
+            }
+        }
+    }
+    fn is_form_body(&self) -> bool {
+        self.request_context
+            .get_header("Content-Type")
+            .ok()
+            .flatten()
+            .and_then(|value| {
+                value.split(';').next().map(|mime| {
+                    mime.trim()
+                        .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+                })
+            })
+            .unwrap_or(false)
+    }
+}
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+impl WafRule {
+    fn is_matched(&self, size: usize, value: &str) -> bool {
+        match &self.condition {
+            WafCondition::Regex { value: pattern } => pattern.0.is_match(value),
+            WafCondition::Contains { value: keyword } => value.contains(keyword.as_str()),
+            WafCondition::MaxSize { value: max_size } => size > *max_size,
+        }
+    }
+    fn inspect(&self, waf_request: &WafRequest) -> Option<WafViolation> {
+        for target in self.targets.iter() {
+            for (size, value) in waf_request.get_values(target) {
+                if self.is_matched(size, value.as_str()) {
+                    return Some(WafViolation {
+                        rule_id: self.id.clone(),
+                        target: format!("{:?}", target),
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WafPolicy {
+    #[serde(default)]
+    pub mode: WafMode,
+    #[serde(default = "default_true")]
+    pub core_ruleset: bool,
+    // Only the first max_body_size bytes of the body are inspected.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    #[serde(default)]
+    pub rules: Vec<WafRule>,
+}
+impl WafPolicy {
+    fn get_rules(&self) -> impl Iterator<Item = &WafRule> {
+        let core_rules: &[WafRule] = if self.core_ruleset { &CORE_RULES } else { &[] };
+        self.rules.iter().chain(core_rules.iter())
+    }
+    pub fn is_body_inspected(&self) -> bool {
+        self.get_rules()
+            .any(|rule| rule.targets.contains(&WafTarget::Body))
+    }
+    pub fn inspect(&self, waf_request: &WafRequest) -> Option<WafViolation> {
+        self.get_rules().find_map(|rule| rule.inspect(waf_request))
+    }
+}
+// Read at most max_body_size bytes from the body for the inspection and return a body which
+// still yields all the bytes to the upstream.
+pub async fn read_body_prefix(
+    mut body: Body,
+    max_body_size: usize,
+) -> Result<(Vec<u8>, Body), anyhow::Error> {
+    let mut chunks: Vec<Bytes> = vec![];
+    let mut inspected = vec![];
+    while inspected.len() < max_body_size {
+        match body.data().await {
+            Some(chunk) => {
+                let chunk = chunk.map_err(|err| anyhow!(err.to_string()))?;
+                let remaining = max_body_size - inspected.len();
+                inspected.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+                chunks.push(chunk);
+            }
+            None => return Ok((inspected, Body::from(chunks.concat()))),
+        }
+    }
+    let prefix = stream::iter(chunks.into_iter().map(Ok::<Bytes, hyper::Error>));
+    Ok((inspected, Body::wrap_stream(prefix.chain(body))))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{HeaderMap, Uri};
+
+    fn new_request_context(uri: &str) -> RequestContext {
+        let uri: Uri = uri.parse().unwrap();
+        RequestContext::new(String::from("127.0.0.1"), &uri, HeaderMap::new())
+    }
+    #[test]
+    fn test_core_ruleset() {
+        let waf_policy: WafPolicy = serde_yaml::from_str("mode:\n  type: Block\n").unwrap();
+        let check = |uri: &str, body: &str| {
+            let request_context = new_request_context(uri);
+            let waf_request = WafRequest {
+                request_context: &request_context,
+                body: body.as_bytes(),
+            };
+            waf_policy
+                .inspect(&waf_request)
+                .map(|violation| violation.rule_id)
+        };
+        assert_eq!(check("http://localhost/get?id=1", ""), None);
+        assert_eq!(
+            check("http://localhost/get?id=1%27%20or%20%271%27=%271", ""),
+            Some(String::from("core-sqli"))
+        );
+        assert_eq!(
+            check(
+                "http://localhost/get?q=%3Cscript%3Ealert(1)%3C/script%3E",
+                ""
+            ),
+            Some(String::from("core-xss"))
+        );
+        assert_eq!(
+            check("http://localhost/static/..%2F..%2Fetc/passwd", ""),
+            Some(String::from("core-path-traversal"))
+        );
+        assert_eq!(
+            check("http://localhost/post", "name=1 UNION SELECT password"),
+            Some(String::from("core-sqli"))
+        );
+        assert_eq!(waf_policy.is_body_inspected(), true);
+    }
+    #[test]
+    fn test_core_ruleset_form_body() {
+        let waf_policy: WafPolicy = serde_yaml::from_str("mode:\n  type: Block\n").unwrap();
+        let check = |content_type: &str, body: &str| {
+            let mut request_context = new_request_context("http://localhost/post");
+            request_context
+                .headers
+                .insert("Content-Type", content_type.parse().unwrap());
+            let waf_request = WafRequest {
+                request_context: &request_context,
+                body: body.as_bytes(),
+            };
+            waf_policy
+                .inspect(&waf_request)
+                .map(|violation| violation.rule_id)
+        };
+        let form = "application/x-www-form-urlencoded; charset=UTF-8";
+        assert_eq!(
+            check(form, "q=1%27+or+%271%27%3D%271"),
+            Some(String::from("core-sqli"))
+        );
+        assert_eq!(
+            check(form, "q=%3Cscript%3Ealert(1)%3C%2Fscript%3E"),
+            Some(String::from("core-xss"))
+        );
+        assert_eq!(check(form, "q=hello+world&page=1"), None);
+        // Only the form body is decoded.
+        assert_eq!(check("application/json", "q=1%27+or+%271%27%3D%271"), None);
+    }
+    #[test]
+    fn test_custom_rules() {
+        let waf_policy: WafPolicy = serde_yaml::from_str(
+            r#"
+mode:
+  type: DetectOnly
+core_ruleset: false
+rules:
+- id: bad-agent
+  targets:
+  - type: Header
+    name: User-Agent
+  condition:
+    type: Regex
+    value: (?i)sqlmap
+- id: large-body
+  targets:
+  - type: Body
+  condition:
+    type: MaxSize
+    value: 4
+"#,
+        )
+        .unwrap();
+        assert_eq!(waf_policy.mode, WafMode::DetectOnly);
+        let mut request_context = new_request_context("http://localhost/get");
+        request_context
+            .headers
+            .insert("User-Agent", "sqlmap/1.7".parse().unwrap());
+        let waf_request = WafRequest {
+            request_context: &request_context,
+            body: b"",
+        };
+        let violation = waf_policy.inspect(&waf_request).unwrap();
+        assert_eq!(violation.rule_id, "bad-agent");
+        let request_context = new_request_context("http://localhost/get");
+        let waf_request = WafRequest {
+            request_context: &request_context,
+            body: b"12345",
+        };
+        let violation = waf_policy.inspect(&waf_request).unwrap();
+        assert_eq!(violation.rule_id, "large-body");
+        let res: Result<WafPolicy, _> = serde_yaml::from_str(
+            "rules:\n- id: bad\n  targets:\n  - type: Path\n  condition:\n    type: Regex\n    value: (abc\n",
+        );
+        assert_eq!(res.is_err(), true);
+    }
+    #[tokio::test]
+    async fn test_read_body_prefix() {
+        let (inspected, body) = read_body_prefix(Body::from("0123456789"), 4).await.unwrap();
+        assert_eq!(inspected, b"0123".to_vec());
+        let body_bytes = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(body_bytes, "0123456789");
+        let (inspected, body) = read_body_prefix(Body::from("01"), 4).await.unwrap();
+        assert_eq!(inspected, b"01".to_vec());
+        let body_bytes = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(body_bytes, "01");
+    }
+}